@@ -0,0 +1,243 @@
+//! Derive macros for [`rinq-rdbc`](https://docs.rs/rinq-rdbc), enabled by its `with-derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Data, DeriveInput, Expr, Fields, LitStr, Token,
+};
+
+/// Derives `rinq_rdbc::FromRow` for a struct with named fields.
+///
+/// Each field is read from the column of the same name with `Row::get_by_name`,
+/// which converts the value with `TryFrom<SqlValue>`. Use an `Option<T>` field for a nullable column,
+/// and `#[rdbc(rename = "...")]` to read a field from a column with another name.
+///
+/// A missing column or a failed conversion returns an error naming the struct and the field.
+#[proc_macro_derive(FromRow, attributes(rdbc))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_from_row(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_from_row(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "FromRow can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "FromRow can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let ident = &input.ident;
+    let struct_name = ident.to_string();
+
+    let mut field_values = vec![];
+
+    for field in fields {
+        let field_ident = field.ident.as_ref().expect("named field");
+        let field_type = &field.ty;
+
+        let field_name = field_ident.to_string();
+        let field_name = field_name.trim_start_matches("r#");
+
+        let column = column_name(field)?.unwrap_or_else(|| field_name.to_owned());
+
+        field_values.push(quote! {
+            #field_ident: row.get_by_name::<#field_type>(#column).map_err(|err| {
+                ::std::io::Error::new(
+                    err.kind(),
+                    ::std::format!("{}.{}: {}", #struct_name, #field_name, err),
+                )
+            })?
+        });
+    }
+
+    let mut generics = input.generics.clone();
+
+    // a field of a generic type must be convertible from a value.
+    if !generics.params.is_empty() {
+        let where_clause = generics.make_where_clause();
+
+        for field in fields {
+            let field_type = &field.ty;
+
+            where_clause.predicates.push(syn::parse_quote! {
+                #field_type: ::std::convert::TryFrom<::rinq_rdbc::SqlValue<'static>, Error = ::std::io::Error>
+            });
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::rinq_rdbc::FromRow for #ident #ty_generics #where_clause {
+            fn from_row(row: &::rinq_rdbc::Row) -> ::std::io::Result<Self> {
+                ::std::result::Result::Ok(Self {
+                    #(#field_values,)*
+                })
+            }
+        }
+    })
+}
+
+/// Returns the column name of `#[rdbc(rename = "...")]` on `field`, if any.
+fn column_name(field: &syn::Field) -> syn::Result<Option<String>> {
+    let mut rename = None;
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("rdbc"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unknown rdbc attribute, expect `rename = \"...\"`"))
+            }
+        })?;
+    }
+
+    Ok(rename)
+}
+
+/// Builds a `rinq_rdbc::SqlQuery` from a sql literal and its arguments, e.g. `sql!("SELECT * FROM user WHERE id = ?", id)`.
+///
+/// The number of parameters is checked against the number of arguments when the macro is expanded,
+/// each `?` is a parameter and `?NNN` is parameter `NNN` as in sqlite. A `?` in a string literal,
+/// a quoted identifier or a comment is not a parameter. Named parameters are not supported.
+/// Each argument is converted with `Into<SqlValue>`.
+#[proc_macro]
+pub fn sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as SqlInput);
+
+    expand_sql(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct SqlInput {
+    sql: LitStr,
+    args: Punctuated<Expr, Token![,]>,
+}
+
+impl Parse for SqlInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let sql = input.parse()?;
+
+        let args = if input.is_empty() {
+            Punctuated::new()
+        } else {
+            input.parse::<Token![,]>()?;
+            Punctuated::parse_terminated(input)?
+        };
+
+        Ok(Self { sql, args })
+    }
+}
+
+fn expand_sql(input: SqlInput) -> syn::Result<TokenStream2> {
+    let parameters = count_parameters(&input.sql.value())
+        .map_err(|message| syn::Error::new_spanned(&input.sql, message))?;
+
+    if parameters != input.args.len() {
+        return Err(syn::Error::new_spanned(
+            &input.sql,
+            format!(
+                "sql! query has {} parameter(s) but {} argument(s) are given",
+                parameters,
+                input.args.len()
+            ),
+        ));
+    }
+
+    let sql = &input.sql;
+    let args = input.args.iter();
+
+    Ok(quote! {
+        ::rinq_rdbc::SqlQuery::new(
+            #sql,
+            ::std::vec![#(::rinq_rdbc::SqlValue::from(#args)),*],
+        )
+    })
+}
+
+/// Returns the number of parameters of `sql`, which is the largest parameter index as in sqlite.
+fn count_parameters(sql: &str) -> Result<usize, String> {
+    let mut chars = sql.chars().peekable();
+    let mut count = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                // a doubled quote is an escaped quote, which continues the literal.
+                for next in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '[' => for _ in chars.by_ref().take_while(|next| *next != ']') {},
+            '-' if chars.peek() == Some(&'-') => {
+                for _ in chars.by_ref().take_while(|next| *next != '\n') {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                let mut star = false;
+
+                for next in chars.by_ref() {
+                    if star && next == '/' {
+                        break;
+                    }
+
+                    star = next == '*';
+                }
+            }
+            '?' => {
+                let mut digits = String::new();
+
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+
+                count = if digits.is_empty() {
+                    count + 1
+                } else {
+                    match digits.parse::<usize>() {
+                        Ok(index) if index > 0 => count.max(index),
+                        _ => return Err(format!("invalid parameter ?{}", digits)),
+                    }
+                };
+            }
+            ':' | '@' | '$'
+                if chars
+                    .peek()
+                    .is_some_and(|next| next.is_alphabetic() || *next == '_') =>
+            {
+                return Err("named parameters are not supported by sql!".to_owned());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(count)
+}
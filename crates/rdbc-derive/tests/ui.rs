@@ -0,0 +1,7 @@
+#[test]
+fn test_ui() {
+    let t = trybuild::TestCases::new();
+
+    t.pass("tests/ui/pass_*.rs");
+    t.compile_fail("tests/ui/fail_*.rs");
+}
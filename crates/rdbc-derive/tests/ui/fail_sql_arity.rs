@@ -0,0 +1,9 @@
+use rinq_rdbc::sql;
+
+fn main() {
+    let id = 1;
+
+    let _ = sql!("SELECT * FROM user WHERE id = ? AND name = ?", id);
+    let _ = sql!("SELECT * FROM user WHERE id = ?", id, "alice");
+    let _ = sql!("SELECT * FROM user WHERE name = :name", "alice");
+}
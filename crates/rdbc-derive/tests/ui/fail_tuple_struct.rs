@@ -0,0 +1,6 @@
+use rinq_rdbc::FromRow;
+
+#[derive(FromRow)]
+struct Id(i64);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rinq_rdbc::FromRow;
+
+#[derive(FromRow)]
+struct User {
+    #[rdbc(column = "user_id")]
+    id: i64,
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+use rinq_rdbc::FromRow;
+
+#[derive(FromRow)]
+struct User {
+    id: i64,
+    #[rdbc(rename = "user_name")]
+    name: String,
+    email: Option<String>,
+    r#type: String,
+}
+
+#[derive(FromRow)]
+struct Wrapper<T> {
+    value: T,
+}
+
+fn main() {
+    fn assert_from_row<T: rinq_rdbc::FromRow>() {}
+
+    assert_from_row::<User>();
+    assert_from_row::<Wrapper<i64>>();
+}
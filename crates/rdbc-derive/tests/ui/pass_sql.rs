@@ -0,0 +1,25 @@
+use rinq_rdbc::{sql, SqlQuery, SqlValue};
+
+fn main() {
+    let id = 1;
+
+    let query: SqlQuery = sql!("SELECT * FROM user WHERE id = ? AND name = ?", id, "alice");
+
+    assert_eq!(query.values(), [SqlValue::Int(1), "alice".into()]);
+
+    // a `?` in a literal, a quoted identifier or a comment is not a parameter.
+    let query = sql!(
+        "SELECT '?', \"?\" FROM user -- why?
+         WHERE /* ? */ id = ?",
+        id,
+    );
+
+    assert_eq!(query.values().len(), 1);
+
+    // a numbered parameter counts up to its index.
+    let query = sql!("SELECT ?2, ?1, ?", 1, 2, 3);
+
+    assert_eq!(query.values().len(), 3);
+
+    assert!(sql!("SELECT 1").values().is_empty());
+}
@@ -0,0 +1,495 @@
+use std::{
+    io::{self, Result},
+    sync::Arc,
+};
+
+use arrow_array::{
+    builder::{BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
+    ArrayRef, NullArray, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use crate::{ColumnType, ResultSet, SqlValue};
+
+/// Returns the arrow type of a column declared as `col_type`, or `None` if the value types can not be told from it.
+///
+/// The declared type name is matched like sqlite assigns a column affinity, e.g. `BIGINT` maps to `Int64`
+/// and `VARCHAR(20)` to `Utf8`. `NUMERIC`/`DECIMAL` and names without a rule are left to the values.
+fn declared_type(col_type: &ColumnType<'_>) -> Option<DataType> {
+    let name = col_type.database_type_name.to_ascii_uppercase();
+
+    if name.contains("BOOL") {
+        Some(DataType::Boolean)
+    } else if name.contains("INT") {
+        Some(DataType::Int64)
+    } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| name.contains(t)) {
+        Some(DataType::Utf8)
+    } else if ["BLOB", "BINARY", "BYTEA"].iter().any(|t| name.contains(t)) {
+        Some(DataType::Binary)
+    } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| name.contains(t)) {
+        Some(DataType::Float64)
+    } else {
+        None
+    }
+}
+
+/// Returns the arrow type of a column that contains both `current` and `value`.
+///
+/// An `Int64` column is widened to `Float64` by a `Float`, other types are kept and any value
+/// that does not fit is reported when the batch is built.
+fn merge_type(current: Option<DataType>, value: &SqlValue<'_>) -> Option<DataType> {
+    match (current, arrow_type(value)) {
+        (None, data_type) => data_type,
+        (Some(DataType::Int64), Some(DataType::Float64)) => Some(DataType::Float64),
+        (current, _) => current,
+    }
+}
+
+/// Returns the arrow type of the column that `value` was read from, or `None` for `Null`.
+fn arrow_type(value: &SqlValue<'_>) -> Option<DataType> {
+    match value {
+        SqlValue::Bool(_) => Some(DataType::Boolean),
+        SqlValue::Int(_) | SqlValue::BigInt(_) => Some(DataType::Int64),
+        SqlValue::Float(_) => Some(DataType::Float64),
+        SqlValue::Binary(_) => Some(DataType::Binary),
+        SqlValue::Null => None,
+        #[cfg(feature = "with-uuid")]
+        SqlValue::Uuid(_) => Some(DataType::Binary),
+        _ => Some(DataType::Utf8),
+    }
+}
+
+fn mismatch(column: &str, data_type: &DataType, value: &SqlValue<'_>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Column {}: expect {} value, got {}",
+            column,
+            data_type,
+            value.redacted()
+        ),
+    )
+}
+
+/// Build the array of column `col` of `rows`.
+fn build_array(field: &Field, rows: &[Vec<SqlValue<'static>>], col: usize) -> Result<ArrayRef> {
+    let values = rows.iter().map(|row| &row[col]);
+
+    let name = field.name();
+
+    let array: ArrayRef = match field.data_type() {
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(rows.len());
+
+            for value in values {
+                match value {
+                    SqlValue::Int(v) => builder.append_value(*v),
+                    SqlValue::BigInt(v) => builder.append_value(
+                        i64::try_from(*v).map_err(|_| mismatch(name, &DataType::Int64, value))?,
+                    ),
+                    SqlValue::Null => builder.append_null(),
+                    _ => return Err(mismatch(name, &DataType::Int64, value)),
+                }
+            }
+
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(rows.len());
+
+            for value in values {
+                match value {
+                    SqlValue::Float(v) => builder.append_value(*v),
+                    // sqlite may return a whole number stored in a `REAL` column as an integer.
+                    SqlValue::Int(v) => builder.append_value(*v as f64),
+                    SqlValue::Null => builder.append_null(),
+                    _ => return Err(mismatch(name, &DataType::Float64, value)),
+                }
+            }
+
+            Arc::new(builder.finish())
+        }
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(rows.len());
+
+            for value in values {
+                match value {
+                    SqlValue::Bool(v) => builder.append_value(*v),
+                    // sqlite stores booleans as integers.
+                    SqlValue::Int(v) => builder.append_value(*v != 0),
+                    SqlValue::Null => builder.append_null(),
+                    _ => return Err(mismatch(name, &DataType::Boolean, value)),
+                }
+            }
+
+            Arc::new(builder.finish())
+        }
+        DataType::Binary => {
+            let mut builder = BinaryBuilder::new();
+
+            for value in values {
+                match value {
+                    SqlValue::Binary(v) => builder.append_value(v),
+                    #[cfg(feature = "with-uuid")]
+                    SqlValue::Uuid(v) => builder.append_value(v.as_bytes()),
+                    SqlValue::Null => builder.append_null(),
+                    _ => return Err(mismatch(name, &DataType::Binary, value)),
+                }
+            }
+
+            Arc::new(builder.finish())
+        }
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::new();
+
+            for value in values {
+                match value {
+                    SqlValue::String(v) => builder.append_value(v),
+                    #[cfg(feature = "with-decimal")]
+                    SqlValue::Decimal(v) => builder.append_value(v.to_string()),
+                    #[cfg(feature = "with-chrono")]
+                    SqlValue::Date(_) | SqlValue::Time(_) | SqlValue::Timestamp(_) => {
+                        builder.append_value(crate::format_temporal(value).unwrap_or_default())
+                    }
+                    SqlValue::Null => builder.append_null(),
+                    _ => return Err(mismatch(name, &DataType::Utf8, value)),
+                }
+            }
+
+            Arc::new(builder.finish())
+        }
+        _ => {
+            if let Some(value) = values
+                .into_iter()
+                .find(|value| !matches!(value, SqlValue::Null))
+            {
+                return Err(mismatch(name, field.data_type(), value));
+            }
+
+            Arc::new(NullArray::new(rows.len()))
+        }
+    };
+
+    Ok(array)
+}
+
+fn build_batch(schema: &SchemaRef, rows: &[Vec<SqlValue<'static>>]) -> Result<RecordBatch> {
+    let arrays = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(col, field)| build_array(field, rows, col))
+        .collect::<Result<Vec<_>>>()?;
+
+    RecordBatch::try_new(schema.clone(), arrays).map_err(io::Error::other)
+}
+
+impl ResultSet {
+    /// Drains this result set into arrow record batches of up to `batch_size` rows.
+    ///
+    /// The schema is taken from the declared [`column_types`](ResultSet::column_types), every field is nullable
+    /// and `Null` values are set in the null bitmap. Columns whose declared type has no arrow type, e.g. an expression
+    /// or a `NUMERIC` column, are typed by the values of the first batch:
+    /// * `Bool` maps to `Boolean`, `Int` and `BigInt` to `Int64` and `Float` to `Float64`,
+    ///   a column that mixes `Int` and `Float` maps to `Float64`.
+    /// * `Binary` and `Uuid` map to `Binary`.
+    /// * `String`, `Decimal` and the temporal values map to `Utf8` text, as in [`write_csv`](ResultSet::write_csv).
+    /// * A column that only contains `Null` maps to `Null`.
+    ///
+    /// Returns [`InvalidData`](io::ErrorKind::InvalidData) error if a value does not fit the type of its column,
+    /// except an `Int` in a `Float64` column, which is converted.
+    /// An empty result set returns no batches.
+    pub async fn into_arrow(self, batch_size: usize) -> Result<Vec<RecordBatch>> {
+        if batch_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Arrow batch size must not be zero",
+            ));
+        }
+
+        let columns = self.columns().await?;
+
+        let declared = self
+            .column_types()
+            .await?
+            .iter()
+            .map(declared_type)
+            .collect::<Vec<_>>();
+
+        let mut data_types = declared.clone();
+
+        let mut schema = None;
+
+        let mut rows = vec![];
+
+        let mut batches = vec![];
+
+        while self.next().await? {
+            let mut row = Vec::with_capacity(columns.len());
+
+            for col in 0..columns.len() {
+                let value = self.get(col).await?;
+
+                if schema.is_none() && declared[col].is_none() {
+                    data_types[col] = merge_type(data_types[col].take(), &value);
+                }
+
+                row.push(value);
+            }
+
+            rows.push(row);
+
+            if rows.len() == batch_size {
+                let schema = schema.get_or_insert_with(|| to_schema(&columns, &data_types));
+
+                batches.push(build_batch(schema, &rows)?);
+                rows.clear();
+            }
+        }
+
+        if !rows.is_empty() {
+            let schema = schema.get_or_insert_with(|| to_schema(&columns, &data_types));
+
+            batches.push(build_batch(schema, &rows)?);
+        }
+
+        Ok(batches)
+    }
+}
+
+fn to_schema(columns: &[String], data_types: &[Option<DataType>]) -> SchemaRef {
+    let fields = columns
+        .iter()
+        .zip(data_types)
+        .map(|(name, data_type)| {
+            Field::new(name, data_type.clone().unwrap_or(DataType::Null), true)
+        })
+        .collect::<Vec<_>>();
+
+    Arc::new(Schema::new(fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Array, BinaryArray, BooleanArray, Float64Array, Int64Array, StringArray};
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mock::MockDatabase;
+
+    fn column<T: 'static>(batch: &RecordBatch, col: usize) -> &T {
+        batch.column(col).as_any().downcast_ref::<T>().unwrap()
+    }
+
+    #[test]
+    fn test_into_arrow() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT * FROM t",
+                    &["id", "name", "score", "active", "data", "note"],
+                    vec![
+                        vec![
+                            SqlValue::Int(1),
+                            SqlValue::Null,
+                            SqlValue::Float(0.5),
+                            SqlValue::Bool(true),
+                            SqlValue::from(vec![1u8]),
+                            SqlValue::Null,
+                        ],
+                        vec![
+                            SqlValue::Int(2),
+                            "bob".into(),
+                            SqlValue::Int(3),
+                            SqlValue::Null,
+                            SqlValue::Null,
+                            SqlValue::Null,
+                        ],
+                        vec![
+                            SqlValue::BigInt(3),
+                            "carol".into(),
+                            SqlValue::Null,
+                            SqlValue::Bool(false),
+                            SqlValue::from(vec![]),
+                            SqlValue::Null,
+                        ],
+                    ],
+                )
+                .fixture(
+                    "SELECT mixed FROM t",
+                    &["mixed"],
+                    vec![vec![SqlValue::Int(1)], vec!["one".into()]],
+                )
+                .connect();
+
+            let rs = conn
+                .prepare("SELECT * FROM t")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let batches = rs.into_arrow(2).await.unwrap();
+
+            assert_eq!(
+                batches
+                    .iter()
+                    .map(RecordBatch::num_rows)
+                    .collect::<Vec<_>>(),
+                [2, 1]
+            );
+
+            let schema = batches[0].schema();
+
+            assert_eq!(
+                schema
+                    .fields()
+                    .iter()
+                    .map(|field| field.data_type().clone())
+                    .collect::<Vec<_>>(),
+                [
+                    DataType::Int64,
+                    // the type is taken from the second row of the first batch.
+                    DataType::Utf8,
+                    DataType::Float64,
+                    DataType::Boolean,
+                    DataType::Binary,
+                    DataType::Null
+                ]
+            );
+
+            let first = &batches[0];
+
+            assert_eq!(column::<Int64Array>(first, 0).values(), &[1, 2]);
+            assert!(column::<StringArray>(first, 1).is_null(0));
+            assert_eq!(column::<StringArray>(first, 1).value(1), "bob");
+            assert_eq!(column::<Float64Array>(first, 2).values(), &[0.5, 3.0]);
+            assert!(column::<BooleanArray>(first, 3).value(0));
+            assert!(column::<BooleanArray>(first, 3).is_null(1));
+            assert_eq!(column::<BinaryArray>(first, 4).value(0), [1]);
+            assert_eq!(first.column(5).len(), 2);
+
+            let last = &batches[1];
+
+            assert_eq!(column::<Int64Array>(last, 0).value(0), 3);
+            assert_eq!(column::<StringArray>(last, 1).value(0), "carol");
+            assert!(column::<Float64Array>(last, 2).is_null(0));
+
+            let rs = conn
+                .prepare("SELECT mixed FROM t")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let err = rs.into_arrow(10).await.err().unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.to_string(),
+                "Column mixed: expect Int64 value, got String(len=3)"
+            );
+        });
+    }
+
+    #[test]
+    fn test_into_arrow_types() {
+        block_on(async {
+            let query = "SELECT price, qty, total, note, flag FROM t";
+
+            let conn = MockDatabase::default()
+                .fixture(
+                    query,
+                    &["price", "qty", "total", "note", "flag"],
+                    vec![
+                        vec![
+                            SqlValue::Int(1),
+                            SqlValue::Null,
+                            SqlValue::Int(2),
+                            SqlValue::Null,
+                            SqlValue::Int(1),
+                        ],
+                        vec![
+                            SqlValue::Float(2.5),
+                            SqlValue::Null,
+                            SqlValue::Float(0.5),
+                            SqlValue::Null,
+                            SqlValue::Int(0),
+                        ],
+                        vec![
+                            SqlValue::Int(3),
+                            SqlValue::Null,
+                            SqlValue::Int(4),
+                            SqlValue::Null,
+                            SqlValue::Null,
+                        ],
+                    ],
+                )
+                .declared_types(query, &["REAL", "INTEGER", "NUMERIC", "", "BOOLEAN"])
+                .connect();
+
+            let batches = conn
+                .query_one_shot(query, &[])
+                .await
+                .unwrap()
+                .into_arrow(2)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                batches[0]
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|field| field.data_type().clone())
+                    .collect::<Vec<_>>(),
+                [
+                    // declared `REAL`, the leading `Int` does not decide the type.
+                    DataType::Float64,
+                    // declared `INTEGER`, only contains `Null`.
+                    DataType::Int64,
+                    // `NUMERIC` mixes `Int` and `Float` in the first batch.
+                    DataType::Float64,
+                    // untyped and only `Null`, the type is known after the first batch.
+                    DataType::Null,
+                    DataType::Boolean,
+                ]
+            );
+
+            assert_eq!(column::<Float64Array>(&batches[0], 0).values(), &[1.0, 2.5]);
+            assert_eq!(column::<Int64Array>(&batches[0], 1).null_count(), 2);
+            assert_eq!(column::<Float64Array>(&batches[1], 2).value(0), 4.0);
+            assert_eq!(batches[1].column(3).len(), 1);
+            assert!(!column::<BooleanArray>(&batches[0], 4).value(1));
+
+            // a value in a column that was typed `Null` by the first batch.
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT note FROM t",
+                    &["note"],
+                    vec![
+                        vec![SqlValue::Null],
+                        vec![SqlValue::Null],
+                        vec!["late".into()],
+                    ],
+                )
+                .connect();
+
+            let err = conn
+                .query_one_shot("SELECT note FROM t", &[])
+                .await
+                .unwrap()
+                .into_arrow(2)
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(
+                err.to_string(),
+                "Column note: expect Null value, got String(len=4)"
+            );
+        });
+    }
+}
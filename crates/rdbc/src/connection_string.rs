@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    io::{self, Result},
+};
+
+use crate::{open, DbConn};
+
+/// A parsed url-like data source name, e.g. `sqlite://./data.db?mode=ro`.
+///
+/// The form is `<driver>://<path_or_host>[?<key>=<value>[&...]]`,
+/// keys and values are taken verbatim without percent-decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionString {
+    /// The registered driver name.
+    pub driver: String,
+    /// The part between `://` and `?`, a file path or host for the driver.
+    pub path_or_host: String,
+    /// The query string parameters, a key without `=` maps to an empty value.
+    pub params: HashMap<String, String>,
+    source: String,
+}
+
+impl ConnectionString {
+    /// Parse `dsn`, returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if there is no `<driver>://` prefix.
+    pub fn parse<S: AsRef<str>>(dsn: S) -> Result<Self> {
+        let dsn = dsn.as_ref();
+
+        let (driver, source) = dsn
+            .split_once("://")
+            .filter(|(driver, _)| !driver.is_empty())
+            .ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Expect `<driver>://` prefix in connection string: {}", dsn),
+            ))?;
+
+        let (path_or_host, query) = source.split_once('?').unwrap_or((source, ""));
+
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (key, value) = param.split_once('=').unwrap_or((param, ""));
+
+                (key.to_owned(), value.to_owned())
+            })
+            .collect();
+
+        Ok(Self {
+            driver: driver.to_owned(),
+            path_or_host: path_or_host.to_owned(),
+            params,
+            source: source.to_owned(),
+        })
+    }
+
+    /// Returns everything after `<driver>://` verbatim, this is the source name passed to the driver.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Open a database with a url-like data source name, e.g. `sqlite://./data.db`.
+///
+/// The driver is selected by the scheme of `dsn`,
+/// and the rest is passed to the driver as the `source_name`, see [`ConnectionString`] and [`open`].
+pub async fn open_url<S: AsRef<str>>(dsn: S) -> Result<DbConn> {
+    let connection_string = ConnectionString::parse(dsn)?;
+
+    open(&connection_string.driver, connection_string.source()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let connection_string = ConnectionString::parse("sqlite://./data.db?mode=ro").unwrap();
+
+        assert_eq!(connection_string.driver, "sqlite");
+        assert_eq!(connection_string.path_or_host, "./data.db");
+        assert_eq!(
+            connection_string.params,
+            HashMap::from([("mode".to_owned(), "ro".to_owned())])
+        );
+        assert_eq!(connection_string.source(), "./data.db?mode=ro");
+
+        let connection_string = ConnectionString::parse("sqlite://:memory:").unwrap();
+
+        assert_eq!(connection_string.driver, "sqlite");
+        assert_eq!(connection_string.path_or_host, ":memory:");
+        assert!(connection_string.params.is_empty());
+
+        let connection_string =
+            ConnectionString::parse("mock://localhost:5432/db?ssl&timeout=10").unwrap();
+
+        assert_eq!(connection_string.path_or_host, "localhost:5432/db");
+        assert_eq!(connection_string.params["ssl"], "");
+        assert_eq!(connection_string.params["timeout"], "10");
+
+        for dsn in ["./data.db", "://data.db"] {
+            assert_eq!(
+                ConnectionString::parse(dsn).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::{Result, Write};
+
+use crate::{ResultSet, SqlValue};
+
+/// Formatting options of [`ResultSet::write_csv`].
+pub struct CsvOptions {
+    /// The field delimiter, default is `,`.
+    pub delimiter: char,
+    /// The quote character, default is `"`.
+    pub quote: char,
+    /// The text written for [`SqlValue::Null`] fields, default is an empty string.
+    pub null: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            null: String::new(),
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Write `field` to `writer`, quoting it if it contains the delimiter, the quote character or a line break.
+    fn write_field<W: Write>(&self, writer: &mut W, field: &str) -> Result<()> {
+        let needs_quote = field
+            .chars()
+            .any(|c| c == self.delimiter || c == self.quote || c == '\r' || c == '\n');
+
+        if !needs_quote {
+            return writer.write_all(field.as_bytes());
+        }
+
+        let mut quote = [0; 4];
+        let quote = self.quote.encode_utf8(&mut quote);
+
+        let mut escaped = quote.to_owned();
+        escaped.push_str(quote);
+
+        write!(
+            writer,
+            "{}{}{}",
+            quote,
+            field.replace(&*quote, &escaped),
+            quote
+        )
+    }
+
+    fn write_record<W: Write, I: IntoIterator<Item = S>, S: AsRef<str>>(
+        &self,
+        writer: &mut W,
+        fields: I,
+    ) -> Result<()> {
+        for (index, field) in fields.into_iter().enumerate() {
+            if index > 0 {
+                write!(writer, "{}", self.delimiter)?;
+            }
+
+            self.write_field(writer, field.as_ref())?;
+        }
+
+        writer.write_all(b"\r\n")
+    }
+
+    /// Returns the csv text of `value`.
+    ///
+    /// Numbers are written in the same form regardless of the system locale, see [`format_float`].
+    /// `Bool` is written as `true`/`false`, `Binary` as lowercase hex, `Uuid` as its hyphenated form
+    /// and temporal values as ISO 8601 text, see `rinq_rdbc::DATE_FORMAT`.
+    fn format_value(&self, value: &SqlValue<'_>) -> String {
+        match value {
+            SqlValue::Bool(v) => v.to_string(),
+            SqlValue::Int(v) => v.to_string(),
+            SqlValue::BigInt(v) => v.to_string(),
+            SqlValue::Float(v) => format_float(*v),
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => v.to_string(),
+            SqlValue::Binary(v) => v.iter().map(|b| format!("{:02x}", b)).collect(),
+            SqlValue::String(v) => v.to_string(),
+            SqlValue::Null => self.null.clone(),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(_) | SqlValue::Time(_) | SqlValue::Timestamp(_) => {
+                crate::format_temporal(value).unwrap_or_default()
+            }
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => v.hyphenated().to_string(),
+        }
+    }
+}
+
+/// Returns the shortest text that parses back to exactly `v`, e.g. `0.1`, `1.0` or `1e-7`.
+///
+/// The decimal separator is always `.`, very large and very small values use an exponent,
+/// and the non-finite values are written as `NaN`, `inf` and `-inf`.
+fn format_float(v: f64) -> String {
+    // unlike `Display`, `Debug` switches to the exponent form instead of writing hundreds of digits.
+    format!("{:?}", v)
+}
+
+impl ResultSet {
+    /// Writes this result set to `writer` in csv format.
+    ///
+    /// The first record is the header of column names, followed by one record per row.
+    /// Records are terminated by `\r\n` as described in RFC 4180.
+    ///
+    /// On success, returns the number of rows written, not counting the header.
+    pub async fn write_csv<W: Write>(self, mut writer: W, opts: &CsvOptions) -> Result<u64> {
+        let columns = self.columns().await?;
+
+        opts.write_record(&mut writer, &columns)?;
+
+        let mut rows = 0;
+
+        while self.next().await? {
+            let mut fields = vec![];
+
+            for col in 0..columns.len() {
+                fields.push(opts.format_value(&self.get(col).await?));
+            }
+
+            opts.write_record(&mut writer, fields)?;
+
+            rows += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mock::MockDatabase;
+
+    #[test]
+    fn test_write_field() {
+        let opts = CsvOptions::default();
+
+        let mut buf = vec![];
+
+        opts.write_record(&mut buf, ["a", "b,c", "say \"hi\"", "x\ny"])
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "a,\"b,c\",\"say \"\"hi\"\"\",\"x\ny\"\r\n"
+        );
+
+        let opts = CsvOptions {
+            delimiter: ';',
+            quote: '\'',
+            null: "NULL".to_owned(),
+        };
+
+        let mut buf = vec![];
+
+        opts.write_record(&mut buf, ["a,b", "c;d", "it's"]).unwrap();
+
+        assert_eq!(String::from_utf8(buf).unwrap(), "a,b;'c;d';'it''s'\r\n");
+
+        assert_eq!(opts.format_value(&SqlValue::Null), "NULL");
+    }
+
+    #[test]
+    fn test_format_float() {
+        let opts = CsvOptions::default();
+
+        for (value, text) in [
+            (0.1, "0.1"),
+            (-2.5, "-2.5"),
+            (1.0, "1.0"),
+            (1.0 / 3.0, "0.3333333333333333"),
+            (1e300, "1e300"),
+            (1e-7, "1e-7"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+            (-0.0, "-0.0"),
+        ] {
+            let formatted = opts.format_value(&SqlValue::Float(value));
+
+            assert_eq!(formatted, text);
+            assert_eq!(formatted.parse::<f64>().unwrap().to_bits(), value.to_bits());
+        }
+
+        assert_eq!(format_float(f64::NAN), "NaN");
+        assert_eq!(format_float(f64::NEG_INFINITY), "-inf");
+        assert_eq!(
+            opts.format_value(&SqlValue::Int(i64::MIN)),
+            "-9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name FROM user",
+                    &["id", "name"],
+                    vec![
+                        vec![
+                            SqlValue::Int(1),
+                            SqlValue::String(Cow::Borrowed("Smith, John")),
+                        ],
+                        vec![SqlValue::Int(2), SqlValue::Null],
+                    ],
+                )
+                .connect();
+
+            let stmt = conn.prepare("SELECT id, name FROM user").await.unwrap();
+
+            let mut buf = vec![];
+
+            let rows = stmt
+                .query(&[])
+                .await
+                .unwrap()
+                .write_csv(&mut buf, &CsvOptions::default())
+                .await
+                .unwrap();
+
+            assert_eq!(rows, 2);
+
+            let csv = String::from_utf8(buf).unwrap();
+
+            let records = csv
+                .split_terminator("\r\n")
+                .map(parse_record)
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                records,
+                vec![vec!["id", "name"], vec!["1", "Smith, John"], vec!["2", ""]]
+            );
+        });
+    }
+
+    /// A minimal parser for records written with the default [`CsvOptions`].
+    fn parse_record(record: &str) -> Vec<String> {
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = record.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+
+        fields.push(field);
+
+        fields
+    }
+}
@@ -0,0 +1,93 @@
+//! Content digests of values, see [`SqlValue::content_digest`].
+
+use std::borrow::Cow;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::SqlValue;
+
+/// The hash algorithms of [`SqlValue::content_digest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Digest {
+    /// SHA-256, a 32-byte digest.
+    Sha256,
+}
+
+impl<'a> SqlValue<'a> {
+    /// Returns the digest of the content of this value computed with `algo`, or `None` for [`Null`](SqlValue::Null).
+    ///
+    /// The digest is computed over the raw bytes of `Binary` and the utf-8 bytes of `String`,
+    /// so a blob and a text with the same bytes have the same digest. Other variants are hashed in a canonical form:
+    /// * `Bool` as one byte `0` or `1`.
+    /// * `Int`, `BigInt` and `Float` as the big-endian bytes of `i64`, `i128` and the `f64` bits.
+    /// * `Decimal` as the text of its normalized value, so `1.50` and `1.5` have the same digest.
+    /// * `Date`, `Time` and `Timestamp` as their ISO 8601 text, see `DATE_FORMAT`.
+    /// * `Uuid` as its 16 bytes.
+    pub fn content_digest(&self, algo: Digest) -> Option<Vec<u8>> {
+        let bytes = self.canonical_bytes()?;
+
+        match algo {
+            Digest::Sha256 => Some(Sha256::digest(&bytes).to_vec()),
+        }
+    }
+
+    fn canonical_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        let bytes = match self {
+            SqlValue::Bool(v) => Cow::Owned(vec![*v as u8]),
+            SqlValue::Int(v) => Cow::Owned(v.to_be_bytes().to_vec()),
+            SqlValue::BigInt(v) => Cow::Owned(v.to_be_bytes().to_vec()),
+            SqlValue::Float(v) => Cow::Owned(v.to_bits().to_be_bytes().to_vec()),
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => Cow::Owned(v.normalized().to_string().into_bytes()),
+            SqlValue::Binary(v) => Cow::Borrowed(v.as_ref()),
+            SqlValue::String(v) => Cow::Borrowed(v.as_bytes()),
+            SqlValue::Null => return None,
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(_) | SqlValue::Time(_) | SqlValue::Timestamp(_) => {
+                Cow::Owned(crate::format_temporal(self)?.into_bytes())
+            }
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => Cow::Borrowed(v.as_bytes().as_slice()),
+        };
+
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: Vec<u8>) -> String {
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_content_digest() {
+        assert_eq!(
+            hex(SqlValue::from("abc")
+                .content_digest(Digest::Sha256)
+                .unwrap()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        assert_eq!(
+            SqlValue::from(&b"abc"[..]).content_digest(Digest::Sha256),
+            SqlValue::from("abc").content_digest(Digest::Sha256)
+        );
+
+        assert_ne!(
+            SqlValue::Int(1).content_digest(Digest::Sha256),
+            SqlValue::BigInt(1).content_digest(Digest::Sha256)
+        );
+
+        assert!(SqlValue::Null.content_digest(Digest::Sha256).is_none());
+
+        #[cfg(feature = "with-decimal")]
+        assert_eq!(
+            SqlValue::Decimal("1.50".parse().unwrap()).content_digest(Digest::Sha256),
+            SqlValue::Decimal("1.5".parse().unwrap()).content_digest(Digest::Sha256)
+        );
+    }
+}
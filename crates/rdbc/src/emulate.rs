@@ -0,0 +1,248 @@
+//! Client-side parameter substitution for drivers without server-side prepared statements,
+//! see [`Capabilities::server_side_prepare`](crate::Capabilities::server_side_prepare).
+
+use std::{
+    fmt::Write,
+    io::{self, Result},
+};
+
+use crate::{Database, SqlValue};
+
+/// The statement object of a [`Stmt`](crate::Stmt) whose query is substituted on each execution.
+pub(crate) struct EmulatedStmt;
+
+/// Returns `query` with each `?` placeholder replaced by the literal of the value at the same position.
+///
+/// Placeholders inside quotes, brackets and comments are left unchanged.
+/// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if the number of `values` does not match
+/// the placeholders, or for a numbered or named placeholder.
+pub(crate) fn emulate_prepared(
+    database: &dyn Database,
+    query: &str,
+    values: &[SqlValue<'_>],
+) -> Result<String> {
+    let mut chars = query.chars().peekable();
+    let mut output = String::with_capacity(query.len());
+    let given = values.len();
+    let mut values = values.iter();
+    let mut count = 0;
+
+    while let Some(c) = chars.next() {
+        output.push(c);
+
+        match c {
+            '\'' | '"' | '`' => {
+                // a doubled quote is an escaped quote, which continues the literal.
+                for next in chars.by_ref() {
+                    output.push(next);
+
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '[' => {
+                for next in chars.by_ref() {
+                    output.push(next);
+
+                    if next == ']' {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for next in chars.by_ref() {
+                    output.push(next);
+
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                output.extend(chars.next());
+
+                let mut star = false;
+
+                for next in chars.by_ref() {
+                    output.push(next);
+
+                    if star && next == '/' {
+                        break;
+                    }
+
+                    star = next == '*';
+                }
+            }
+            '?' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "numbered parameters are not supported by an emulated prepared statement",
+                ));
+            }
+            '?' => {
+                output.pop();
+
+                count += 1;
+
+                if let Some(value) = values.next() {
+                    output.push_str(&literal(database, value)?);
+                }
+            }
+            ':' | '@' | '$'
+                if chars
+                    .peek()
+                    .is_some_and(|next| next.is_alphabetic() || *next == '_') =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "named parameters are not supported by an emulated prepared statement",
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if count != given {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected {} parameters, got {}", count, given),
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Returns the sql literal of `value`, strings are quoted by [`Database::quote_string`].
+fn literal(database: &dyn Database, value: &SqlValue<'_>) -> Result<String> {
+    let number = match value {
+        SqlValue::Bool(v) => (*v as i64).to_string(),
+        SqlValue::Int(v) => v.to_string(),
+        SqlValue::BigInt(v) => v.to_string(),
+        SqlValue::Float(v) if v.is_finite() => format!("{:?}", v),
+        SqlValue::Float(v) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no sql literal", v),
+            ))
+        }
+        #[cfg(feature = "with-decimal")]
+        SqlValue::Decimal(v) => v.to_string(),
+        SqlValue::Binary(v) => return Ok(hex_literal(v)),
+        SqlValue::String(v) => return Ok(database.quote_string(v)),
+        SqlValue::Null => return Ok("NULL".to_owned()),
+        #[cfg(feature = "with-chrono")]
+        SqlValue::Date(_) | SqlValue::Time(_) | SqlValue::Timestamp(_) => {
+            return Ok(database.quote_string(&crate::format_temporal(value).unwrap_or_default()))
+        }
+        #[cfg(feature = "with-uuid")]
+        SqlValue::Uuid(v) => return Ok(hex_literal(v.as_bytes())),
+    };
+
+    // a negative number after a `-` would start a comment.
+    if number.starts_with('-') {
+        Ok(format!("({})", number))
+    } else {
+        Ok(number)
+    }
+}
+
+fn hex_literal(bytes: &[u8]) -> String {
+    let mut literal = String::with_capacity(bytes.len() * 2 + 3);
+
+    literal.push_str("X'");
+
+    for byte in bytes {
+        _ = write!(literal, "{:02X}", byte);
+    }
+
+    literal.push('\'');
+
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mock::MockDatabase;
+
+    #[test]
+    fn test_emulate_prepared() {
+        let database = MockDatabase::default();
+
+        assert_eq!(
+            emulate_prepared(
+                &database,
+                "SELECT '?', \"a?\", [b?] -- ?\n FROM t /* ? */ WHERE a = ? AND b = ? AND c -? AND d IN (?, ?)",
+                &["it's".into(), SqlValue::Null, (-1).into(), true.into(), 1.5.into()]
+            )
+            .unwrap(),
+            "SELECT '?', \"a?\", [b?] -- ?\n FROM t /* ? */ WHERE a = 'it''s' AND b = NULL AND c -(-1) AND d IN (1, 1.5)"
+        );
+
+        assert_eq!(
+            emulate_prepared(&database, "SELECT ?", &[b"\x00\xff"[..].into()]).unwrap(),
+            "SELECT X'00FF'"
+        );
+
+        for (query, values) in [
+            ("SELECT ?, ?", vec![1.into()]),
+            ("SELECT ?", vec![1.into(), 2.into()]),
+            ("SELECT ?1", vec![1.into()]),
+            ("SELECT :id", vec![1.into()]),
+            ("SELECT ?", vec![f64::NAN.into()]),
+        ] {
+            let err = emulate_prepared(&database, query, &values).unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_emulated_stmt() {
+        let conn = MockDatabase::default()
+            .no_prepare()
+            .fixture(
+                "SELECT name FROM user WHERE id = 1 AND name <> 'o''k'",
+                &["name"],
+                vec![vec!["alice".into()]],
+            )
+            .connect();
+
+        assert!(!conn.capabilities().server_side_prepare);
+
+        block_on(async {
+            let stmt = conn
+                .prepare("SELECT name FROM user WHERE id = ? AND name <> ?")
+                .await
+                .unwrap();
+
+            let rs = stmt.query(&[1.into(), "o'k".into()]).await.unwrap();
+
+            assert!(rs.next().await.unwrap());
+            assert_eq!(rs.get(0).await.unwrap(), SqlValue::String("alice".into()));
+            assert!(!rs.next().await.unwrap());
+
+            let stmt = conn.prepare("INSERT INTO user VALUES (?)").await.unwrap();
+
+            assert_eq!(stmt.exec(&["bob".into()]).await.unwrap(), (1, 1));
+            assert_eq!(
+                stmt.exec_batch(&[vec!["carol".into()], vec!["dave".into()]])
+                    .await
+                    .unwrap(),
+                (3, 2)
+            );
+
+            assert_eq!(
+                stmt.exec(&[]).await.unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+            assert_eq!(
+                stmt.parameter_count().unwrap_err().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+}
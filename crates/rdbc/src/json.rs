@@ -0,0 +1,207 @@
+use std::io::{Result, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Number, Value};
+
+use crate::{ResultSet, SqlValue};
+
+/// Convert `value` to json, see [`ResultSet::into_json`] for the mapping.
+pub(crate) fn to_json_value(value: &SqlValue<'_>) -> Value {
+    match value {
+        SqlValue::Bool(v) => Value::Bool(*v),
+        SqlValue::Int(v) => Value::Number((*v).into()),
+        SqlValue::BigInt(v) => {
+            if let Ok(v) = i64::try_from(*v) {
+                Value::Number(v.into())
+            } else if let Ok(v) = u64::try_from(*v) {
+                Value::Number(v.into())
+            } else {
+                Value::String(v.to_string())
+            }
+        }
+        SqlValue::Float(v) => Number::from_f64(*v)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        #[cfg(feature = "with-decimal")]
+        SqlValue::Decimal(v) => Value::String(v.to_string()),
+        SqlValue::Binary(v) => Value::String(STANDARD.encode(v)),
+        SqlValue::String(v) => Value::String(v.to_string()),
+        SqlValue::Null => Value::Null,
+        #[cfg(feature = "with-chrono")]
+        SqlValue::Date(_) | SqlValue::Time(_) | SqlValue::Timestamp(_) => {
+            Value::String(crate::format_temporal(value).unwrap_or_default())
+        }
+        #[cfg(feature = "with-uuid")]
+        SqlValue::Uuid(v) => Value::String(v.hyphenated().to_string()),
+    }
+}
+
+impl ResultSet {
+    /// Drains this result set into a json array of objects keyed by column name.
+    ///
+    /// Values are mapped as follows:
+    /// * `Null` maps to `null`, `Bool` to a boolean and `String` to a string.
+    /// * `Int` and `Float` map to numbers, non-finite floats map to `null`.
+    /// * `BigInt` maps to a number if it fits in 64 bits, otherwise to its decimal string.
+    /// * `Decimal` maps to its decimal string, to keep the exact value.
+    /// * `Binary` maps to a standard base64 string with padding.
+    /// * `Date`, `Time` and `Timestamp` map to ISO 8601 strings, see `rinq_rdbc::DATE_FORMAT`.
+    /// * `Uuid` maps to its lowercase hyphenated string.
+    pub async fn into_json(self) -> Result<Value> {
+        let columns = self.columns().await?;
+
+        let mut rows = vec![];
+
+        while self.next().await? {
+            rows.push(self.json_row(&columns).await?);
+        }
+
+        Ok(Value::Array(rows))
+    }
+
+    /// Writes this result set to `writer` as newline-delimited json, one object per row.
+    ///
+    /// Values are mapped as in [`into_json`](ResultSet::into_json).
+    /// The writer is flushed after each row, so clients receive rows incrementally.
+    ///
+    /// On success, returns the number of rows written.
+    pub async fn write_ndjson<W: Write>(self, mut writer: W) -> Result<u64> {
+        let columns = self.columns().await?;
+
+        let mut rows = 0;
+
+        while self.next().await? {
+            serde_json::to_writer(&mut writer, &self.json_row(&columns).await?)?;
+
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+
+            rows += 1;
+        }
+
+        Ok(rows)
+    }
+
+    /// Returns the current row as a json object keyed by `columns`.
+    async fn json_row(&self, columns: &[String]) -> Result<Value> {
+        let mut row = Map::new();
+
+        for (col, name) in columns.iter().enumerate() {
+            row.insert(name.clone(), to_json_value(&self.get(col).await?));
+        }
+
+        Ok(Value::Object(row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock::MockDatabase;
+
+    #[test]
+    fn test_into_json() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name, avatar FROM user",
+                    &["id", "name", "avatar"],
+                    vec![
+                        vec![
+                            SqlValue::Int(1),
+                            SqlValue::String(Cow::Borrowed("alice")),
+                            SqlValue::Binary(Cow::Borrowed(b"hi!")),
+                        ],
+                        vec![
+                            SqlValue::Int(2),
+                            SqlValue::String(Cow::Borrowed("bob")),
+                            SqlValue::Null,
+                        ],
+                    ],
+                )
+                .connect();
+
+            let stmt = conn
+                .prepare("SELECT id, name, avatar FROM user")
+                .await
+                .unwrap();
+
+            let value = stmt.query(&[]).await.unwrap().into_json().await.unwrap();
+
+            assert_eq!(
+                value,
+                json!([
+                    { "id": 1, "name": "alice", "avatar": "aGkh" },
+                    { "id": 2, "name": "bob", "avatar": null },
+                ])
+            );
+        });
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name FROM user",
+                    &["id", "name"],
+                    (1..=3)
+                        .map(|id| {
+                            vec![
+                                SqlValue::Int(id),
+                                SqlValue::String(Cow::Owned(format!("user {}", id))),
+                            ]
+                        })
+                        .collect(),
+                )
+                .connect();
+
+            let stmt = conn.prepare("SELECT id, name FROM user").await.unwrap();
+
+            let mut buf = vec![];
+
+            let rows = stmt
+                .query(&[])
+                .await
+                .unwrap()
+                .write_ndjson(&mut buf)
+                .await
+                .unwrap();
+
+            assert_eq!(rows, 3);
+
+            let lines = String::from_utf8(buf).unwrap();
+
+            let lines = lines.lines().collect::<Vec<_>>();
+
+            assert_eq!(lines.len(), 3);
+
+            for (id, line) in (1..=3).zip(lines) {
+                assert_eq!(
+                    serde_json::from_str::<Value>(line).unwrap(),
+                    json!({ "id": id, "name": format!("user {}", id) })
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_to_json_value() {
+        assert_eq!(to_json_value(&SqlValue::Bool(true)), json!(true));
+        assert_eq!(to_json_value(&SqlValue::Float(1.5)), json!(1.5));
+        assert_eq!(to_json_value(&SqlValue::Float(f64::NAN)), json!(null));
+        assert_eq!(
+            to_json_value(&SqlValue::BigInt(u64::MAX as i128)),
+            json!(u64::MAX)
+        );
+        assert_eq!(
+            to_json_value(&SqlValue::BigInt(i128::MAX)),
+            json!(i128::MAX.to_string())
+        );
+    }
+}
@@ -1,19 +1,89 @@
 use std::{
     borrow::Cow,
+    cell::RefCell,
     collections::HashMap,
+    fmt,
+    future::Future,
     io::{self, Result},
-    sync::{Arc, OnceLock, RwLock},
-    task::Context,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(feature = "with-decimal")]
 use bigdecimal::BigDecimal;
+use futures::{stream, Stream};
 use negative_impl::negative_impl;
 use rasi::{
     syscall::{CancelablePoll, Handle},
+    time::TimeoutExt,
     utils::cancelable_would_block,
 };
 
+#[cfg(feature = "with-arrow")]
+mod arrow;
+
+mod connection_string;
+pub use connection_string::*;
+
+mod csv;
+pub use csv::*;
+
+#[cfg(feature = "with-sha2")]
+mod digest;
+#[cfg(feature = "with-sha2")]
+pub use digest::*;
+
+#[cfg(feature = "with-json")]
+mod json;
+
+#[cfg(feature = "with-proto")]
+mod proto;
+#[cfg(feature = "with-proto")]
+pub use proto::*;
+
+mod row;
+pub use row::*;
+
+#[cfg(feature = "with-derive")]
+pub use rinq_rdbc_derive::{sql, FromRow};
+
+#[cfg(feature = "with-chrono")]
+mod temporal;
+#[cfg(feature = "with-chrono")]
+pub use temporal::*;
+
+mod typed;
+pub use typed::*;
+
+mod emulate;
+use emulate::*;
+
+mod upsert;
+
+#[cfg(test)]
+mod mock;
+
 /// A variant type for sql
+///
+/// Rust primitives, strings, buffers and `Option`s convert into values with `into()`,
+/// `&str` and `&[u8]` are borrowed rather than copied:
+///
+/// ```
+/// use rinq_rdbc::SqlValue;
+///
+/// let name = String::from("hi");
+///
+/// let params: [SqlValue<'_>; 4] = [42.into(), name.as_str().into(), b"\x00\x01"[..].into(), None::<i64>.into()];
+///
+/// assert_eq!(params[3], SqlValue::Null);
+/// ```
+///
+/// The `Debug` output contains the value itself, `Binary` buffers are truncated to the first
+/// [`DEBUG_BINARY_LEN`] bytes. Use [`redacted`](SqlValue::redacted) when printing values that may be sensitive.
 pub enum SqlValue<'a> {
     Bool(bool),
     Int(i64),
@@ -24,9 +94,439 @@ pub enum SqlValue<'a> {
     Binary(Cow<'a, [u8]>),
     String(Cow<'a, str>),
     Null,
+    /// A calendar date, stored as text in [`DATE_FORMAT`] by drivers without a native date type.
+    #[cfg(feature = "with-chrono")]
+    Date(chrono::NaiveDate),
+    /// A time of day, stored as text in [`TIME_FORMAT`] by drivers without a native time type.
+    #[cfg(feature = "with-chrono")]
+    Time(chrono::NaiveTime),
+    /// An instant in UTC, stored as text in [`TIMESTAMP_FORMAT`] by drivers without a native timestamp type.
+    #[cfg(feature = "with-chrono")]
+    Timestamp(chrono::DateTime<chrono::Utc>),
+    /// A uuid, stored as a 16-byte blob by drivers without a native uuid type.
+    #[cfg(feature = "with-uuid")]
+    Uuid(uuid::Uuid),
+}
+
+/// The number of leading bytes of a [`SqlValue::Binary`] shown by its `Debug` output.
+pub const DEBUG_BINARY_LEN: usize = 16;
+
+impl fmt::Debug for SqlValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlValue::Bool(v) => f.debug_tuple("Bool").field(v).finish(),
+            SqlValue::Int(v) => f.debug_tuple("Int").field(v).finish(),
+            SqlValue::BigInt(v) => f.debug_tuple("BigInt").field(v).finish(),
+            SqlValue::Float(v) => f.debug_tuple("Float").field(v).finish(),
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => f.debug_tuple("Decimal").field(v).finish(),
+            SqlValue::Binary(v) => {
+                write!(f, "Binary(len={}, ", v.len())?;
+
+                for b in v.iter().take(DEBUG_BINARY_LEN) {
+                    write!(f, "{:02x}", b)?;
+                }
+
+                if v.len() > DEBUG_BINARY_LEN {
+                    f.write_str("..")?;
+                }
+
+                f.write_str(")")
+            }
+            SqlValue::String(v) => f.debug_tuple("String").field(v).finish(),
+            SqlValue::Null => f.write_str("Null"),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(v) => f.debug_tuple("Date").field(v).finish(),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Time(v) => f.debug_tuple("Time").field(v).finish(),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Timestamp(v) => f.debug_tuple("Timestamp").field(v).finish(),
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => f.debug_tuple("Uuid").field(v).finish(),
+        }
+    }
+}
+
+/// Values are equal if they are the same variant with equal contents.
+///
+/// * There is no numeric conversion between variants, `Int(1)` does not equal `Float(1.0)` or `BigInt(1)`.
+/// * `Float` compares as `f64`, so `NaN` is not equal to itself.
+/// * `Decimal` compares by value regardless of scale, `1.50` equals `1.5`.
+/// * `Null` equals `Null`, unlike sql `NULL` semantics.
+/// * `Binary` and `String` compare by content, whether borrowed or owned.
+impl PartialEq<SqlValue<'_>> for SqlValue<'_> {
+    fn eq(&self, other: &SqlValue<'_>) -> bool {
+        match (self, other) {
+            (SqlValue::Bool(a), SqlValue::Bool(b)) => a == b,
+            (SqlValue::Int(a), SqlValue::Int(b)) => a == b,
+            (SqlValue::BigInt(a), SqlValue::BigInt(b)) => a == b,
+            (SqlValue::Float(a), SqlValue::Float(b)) => a == b,
+            #[cfg(feature = "with-decimal")]
+            (SqlValue::Decimal(a), SqlValue::Decimal(b)) => a == b,
+            (SqlValue::Binary(a), SqlValue::Binary(b)) => a == b,
+            (SqlValue::String(a), SqlValue::String(b)) => a == b,
+            (SqlValue::Null, SqlValue::Null) => true,
+            #[cfg(feature = "with-chrono")]
+            (SqlValue::Date(a), SqlValue::Date(b)) => a == b,
+            #[cfg(feature = "with-chrono")]
+            (SqlValue::Time(a), SqlValue::Time(b)) => a == b,
+            #[cfg(feature = "with-chrono")]
+            (SqlValue::Timestamp(a), SqlValue::Timestamp(b)) => a == b,
+            #[cfg(feature = "with-uuid")]
+            (SqlValue::Uuid(a), SqlValue::Uuid(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+macro_rules! from_integer {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for SqlValue<'static> {
+                fn from(value: $t) -> Self {
+                    SqlValue::Int(value.into())
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i8, i16, i32, i64);
+
+impl From<bool> for SqlValue<'static> {
+    fn from(value: bool) -> Self {
+        SqlValue::Bool(value)
+    }
+}
+
+impl From<f32> for SqlValue<'static> {
+    fn from(value: f32) -> Self {
+        SqlValue::Float(value.into())
+    }
+}
+
+impl From<f64> for SqlValue<'static> {
+    fn from(value: f64) -> Self {
+        SqlValue::Float(value)
+    }
+}
+
+/// Borrows the string as [`SqlValue::String`] without copying.
+impl<'a> From<&'a str> for SqlValue<'a> {
+    fn from(value: &'a str) -> Self {
+        SqlValue::String(Cow::Borrowed(value))
+    }
+}
+
+impl From<String> for SqlValue<'static> {
+    fn from(value: String) -> Self {
+        SqlValue::String(Cow::Owned(value))
+    }
+}
+
+/// Borrows the buffer as [`SqlValue::Binary`] without copying.
+impl<'a> From<&'a [u8]> for SqlValue<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        SqlValue::Binary(Cow::Borrowed(value))
+    }
+}
+
+impl From<Vec<u8>> for SqlValue<'static> {
+    fn from(value: Vec<u8>) -> Self {
+        SqlValue::Binary(Cow::Owned(value))
+    }
+}
+
+/// Maps `None` to [`SqlValue::Null`] and `Some(v)` to the value of `v`.
+impl<'a, T: Into<SqlValue<'a>>> From<Option<T>> for SqlValue<'a> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(SqlValue::Null, Into::into)
+    }
+}
+
+/// Returns the [`InvalidData`](io::ErrorKind::InvalidData) error of a failed `TryFrom<SqlValue>` conversion into `expect`.
+fn mismatch(expect: &str, value: &SqlValue<'_>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Expect {} value, got {}", expect, value.redacted()),
+    )
+}
+
+/// Accepts `Int` and a `BigInt` in the `i64` range.
+impl TryFrom<SqlValue<'_>> for i64 {
+    type Error = io::Error;
+
+    fn try_from(value: SqlValue<'_>) -> Result<Self> {
+        match value {
+            SqlValue::Int(v) => Ok(v),
+            SqlValue::BigInt(v) => i64::try_from(v).map_err(|_| mismatch("i64", &value)),
+            _ => Err(mismatch("i64", &value)),
+        }
+    }
+}
+
+impl TryFrom<SqlValue<'_>> for f64 {
+    type Error = io::Error;
+
+    fn try_from(value: SqlValue<'_>) -> Result<Self> {
+        match value {
+            SqlValue::Float(v) => Ok(v),
+            _ => Err(mismatch("f64", &value)),
+        }
+    }
+}
+
+/// Accepts `Bool` and an `Int` of `0` or `1`, the form drivers without a boolean type return.
+impl TryFrom<SqlValue<'_>> for bool {
+    type Error = io::Error;
+
+    fn try_from(value: SqlValue<'_>) -> Result<Self> {
+        match value {
+            SqlValue::Bool(v) => Ok(v),
+            SqlValue::Int(0) => Ok(false),
+            SqlValue::Int(1) => Ok(true),
+            _ => Err(mismatch("bool", &value)),
+        }
+    }
+}
+
+/// Moves an owned string, a borrowed one is copied.
+impl TryFrom<SqlValue<'_>> for String {
+    type Error = io::Error;
+
+    fn try_from(value: SqlValue<'_>) -> Result<Self> {
+        match value {
+            SqlValue::String(v) => Ok(v.into_owned()),
+            _ => Err(mismatch("String", &value)),
+        }
+    }
+}
+
+/// Moves an owned buffer, a borrowed one is copied.
+impl TryFrom<SqlValue<'_>> for Vec<u8> {
+    type Error = io::Error;
+
+    fn try_from(value: SqlValue<'_>) -> Result<Self> {
+        match value {
+            SqlValue::Binary(v) => Ok(v.into_owned()),
+            _ => Err(mismatch("Vec<u8>", &value)),
+        }
+    }
+}
+
+/// Maps [`SqlValue::Null`] to `None`, other values are converted into `T`.
+impl<'a, T: TryFrom<SqlValue<'a>, Error = io::Error>> TryFrom<SqlValue<'a>> for Option<T> {
+    type Error = io::Error;
+
+    fn try_from(value: SqlValue<'a>) -> Result<Self> {
+        match value {
+            SqlValue::Null => Ok(None),
+            value => T::try_from(value).map(Some),
+        }
+    }
+}
+
+/// Converts to the number of milliseconds since [`UNIX_EPOCH`], stored as [`SqlValue::Int`].
+///
+/// Times before the epoch are represented as negative values.
+impl From<SystemTime> for SqlValue<'static> {
+    fn from(value: SystemTime) -> Self {
+        let millis = match value.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_millis() as i64,
+            Err(err) => -(err.duration().as_millis() as i64),
+        };
+
+        SqlValue::Int(millis)
+    }
+}
+
+/// Borrows the buffer as [`SqlValue::Binary`] without copying.
+#[cfg(feature = "with-bytes")]
+impl<'a> From<&'a bytes::Bytes> for SqlValue<'a> {
+    fn from(value: &'a bytes::Bytes) -> Self {
+        SqlValue::Binary(Cow::Borrowed(value))
+    }
+}
+
+/// Copies the buffer into an owned [`SqlValue::Binary`],
+/// use `From<&Bytes>` to bind without copying.
+#[cfg(feature = "with-bytes")]
+impl From<bytes::Bytes> for SqlValue<'static> {
+    fn from(value: bytes::Bytes) -> Self {
+        SqlValue::Binary(Cow::Owned(value.into()))
+    }
+}
+
+#[cfg(feature = "with-uuid")]
+impl From<uuid::Uuid> for SqlValue<'static> {
+    fn from(value: uuid::Uuid) -> Self {
+        SqlValue::Uuid(value)
+    }
+}
+
+impl<'a> SqlValue<'a> {
+    /// Converts a [`Binary`](SqlValue::Binary) value into [`Bytes`](bytes::Bytes), or returns `None` for other variants.
+    ///
+    /// Owned buffers, e.g. values returned by [`ResultSet::get`], are moved without copying,
+    /// borrowed ones are copied. Note that drivers like sqlite still copy the buffer when binding.
+    #[cfg(feature = "with-bytes")]
+    pub fn into_bytes(self) -> Option<bytes::Bytes> {
+        match self {
+            SqlValue::Binary(Cow::Owned(v)) => Some(v.into()),
+            SqlValue::Binary(Cow::Borrowed(v)) => Some(bytes::Bytes::copy_from_slice(v)),
+            _ => None,
+        }
+    }
+
+    /// Converts into a value that owns its data, borrowed `String` and `Binary` buffers are copied,
+    /// owned buffers and the other variants are moved unchanged.
+    pub fn into_owned(self) -> SqlValue<'static> {
+        match self {
+            SqlValue::Bool(v) => SqlValue::Bool(v),
+            SqlValue::Int(v) => SqlValue::Int(v),
+            SqlValue::BigInt(v) => SqlValue::BigInt(v),
+            SqlValue::Float(v) => SqlValue::Float(v),
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => SqlValue::Decimal(v),
+            SqlValue::Binary(v) => SqlValue::Binary(Cow::Owned(v.into_owned())),
+            SqlValue::String(v) => SqlValue::String(Cow::Owned(v.into_owned())),
+            SqlValue::Null => SqlValue::Null,
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(v) => SqlValue::Date(v),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Time(v) => SqlValue::Time(v),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Timestamp(v) => SqlValue::Timestamp(v),
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => SqlValue::Uuid(v),
+        }
+    }
+
+    /// Returns a value borrowing the `String` and `Binary` buffers of `self`, e.g. to bind it without copying.
+    ///
+    /// A `Decimal` is cloned, the other variants are copied.
+    pub fn as_ref(&self) -> SqlValue<'_> {
+        match self {
+            SqlValue::Bool(v) => SqlValue::Bool(*v),
+            SqlValue::Int(v) => SqlValue::Int(*v),
+            SqlValue::BigInt(v) => SqlValue::BigInt(*v),
+            SqlValue::Float(v) => SqlValue::Float(*v),
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => SqlValue::Decimal(v.clone()),
+            SqlValue::Binary(v) => SqlValue::Binary(Cow::Borrowed(v)),
+            SqlValue::String(v) => SqlValue::String(Cow::Borrowed(v)),
+            SqlValue::Null => SqlValue::Null,
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(v) => SqlValue::Date(*v),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Time(v) => SqlValue::Time(*v),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Timestamp(v) => SqlValue::Timestamp(*v),
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => SqlValue::Uuid(*v),
+        }
+    }
+
+    /// Returns `default` if the value is [`Null`](SqlValue::Null), otherwise returns `self`.
+    ///
+    /// Like [`Option::or`], `default` is eagerly evaluated, use [`or_else`](SqlValue::or_else) to compute it lazily.
+    pub fn or(self, default: SqlValue<'a>) -> SqlValue<'a> {
+        match self {
+            SqlValue::Null => default,
+            value => value,
+        }
+    }
+
+    /// Returns the result of `f` if the value is [`Null`](SqlValue::Null), otherwise returns `self`.
+    pub fn or_else<F: FnOnce() -> SqlValue<'a>>(self, f: F) -> SqlValue<'a> {
+        match self {
+            SqlValue::Null => f(),
+            value => value,
+        }
+    }
+
+    /// Returns the length in bytes of a [`Binary`](SqlValue::Binary) or [`String`](SqlValue::String) value,
+    /// or `None` for other variants.
+    pub fn byte_len(&self) -> Option<usize> {
+        match self {
+            SqlValue::Binary(v) => Some(v.len()),
+            SqlValue::String(v) => Some(v.len()),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of chars of a [`String`](SqlValue::String) value, or `None` for other variants.
+    pub fn char_len(&self) -> Option<usize> {
+        match self {
+            SqlValue::String(v) => Some(v.chars().count()),
+            _ => None,
+        }
+    }
+
+    /// Returns a wrapper that prints only the variant name and, for buffers, the length in bytes,
+    /// e.g. `String(len=12)` or `Int`, never the value itself.
+    pub fn redacted(&self) -> Redacted<'_, 'a> {
+        Redacted(self)
+    }
+
+    /// Interprets an [`Int`](SqlValue::Int) value as milliseconds since [`UNIX_EPOCH`].
+    ///
+    /// Returns `None` for any other variant, see [`From<SystemTime>`](SqlValue#impl-From<SystemTime>-for-SqlValue<'static>).
+    pub fn as_system_time(&self) -> Option<SystemTime> {
+        match self {
+            SqlValue::Int(millis) if *millis >= 0 => {
+                UNIX_EPOCH.checked_add(Duration::from_millis(*millis as u64))
+            }
+            SqlValue::Int(millis) => {
+                UNIX_EPOCH.checked_sub(Duration::from_millis(millis.unsigned_abs()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A [`SqlValue`] wrapper returned by [`SqlValue::redacted`], its `Display` and `Debug`
+/// output hides the content of the value.
+pub struct Redacted<'b, 'a>(&'b SqlValue<'a>);
+
+impl fmt::Display for Redacted<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.0 {
+            SqlValue::Binary(v) => return write!(f, "Binary(len={})", v.len()),
+            SqlValue::String(v) => return write!(f, "String(len={})", v.len()),
+            SqlValue::Bool(_) => "Bool",
+            SqlValue::Int(_) => "Int",
+            SqlValue::BigInt(_) => "BigInt",
+            SqlValue::Float(_) => "Float",
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(_) => "Decimal",
+            SqlValue::Null => "Null",
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(_) => "Date",
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Time(_) => "Time",
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Timestamp(_) => "Timestamp",
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(_) => "Uuid",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl fmt::Debug for Redacted<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
 }
 
 /// This type contains the name and type of a column.
+///
+/// With the `with-serde` feature, it serializes with the field names below
+/// and `decimal_size` as a `[scale, precision]` pair.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize))]
 pub struct ColumnType<'a> {
     /// returns the database system name of the column type.
     pub database_type_name: Cow<'a, str>,
@@ -48,6 +548,157 @@ pub struct ColumnType<'a> {
     pub nullable: Option<bool>,
 }
 
+/// Everything known about a prepared statement, see [`Stmt::describe`].
+#[derive(Debug)]
+pub struct StatementInfo {
+    /// The number of parameters, see [`Stmt::parameter_count`].
+    pub parameter_count: usize,
+    /// The name of each parameter, `None` for a parameter without name, see [`Stmt::parameter_name`].
+    pub parameter_names: Vec<Option<String>>,
+    /// The number of result columns, `0` for a statement which returns no rows.
+    pub column_count: usize,
+    /// The name of each result column.
+    pub column_names: Vec<String>,
+    /// The type of each result column, see [`Stmt::column_types`].
+    pub column_types: Vec<ColumnType<'static>>,
+    /// True if the statement does not write to the database, see [`Stmt::is_readonly`].
+    pub is_readonly: bool,
+}
+
+/// The optional features supported by a driver, see [`Database::capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The driver runs `INSERT ... ON CONFLICT (..) DO UPDATE`, see [`DbConn::upsert`].
+    pub upsert_on_conflict: bool,
+    /// The driver prepares statements with parameters, true by default.
+    ///
+    /// Otherwise [`Stmt::query`] and [`Stmt::exec`] substitute the parameters with literals quoted by
+    /// [`Database::quote_string`] and prepare the resulting statement on each execution.
+    pub server_side_prepare: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            upsert_on_conflict: false,
+            server_side_prepare: true,
+        }
+    }
+}
+
+/// The counters of one loop of a query plan, see [`Stmt::scan_status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanStatus {
+    /// The number of times the loop has run.
+    pub n_loop: i64,
+    /// The number of rows visited by the loop.
+    pub n_visit: i64,
+    /// The number of rows the query planner estimated for each run of the loop.
+    pub est: f64,
+    /// The name of the table or index the loop scans.
+    pub name: String,
+}
+
+/// The kind of row change reported by a [`ChangeEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A row change of a table watched by [`DbConn::watch_table`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// The kind of change.
+    pub op: ChangeOp,
+    /// The row id of the changed row.
+    pub rowid: i64,
+    /// The number of events dropped before this one because the subscriber fell behind.
+    pub missed: u64,
+}
+
+/// Transaction isolation levels, from the weakest to the strongest.
+///
+/// The default is `ReadCommitted`, the default level of most databases.
+/// A driver may run a transaction at a stronger level than requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    #[default]
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+/// Options of a transaction started by [`DbConn::begin_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct TxOptions {
+    level: IsolationLevel,
+    deadline: Option<Duration>,
+}
+
+impl TxOptions {
+    /// Set the isolation level, see [`DbConn::begin_with`].
+    pub fn level(mut self, level: IsolationLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Limit the whole transaction to `deadline` from its start.
+    ///
+    /// Once the deadline has passed, the next operation on the transaction, its savepoints or the statements
+    /// prepared in it rolls back the transaction and returns [`TimedOut`](io::ErrorKind::TimedOut) error,
+    /// as do all later operations. An operation that is still waiting at the deadline, e.g. for a lock,
+    /// times out at the deadline, which uses the global timer like [`Stmt::exec_timeout`].
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+/// The deadline of a transaction, shared by the transaction, its savepoints and its statements.
+struct TxDeadline {
+    at: Instant,
+    /// The top transaction, which is rolled back when the deadline is exceeded.
+    tx_handle: Arc<Handle>,
+    database: Arc<Box<dyn Database>>,
+    /// The transaction has been rolled back.
+    expired: AtomicBool,
+}
+
+impl TxDeadline {
+    /// Returns [`TimedOut`](io::ErrorKind::TimedOut) error if the deadline has passed,
+    /// the transaction is rolled back on the first call after the deadline.
+    async fn check(&self) -> Result<()> {
+        if Instant::now() < self.at {
+            return Ok(());
+        }
+
+        if !self.expired.swap(true, Ordering::SeqCst) {
+            // the transaction ends either way, the rollback error is not more useful than the timeout.
+            _ = cancelable_would_block(|cx| self.database.rollback(cx, &self.tx_handle)).await;
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Transaction deadline exceeded, the transaction is rolled back",
+        ))
+    }
+
+    /// Returns `timeout` shortened to the time left until the deadline.
+    fn bound(deadline: &Option<Arc<TxDeadline>>, timeout: Option<Duration>) -> Option<Duration> {
+        let Some(deadline) = deadline else {
+            return timeout;
+        };
+
+        let left = deadline.at.saturating_duration_since(Instant::now());
+
+        Some(timeout.map_or(left, |timeout| timeout.min(left)))
+    }
+}
+
 /// Represents database driver that can be shared between threads, and can therefore implement a connection pool
 pub trait Database: Send + Sync {
     /// Open a new database connection with `source_name` and not block the calling thread.
@@ -56,9 +707,116 @@ pub trait Database: Send + Sync {
     /// Poll [`start_connect`](Database::start_connect) op's result.
     fn poll_connect(&self, cx: &mut Context<'_>, handle: &Handle) -> CancelablePoll<Result<()>>;
 
-    /// Starts a transaction via one connection. The default isolation level is dependent on the driver.
+    /// Starts a transaction via one connection with the [default](IsolationLevel::default) isolation level.
     fn begin(&self, cx: &mut Context<'_>, conn: &Handle) -> CancelablePoll<Result<Handle>>;
 
+    /// Starts a transaction via one connection with isolation `level`.
+    ///
+    /// Drivers that can not honor `level` return [`Unsupported`](io::ErrorKind::Unsupported) error.
+    /// The default implementation calls [`begin`](Database::begin) for the default level
+    /// and returns [`Unsupported`](io::ErrorKind::Unsupported) error for the others.
+    fn begin_with(
+        &self,
+        cx: &mut Context<'_>,
+        conn: &Handle,
+        level: IsolationLevel,
+    ) -> CancelablePoll<Result<Handle>> {
+        if level == IsolationLevel::default() {
+            return self.begin(cx, conn);
+        }
+
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Isolation level {:?} is not supported by the driver", level),
+        )))
+    }
+
+    /// Starts a transaction via one connection and creates a prepared statement in it.
+    ///
+    /// Drivers that can do both in one round trip should override this method
+    /// together with [`poll_begin_prepare`](Database::poll_begin_prepare).
+    /// The default implementation calls [`begin`](Database::begin) and
+    /// [`start_prepare`](Database::start_prepare) sequentially.
+    fn start_begin_prepare(&self, conn: &Handle, query: &str) -> Result<Handle> {
+        _ = conn;
+
+        Ok(Handle::new(Mutex::new(BeginPrepare {
+            query: query.to_owned(),
+            tx: None,
+            stmt: None,
+            prepare_error: None,
+        })))
+    }
+
+    /// Poll [`begin_prepare`](Database::start_begin_prepare) result.
+    ///
+    /// On success, returns the transaction and the prepared statement handles.
+    /// If the prepare fails, the transaction is rolled back before the error is returned.
+    fn poll_begin_prepare(
+        &self,
+        cx: &mut Context<'_>,
+        conn: &Handle,
+        begin_prepare: &Handle,
+    ) -> CancelablePoll<Result<(Handle, Handle)>> {
+        let mut begin_prepare = begin_prepare
+            .downcast::<Mutex<BeginPrepare>>()
+            .expect("Expect default BeginPrepare handle")
+            .lock()
+            .unwrap();
+
+        if let Some(err) = begin_prepare.prepare_error.take() {
+            let tx = begin_prepare
+                .tx
+                .take()
+                .expect("Expect transaction to roll back");
+
+            return begin_prepare.rollback(self, cx, tx, err);
+        }
+
+        let tx = match begin_prepare.tx.take() {
+            Some(tx) => tx,
+            None => match self.begin(cx, conn) {
+                CancelablePoll::Ready(Ok(tx)) => tx,
+                CancelablePoll::Ready(Err(err)) => return CancelablePoll::Ready(Err(err)),
+                CancelablePoll::Pending(cancel) => return CancelablePoll::Pending(cancel),
+            },
+        };
+
+        let stmt = match begin_prepare.stmt.take() {
+            Some(stmt) => stmt,
+            None => match self.start_prepare(&tx, &begin_prepare.query) {
+                Ok(stmt) => stmt,
+                Err(err) => return begin_prepare.rollback(self, cx, tx, err),
+            },
+        };
+
+        match self.poll_prepare(cx, &stmt) {
+            CancelablePoll::Ready(Ok(())) => CancelablePoll::Ready(Ok((tx, stmt))),
+            CancelablePoll::Ready(Err(err)) => begin_prepare.rollback(self, cx, tx, err),
+            CancelablePoll::Pending(cancel) => {
+                begin_prepare.tx = Some(tx);
+                begin_prepare.stmt = Some(stmt);
+
+                CancelablePoll::Pending(cancel)
+            }
+        }
+    }
+
+    /// Creates a savepoint in the transaction `tx`, which acts as a nested transaction.
+    ///
+    /// The returned handle is passed to [`commit`](Database::commit) to release the savepoint
+    /// and to [`rollback`](Database::rollback) to roll back to it.
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn savepoint(&self, cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<Handle>> {
+        _ = cx;
+        _ = tx;
+
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Savepoint is not supported by the driver",
+        )))
+    }
+
     /// Aborts the transaction.
     fn rollback(&self, cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<()>>;
 
@@ -74,6 +832,71 @@ pub trait Database: Send + Sync {
     /// Asynchronously fetch the [`start_prepare`](Database::start_prepare)'s calling result.
     fn poll_prepare(&self, cx: &mut Context<'_>, stmt: &Handle) -> CancelablePoll<Result<()>>;
 
+    /// Returns the number of parameters of the prepared statement `stmt`.
+    ///
+    /// [`Stmt::query`] and [`Stmt::exec`] check the number of values against it before binding.
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error, which skips the check.
+    fn parameter_count(&self, stmt: &Handle) -> Result<usize> {
+        _ = stmt;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Parameter count is not supported by the driver",
+        ))
+    }
+
+    /// Returns the name of the parameter of `stmt` at `index`, which is the position of its value
+    /// in the values passed to [`start_query`](Database::start_query), e.g. `:id`,
+    /// or `None` if the parameter has no name.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn parameter_name(&self, stmt: &Handle, index: usize) -> Result<Option<String>> {
+        _ = stmt;
+        _ = index;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Parameter name is not supported by the driver",
+        ))
+    }
+
+    /// Returns the result columns of the prepared statement `stmt` without executing it,
+    /// see [`poll_col_types`](Database::poll_col_types).
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn stmt_col_types(&self, stmt: &Handle) -> Result<Vec<ColumnType<'static>>> {
+        _ = stmt;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Statement column types are not supported by the driver",
+        ))
+    }
+
+    /// Returns the counters of each loop of the query plan of `stmt`, collected by its executions.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn scan_status(&self, stmt: &Handle) -> Result<Vec<ScanStatus>> {
+        _ = stmt;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Scan status is not supported by the driver",
+        ))
+    }
+
+    /// Returns true if the prepared statement `stmt` does not write to the database.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn is_readonly(&self, stmt: &Handle) -> Result<bool> {
+        _ = stmt;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Readonly check is not supported by the driver",
+        ))
+    }
+
     /// Execute a query that is expected to return a result set, such as a SELECT statement
     fn start_query(&self, stmt: &Handle, values: &[SqlValue<'_>]) -> Result<Handle>;
 
@@ -101,6 +924,99 @@ pub trait Database: Send + Sync {
         result: &Handle,
     ) -> CancelablePoll<Result<(i64, i64)>>;
 
+    /// Like [`start_query`](Database::start_query), but binds each value of `params` to the parameter with the same name.
+    ///
+    /// The result set is polled with [`poll_next`](Database::poll_next) and [`poll_value`](Database::poll_value).
+    /// Drivers return [`InvalidInput`](io::ErrorKind::InvalidInput) error for an unknown parameter name.
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn start_query_named(&self, stmt: &Handle, params: &[(&str, SqlValue<'_>)]) -> Result<Handle> {
+        _ = stmt;
+        _ = params;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Named parameters are not supported by the driver",
+        ))
+    }
+
+    /// Like [`start_exec`](Database::start_exec), but binds each value of `params` to the parameter with the same name.
+    ///
+    /// The result is polled with [`poll_exec`](Database::poll_exec).
+    /// Drivers return [`InvalidInput`](io::ErrorKind::InvalidInput) error for an unknown parameter name.
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn start_exec_named(&self, stmt: &Handle, params: &[(&str, SqlValue<'_>)]) -> Result<Handle> {
+        _ = stmt;
+        _ = params;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Named parameters are not supported by the driver",
+        ))
+    }
+
+    /// Execute a statement once for each parameter set in `rows`.
+    ///
+    /// [`Stmt::exec_batch`] runs the batch in a transaction or at a savepoint, so a failed parameter set
+    /// discards the ones executed before it.
+    /// Drivers that can send many parameter sets in one round trip should override this method
+    /// together with [`poll_exec_batch_params`](Database::poll_exec_batch_params).
+    /// The default implementation copies `rows` and executes them one by one via
+    /// [`start_exec`](Database::start_exec)/[`poll_exec`](Database::poll_exec).
+    fn start_exec_batch_params(&self, stmt: &Handle, rows: &[Vec<SqlValue<'_>>]) -> Result<Handle> {
+        _ = stmt;
+
+        Ok(Handle::new(Mutex::new(ExecBatch {
+            rows: rows
+                .iter()
+                .map(|row| row.iter().map(|v| v.as_ref().into_owned()).collect())
+                .collect(),
+            next: 0,
+            executing: None,
+            last_insert_id: 0,
+            rows_affected: 0,
+        })))
+    }
+
+    /// Poll [`exec_batch_params`](Database::start_exec_batch_params) result.
+    ///
+    /// On success, returns the `last insert id` of the last parameter set and the total `rows affected`.
+    fn poll_exec_batch_params(
+        &self,
+        cx: &mut Context<'_>,
+        stmt: &Handle,
+        batch: &Handle,
+    ) -> CancelablePoll<Result<(i64, i64)>> {
+        let mut batch = batch
+            .downcast::<Mutex<ExecBatch>>()
+            .expect("Expect default ExecBatch handle")
+            .lock()
+            .unwrap();
+
+        loop {
+            if let Some(executing) = &batch.executing {
+                match self.poll_exec(cx, executing) {
+                    CancelablePoll::Ready(Ok((last_insert_id, rows_affected))) => {
+                        batch.last_insert_id = last_insert_id;
+                        batch.rows_affected += rows_affected;
+                        batch.executing = None;
+                        batch.next += 1;
+                    }
+                    CancelablePoll::Ready(Err(err)) => return CancelablePoll::Ready(Err(err)),
+                    pending => return pending,
+                }
+            }
+
+            if batch.next == batch.rows.len() {
+                return CancelablePoll::Ready(Ok((batch.last_insert_id, batch.rows_affected)));
+            }
+
+            match self.start_exec(stmt, &batch.rows[batch.next]) {
+                Ok(executing) => batch.executing = Some(executing),
+                Err(err) => return CancelablePoll::Ready(Err(err)),
+            }
+        }
+    }
+
     /// Returns the column names.
     ///
     /// Returns error, if the `ResultSet` is closed.
@@ -116,215 +1032,2716 @@ pub trait Database: Send + Sync {
         cx: &mut Context<'_>,
         result_set: &Handle,
     ) -> CancelablePoll<Result<Vec<ColumnType<'static>>>>;
-}
 
-/// Represents a database connection.
-pub struct DbConn {
-    conn: Handle,
-    database: Arc<Box<dyn Database>>,
-}
+    /// Returns the `(table, column)` that the column at `col` of `result_set` is read from,
+    /// which is not changed by a column alias.
+    ///
+    /// Returns `None` if the column is an expression or subquery, or the driver does not know the origin.
+    /// The default implementation returns `None`.
+    fn column_origin(&self, result_set: &Handle, col: usize) -> Result<Option<(String, String)>> {
+        _ = result_set;
+        _ = col;
 
-impl DbConn {
-    /// Creates a prepared statement for later queries or executions.
-    pub async fn prepare<Q: AsRef<str>>(&self, query: Q) -> Result<Stmt> {
-        let stmt_handle = self.database.start_prepare(&self.conn, query.as_ref())?;
+        Ok(None)
+    }
 
-        cancelable_would_block(|cx| self.database.poll_prepare(cx, &stmt_handle)).await?;
+    /// Returns the row id of the current row of `result_set`, for a query which selects from a single table
+    /// whose rows have an id, even if the id is not one of the selected columns.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn poll_rowid(&self, cx: &mut Context<'_>, result_set: &Handle) -> CancelablePoll<Result<i64>> {
+        _ = cx;
+        _ = result_set;
 
-        Ok(Stmt {
-            stmt_handle,
-            database: self.database.clone(),
-        })
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Row id is not supported by the driver",
+        )))
     }
 
-    /// Starts a transaction.
-    pub async fn begin(&self) -> Result<Tx> {
-        cancelable_would_block(|cx| self.database.begin(cx, &self.conn))
-            .await
-            .map(|tx_handle| Tx {
-                tx_handle,
-                database: self.database.clone(),
-            })
-    }
-}
+    /// Returns the row id of the most recent successful insert on the connection `conn`.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn poll_last_insert_id(
+        &self,
+        cx: &mut Context<'_>,
+        conn: &Handle,
+    ) -> CancelablePoll<Result<i64>> {
+        _ = cx;
+        _ = conn;
 
-/// Tx is an in-progress database transaction.
-pub struct Tx {
-    tx_handle: Handle,
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Last insert id is not supported by the driver",
+        )))
+    }
+
+    /// Returns the number of rows inserted, updated or deleted by all statements on the connection `conn`
+    /// since it was opened.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn poll_total_changes(
+        &self,
+        cx: &mut Context<'_>,
+        conn: &Handle,
+    ) -> CancelablePoll<Result<i64>> {
+        _ = cx;
+        _ = conn;
+
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Total changes is not supported by the driver",
+        )))
+    }
+
+    /// Returns the `last_insert_id` and `rows_affected` of the statement that produced `result_set`,
+    /// once all of its rows are read, like the result of [`poll_exec`](Database::poll_exec).
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn poll_query_result(
+        &self,
+        cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<(i64, i64)>> {
+        _ = cx;
+        _ = result_set;
+
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Query result is not supported by the driver",
+        )))
+    }
+
+    /// Starts the read snapshot of the transaction `tx` now rather than on its first read,
+    /// so that the changes committed by other connections from now on are not visible in `tx`.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn poll_snapshot(&self, cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<()>> {
+        _ = cx;
+        _ = tx;
+
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Transaction snapshot is not supported by the driver",
+        )))
+    }
+
+    /// Subscribes to the row changes of `table` made on the connection `conn`,
+    /// returns a watch handle that is polled by [`poll_change`](Database::poll_change).
+    ///
+    /// The subscription ends when the returned handle is dropped.
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn start_watch(&self, conn: &Handle, table: &str) -> Result<Handle> {
+        _ = conn;
+        _ = table;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Watch is not supported by the driver",
+        ))
+    }
+
+    /// Returns the next row change of the watch handle `watch`, created by [`start_watch`](Database::start_watch).
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn poll_change(
+        &self,
+        cx: &mut Context<'_>,
+        watch: &Handle,
+    ) -> CancelablePoll<Result<ChangeEvent>> {
+        _ = cx;
+        _ = watch;
+
+        CancelablePoll::Ready(Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Watch is not supported by the driver",
+        )))
+    }
+
+    /// Sets the session value `key` of the connection `conn` to `value`, which is kept until the connection is closed
+    /// and can be read by the sql run on the connection, e.g. by a trigger. A [`SqlValue::Null`] value removes the key.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn set_session_value(&self, conn: &Handle, key: &str, value: SqlValue<'static>) -> Result<()> {
+        _ = conn;
+        _ = key;
+        _ = value;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Session values are not supported by the driver",
+        ))
+    }
+
+    /// Returns the session value `key` of the connection `conn`, or `None` if it is not set.
+    ///
+    /// The default implementation returns [`Unsupported`](io::ErrorKind::Unsupported) error.
+    fn session_value(&self, conn: &Handle, key: &str) -> Result<Option<SqlValue<'static>>> {
+        _ = conn;
+        _ = key;
+
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Session values are not supported by the driver",
+        ))
+    }
+
+    /// Returns the optional features supported by this driver.
+    ///
+    /// The default implementation returns [`Capabilities::default`].
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Returns `value` as a string literal of this database.
+    ///
+    /// The default implementation encloses `value` in single quotes, doubling the embedded ones.
+    fn quote_string(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+
+    /// Returns `name` as an identifier of this database.
+    ///
+    /// The default implementation encloses `name` in double quotes, doubling the embedded ones.
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+
+    /// Returns details of the most recent error that occurred on the connection `conn`.
+    ///
+    /// Drivers whose poll results are coarse can use this to expose the underlying
+    /// database diagnostics. The default implementation returns `None`.
+    fn last_error(&self, conn: &Handle) -> Option<io::Error> {
+        _ = conn;
+        None
+    }
+
+    /// Returns true if an error has already rolled back the transaction `tx`.
+    ///
+    /// Which errors abort a transaction depends on the database, e.g. a constraint violation
+    /// leaves a sqlite transaction usable while a full disk rolls it back.
+    /// The default implementation returns `false`.
+    fn is_tx_aborted(&self, tx: &Handle) -> bool {
+        _ = tx;
+        false
+    }
+}
+
+/// The state of the default [`start_begin_prepare`](Database::start_begin_prepare) implementation.
+struct BeginPrepare {
+    query: String,
+    tx: Option<Handle>,
+    stmt: Option<Handle>,
+    /// The error of a failed prepare, returned once `tx` is rolled back.
+    prepare_error: Option<io::Error>,
+}
+
+impl BeginPrepare {
+    /// Roll back `tx` after the prepare failed with `err`, then return `err`.
+    ///
+    /// A failed rollback is appended to the message of `err`.
+    fn rollback<D: Database + ?Sized>(
+        &mut self,
+        database: &D,
+        cx: &mut Context<'_>,
+        tx: Handle,
+        err: io::Error,
+    ) -> CancelablePoll<Result<(Handle, Handle)>> {
+        match database.rollback(cx, &tx) {
+            CancelablePoll::Ready(Ok(())) => CancelablePoll::Ready(Err(err)),
+            CancelablePoll::Ready(Err(rollback_err)) => CancelablePoll::Ready(Err(io::Error::new(
+                err.kind(),
+                format!("{}, rollback failed: {}", err, rollback_err),
+            ))),
+            CancelablePoll::Pending(cancel) => {
+                self.tx = Some(tx);
+                self.prepare_error = Some(err);
+
+                CancelablePoll::Pending(cancel)
+            }
+        }
+    }
+}
+
+/// The state of the default [`start_exec_batch_params`](Database::start_exec_batch_params) implementation.
+struct ExecBatch {
+    rows: Vec<Vec<SqlValue<'static>>>,
+    /// The index of the parameter set that is executing or will be executed next.
+    next: usize,
+    executing: Option<Handle>,
+    last_insert_id: i64,
+    rows_affected: i64,
+}
+
+/// The default statement timeout shared by a connection and the transactions and statements created from it.
+type StatementTimeout = Arc<Mutex<Option<Duration>>>;
+
+/// The statements of [`DbConn::prepare_cached`] by query.
+///
+/// A statement is in use while a [`Stmt`] holds a clone of its handle.
+type StmtCache = Arc<Mutex<HashMap<String, Arc<Handle>>>>;
+
+/// Await `future`, or returns [`TimedOut`](io::ErrorKind::TimedOut) error after `timeout` if it is not `None`.
+///
+/// On timeout the pending driver operation is cancelled.
+async fn timeout_or<T, F: Future<Output = Result<T>>>(
+    future: F,
+    timeout: Option<Duration>,
+    op: &str,
+) -> Result<T> {
+    match timeout {
+        Some(duration) => future.timeout(duration).await.unwrap_or(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{} timeout", op),
+        ))),
+        None => future.await,
+    }
+}
+
+/// Represents a database connection.
+pub struct DbConn {
+    conn: Arc<Handle>,
     database: Arc<Box<dyn Database>>,
+    statement_timeout: StatementTimeout,
+    stmt_cache: StmtCache,
 }
 
-impl Tx {
+impl DbConn {
     /// Creates a prepared statement for later queries or executions.
     pub async fn prepare<Q: AsRef<str>>(&self, query: Q) -> Result<Stmt> {
-        let stmt_handle = self
-            .database
-            .start_prepare(&self.tx_handle, query.as_ref())?;
+        Stmt::prepare(
+            self.database.clone(),
+            self.conn.clone(),
+            false,
+            self.statement_timeout.clone(),
+            None,
+            query.as_ref(),
+        )
+        .await
+    }
 
-        cancelable_would_block(|cx| self.database.poll_prepare(cx, &stmt_handle)).await?;
+    /// Like [`prepare`](DbConn::prepare), but reuses the statement of an earlier call with the same `query`.
+    ///
+    /// A cached statement is already compiled, so it is returned immediately without polling the driver.
+    /// If the cached statement is still used by another [`Stmt`], or on the first call, the query is
+    /// prepared as usual and the new statement is cached if none is.
+    pub async fn prepare_cached<Q: AsRef<str>>(&self, query: Q) -> Result<Stmt> {
+        let query = query.as_ref();
 
-        Ok(Stmt {
-            stmt_handle,
-            database: self.database.clone(),
-        })
+        let cached = self
+            .lock_stmt_cache()
+            .get(query)
+            .filter(|stmt_handle| Arc::strong_count(stmt_handle) == 1)
+            .cloned();
+
+        if let Some(stmt_handle) = cached {
+            return Ok(Stmt {
+                stmt_handle,
+                conn_or_tx: self.conn.clone(),
+                in_tx: false,
+                query: query.to_owned(),
+                database: self.database.clone(),
+                statement_timeout: self.statement_timeout.clone(),
+                deadline: None,
+                emulated: false,
+            });
+        }
+
+        let stmt = self.prepare(query).await?;
+
+        // an emulated statement is prepared on each execution.
+        if stmt.emulated {
+            return Ok(stmt);
+        }
+
+        self.lock_stmt_cache()
+            .entry(query.to_owned())
+            .or_insert_with(|| stmt.stmt_handle.clone());
+
+        Ok(stmt)
     }
 
-    /// Manual commits the transaction.
-    pub async fn commit(&self) -> Result<()> {
-        cancelable_would_block(|cx| self.database.commit(cx, &self.tx_handle)).await
+    fn lock_stmt_cache(&self) -> MutexGuard<'_, HashMap<String, Arc<Handle>>> {
+        self.stmt_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// Manual rollback the transaction.
-    pub async fn rollback(&self) -> Result<()> {
-        cancelable_would_block(|cx| self.database.rollback(cx, &self.tx_handle)).await
+    /// Returns the optional features supported by the driver of this connection.
+    pub fn capabilities(&self) -> Capabilities {
+        self.database.capabilities()
     }
-}
 
-/// Represents a prepared statement.
-pub struct Stmt {
-    stmt_handle: Handle,
-    database: Arc<Box<dyn Database>>,
-}
+    /// Sets the timeout of every statement execution on this connection, `None` disables it.
+    ///
+    /// Statements and transactions created from this connection, before or after this call,
+    /// inherit the timeout unless it is overridden per call, see [`Stmt::exec_timeout`] and [`Stmt::query_timeout`].
+    ///
+    /// This function uses the global timer, see [`register_global_timer`](rasi::syscall::register_global_timer).
+    pub fn set_default_statement_timeout(&self, timeout: Option<Duration>) {
+        *self
+            .statement_timeout
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = timeout;
+    }
 
-#[negative_impl]
-impl !Sync for Stmt {}
+    /// Prepares `query` and executes it once with `values`, the statement is dropped afterwards.
+    ///
+    /// On success, returns the `last_insert_id` and `rows_affected`, see [`Stmt::exec`].
+    /// Use [`prepare`](DbConn::prepare) or [`exec_keep`](DbConn::exec_keep) to run a statement more than once.
+    pub async fn execute<Q: AsRef<str>>(
+        &self,
+        query: Q,
+        values: &[SqlValue<'_>],
+    ) -> Result<(i64, i64)> {
+        self.prepare(query).await?.exec(values).await
+    }
 
-impl Stmt {
-    /// executes a prepared query statement with the given arguments and returns the query results.
-    pub async fn query(&self, values: &[SqlValue<'_>]) -> Result<ResultSet> {
-        let result_set_handle = self.database.start_query(&self.stmt_handle, values)?;
+    /// Prepares `query` and runs it once with `values`, see [`Stmt::query`].
+    ///
+    /// The returned result set does not borrow the statement, which is released by the driver once the rows are no longer read.
+    pub async fn query_one_shot<Q: AsRef<str>>(
+        &self,
+        query: Q,
+        values: &[SqlValue<'_>],
+    ) -> Result<ResultSet> {
+        self.prepare(query).await?.query(values).await
+    }
 
-        Ok(ResultSet {
-            result_set_handle,
-            database: self.database.clone(),
+    /// Prepares `query`, executes it once with `values` and returns the result together with the statement,
+    /// which can be executed again without re-preparing.
+    ///
+    /// On success, the result is the `last_insert_id` and `rows_affected`, see [`Stmt::exec`].
+    pub async fn exec_keep<Q: AsRef<str>>(
+        &self,
+        query: Q,
+        values: &[SqlValue<'_>],
+    ) -> Result<((i64, i64), Stmt)> {
+        let stmt = self.prepare(query).await?;
+
+        let result = stmt.exec(values).await?;
+
+        Ok((result, stmt))
+    }
+
+    /// Starts a transaction and creates a prepared statement within it.
+    ///
+    /// Drivers may do both in one round trip.
+    pub async fn begin_with_stmt<Q: AsRef<str>>(&self, query: Q) -> Result<(Tx, Stmt)> {
+        let query = query.as_ref();
+
+        let begin_prepare = self.database.start_begin_prepare(&self.conn, query)?;
+
+        let (tx_handle, stmt_handle) = cancelable_would_block(|cx| {
+            self.database
+                .poll_begin_prepare(cx, &self.conn, &begin_prepare)
         })
+        .await?;
+
+        let tx_handle = Arc::new(tx_handle);
+
+        Ok((
+            Tx {
+                tx_handle: tx_handle.clone(),
+                database: self.database.clone(),
+                statement_timeout: self.statement_timeout.clone(),
+                level: 0,
+                depth: Default::default(),
+                deadline: None,
+                finished: AtomicBool::new(false),
+            },
+            Stmt {
+                stmt_handle: Arc::new(stmt_handle),
+                conn_or_tx: tx_handle,
+                in_tx: true,
+                query: query.to_owned(),
+                database: self.database.clone(),
+                statement_timeout: self.statement_timeout.clone(),
+                deadline: None,
+                emulated: false,
+            },
+        ))
     }
 
-    /// Executes a prepared statement with the given arguments.
+    /// Returns the row id of the most recent successful insert on this connection.
     ///
-    /// On success, returns the `last_insert_id` and `rows_affected`.
-    pub async fn exec(&self, values: &[SqlValue<'_>]) -> Result<(i64, i64)> {
-        let result_handle = self.database.start_exec(&self.stmt_handle, values)?;
+    /// The value belongs to the connection, not to a statement:
+    /// any later insert on the same connection, including one made inside a transaction, changes it.
+    pub async fn last_insert_id(&self) -> Result<i64> {
+        cancelable_would_block(|cx| self.database.poll_last_insert_id(cx, &self.conn)).await
+    }
 
-        cancelable_would_block(|cx| self.database.poll_exec(cx, &result_handle)).await
+    /// Returns the number of rows inserted, updated or deleted on this connection since it was opened,
+    /// a cumulative count unlike the `rows_affected` of a single [`Stmt::exec`].
+    ///
+    /// Comparing two values tells whether anything changed in between, e.g. to invalidate a cache.
+    pub async fn total_changes(&self) -> Result<i64> {
+        cancelable_would_block(|cx| self.database.poll_total_changes(cx, &self.conn)).await
     }
-}
 
-/// Represents a query result set.
-pub struct ResultSet {
-    result_set_handle: Handle,
-    database: Arc<Box<dyn Database>>,
-}
+    /// Sets a value for the lifetime of this connection, e.g. the current user for the triggers of an audit log,
+    /// see [`Database::set_session_value`] for how the sql reads it.
+    pub fn set_session_value<V: Into<SqlValue<'static>>>(&self, key: &str, value: V) -> Result<()> {
+        self.database
+            .set_session_value(&self.conn, key, value.into())
+    }
 
-#[negative_impl]
-impl !Send for ResultSet {}
+    /// Returns the value set by [`set_session_value`](DbConn::set_session_value), or `None` if `key` is not set.
+    pub fn get_session_value(&self, key: &str) -> Result<Option<SqlValue<'static>>> {
+        self.database.session_value(&self.conn, key)
+    }
 
-#[negative_impl]
-impl !Sync for ResultSet {}
+    /// Returns a stream of the rows inserted, updated or deleted in `table` through this connection,
+    /// see [`Database::start_watch`].
+    ///
+    /// Changes made by other connections are not reported. The stream ends if the driver fails to
+    /// poll the next change, and the subscription is removed when the stream is dropped.
+    pub fn watch_table(&self, table: &str) -> Result<impl Stream<Item = ChangeEvent>> {
+        let database = self.database.clone();
+        let watch = database.start_watch(&self.conn, table)?;
 
-impl ResultSet {
-    /// Returns the column names
-    pub async fn columns(&self) -> Result<Vec<String>> {
-        cancelable_would_block(|cx| self.database.poll_cols(cx, &self.result_set_handle)).await
+        Ok(stream::poll_fn(move |cx| {
+            match database.poll_change(cx, &watch) {
+                CancelablePoll::Ready(Ok(event)) => Poll::Ready(Some(event)),
+                CancelablePoll::Ready(Err(_)) => Poll::Ready(None),
+                CancelablePoll::Pending(_) => Poll::Pending,
+            }
+        }))
     }
 
-    /// Returns column information such as column type, length, and nullable
-    pub async fn column_types(&self) -> Result<Vec<ColumnType<'static>>> {
-        cancelable_would_block(|cx| self.database.poll_col_types(cx, &self.result_set_handle)).await
+    /// Returns the most recent error reported by the driver for this connection, if any.
+    pub fn last_error(&self) -> Option<io::Error> {
+        self.database.last_error(&self.conn)
     }
 
-    /// prepares the next result row for reading
-    pub async fn next(&self) -> Result<bool> {
-        cancelable_would_block(|cx| self.database.poll_next(cx, &self.result_set_handle)).await
+    /// Starts a transaction with the [default](IsolationLevel::default) isolation level.
+    pub async fn begin(&self) -> Result<Tx> {
+        self.begin_with(IsolationLevel::default()).await
     }
 
-    /// Get column value by col number.
-    pub async fn get(&self, col: usize) -> Result<SqlValue<'static>> {
-        cancelable_would_block(|cx| self.database.poll_value(cx, &self.result_set_handle, col))
+    /// Starts a transaction with isolation `level`.
+    ///
+    /// Returns [`Unsupported`](io::ErrorKind::Unsupported) error if the driver can not honor `level`.
+    pub async fn begin_with(&self, level: IsolationLevel) -> Result<Tx> {
+        cancelable_would_block(|cx| self.database.begin_with(cx, &self.conn, level))
             .await
+            .map(|tx_handle| Tx {
+                tx_handle: Arc::new(tx_handle),
+                database: self.database.clone(),
+                statement_timeout: self.statement_timeout.clone(),
+                level: 0,
+                depth: Default::default(),
+                deadline: None,
+                finished: AtomicBool::new(false),
+            })
     }
 
-    /// Get col value by col name or col alias.
-    pub async fn get_by_col_name<C: AsRef<str>>(
-        &self,
-        col_name: C,
-        col_types: &[ColumnType<'_>],
-    ) -> Result<SqlValue<'static>> {
-        let col_name = col_name.as_ref();
+    /// Starts a transaction with `options`, e.g. `conn.begin_with_options(TxOptions::default().deadline(duration))`.
+    pub async fn begin_with_options(&self, options: TxOptions) -> Result<Tx> {
+        let mut tx = self.begin_with(options.level).await?;
 
-        let offset = col_types
-            .iter()
-            .enumerate()
-            .find_map(|(offset, col)| {
-                if col.name == col_name {
-                    Some(offset)
-                } else {
-                    None
-                }
+        tx.deadline = options.deadline.map(|deadline| {
+            Arc::new(TxDeadline {
+                at: Instant::now() + deadline,
+                tx_handle: tx.tx_handle.clone(),
+                database: self.database.clone(),
+                expired: AtomicBool::new(false),
             })
-            .ok_or(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Unknown column name or alias: {}", col_name),
-            ))?;
+        });
 
-        self.get(offset).await
+        Ok(tx)
     }
 }
 
-#[derive(Default)]
-struct GlobalRegister {
-    drivers: RwLock<HashMap<String, Arc<Box<dyn Database>>>>,
+/// Tx is an in-progress database transaction.
+pub struct Tx {
+    tx_handle: Arc<Handle>,
+    database: Arc<Box<dyn Database>>,
+    statement_timeout: StatementTimeout,
+    /// The position of the savepoint of this `Tx` in the savepoint stack, `0` for the top transaction.
+    level: usize,
+    /// The number of active savepoints, shared by the top transaction and its savepoints.
+    depth: Arc<AtomicUsize>,
+    /// The deadline of [`TxOptions::deadline`], shared by the top transaction and its savepoints.
+    deadline: Option<Arc<TxDeadline>>,
+    /// True after a successful [`commit`](Tx::commit) or [`rollback`](Tx::rollback), see the `Drop` impl.
+    finished: AtomicBool,
 }
 
-static REGISTER: OnceLock<GlobalRegister> = OnceLock::new();
+impl Tx {
+    /// Creates a prepared statement for later queries or executions.
+    pub async fn prepare<Q: AsRef<str>>(&self, query: Q) -> Result<Stmt> {
+        self.check_deadline().await?;
 
-fn get_register() -> &'static GlobalRegister {
-    REGISTER.get_or_init(|| Default::default())
-}
+        Stmt::prepare(
+            self.database.clone(),
+            self.tx_handle.clone(),
+            true,
+            self.statement_timeout.clone(),
+            self.deadline.clone(),
+            query.as_ref(),
+        )
+        .await
+    }
 
-/// Open opens a database specified by its database driver name and a driver-specific data source name, usually consisting of at least a database name and connection information.
-pub async fn open<D: AsRef<str>, S: AsRef<str>>(driver_name: D, source_name: S) -> Result<DbConn> {
-    let drivers = get_register()
-        .drivers
-        .read()
-        .map_err(|err| io::Error::new(io::ErrorKind::Interrupted, err.to_string()))?;
+    /// Like [`DbConn::execute`], but runs the statement in this transaction.
+    pub async fn execute<Q: AsRef<str>>(
+        &self,
+        query: Q,
+        values: &[SqlValue<'_>],
+    ) -> Result<(i64, i64)> {
+        self.prepare(query).await?.exec(values).await
+    }
 
-    if let Some(database) = drivers.get(driver_name.as_ref()) {
-        let conn = database.start_connect(source_name.as_ref())?;
+    /// Like [`DbConn::query_one_shot`], but runs the statement in this transaction.
+    pub async fn query_one_shot<Q: AsRef<str>>(
+        &self,
+        query: Q,
+        values: &[SqlValue<'_>],
+    ) -> Result<ResultSet> {
+        self.prepare(query).await?.query(values).await
+    }
 
-        cancelable_would_block(|cx| database.poll_connect(cx, &conn)).await?;
+    /// Starts a nested transaction at a savepoint of this transaction.
+    ///
+    /// Committing the returned `Tx` releases the savepoint, the changes become part of this transaction
+    /// and are still discarded if this transaction rolls back. Rolling it back undoes only the changes made since the savepoint.
+    pub async fn savepoint(&self) -> Result<Tx> {
+        self.check_deadline().await?;
 
-        return Ok(DbConn {
-            conn,
-            database: database.clone(),
-        });
-    } else {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Unknown driver: {}", driver_name.as_ref()),
-        ));
+        let tx_handle =
+            cancelable_would_block(|cx| self.database.savepoint(cx, &self.tx_handle)).await?;
+
+        // the savepoint is pushed on top of all active savepoints, also when they are nested deeper than this one.
+        let level = self.depth.fetch_add(1, Ordering::SeqCst) + 1;
+
+        Ok(Tx {
+            tx_handle: Arc::new(tx_handle),
+            database: self.database.clone(),
+            statement_timeout: self.statement_timeout.clone(),
+            level,
+            depth: self.depth.clone(),
+            deadline: self.deadline.clone(),
+            finished: AtomicBool::new(false),
+        })
     }
-}
 
-/// Register new database driver.
-///
-/// Cause a panic, if register same driver name twice.
-pub fn register<N: AsRef<str>, D: Database + 'static>(driver_name: N, database: D) -> Result<()> {
-    let mut drivers = get_register()
-        .drivers
-        .write()
-        .map_err(|err| io::Error::new(io::ErrorKind::Interrupted, err.to_string()))?;
+    /// Returns the number of active savepoints in the transaction, `0` if there is none.
+    ///
+    /// The value is shared by the top transaction and all of its savepoints: [`savepoint`](Tx::savepoint) increments it,
+    /// committing or rolling back a savepoint drops it to the level below that savepoint.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::SeqCst)
+    }
 
-    assert!(
-        drivers
-            .insert(
-                driver_name.as_ref().to_owned(),
-                Arc::new(Box::new(database))
-            )
-            .is_none(),
-        "register driver twice: {}",
-        driver_name.as_ref(),
-    );
+    /// Pins the read snapshot of this transaction, all later reads in it see the database as of this call.
+    ///
+    /// A transaction may otherwise take its snapshot on the first read only, e.g. a sqlite `BEGIN DEFERRED`
+    /// transaction in WAL mode sees the commits made by other connections between `begin` and its first read.
+    pub async fn snapshot(&self) -> Result<()> {
+        cancelable_would_block(|cx| self.database.poll_snapshot(cx, &self.tx_handle)).await
+    }
+
+    /// Returns true if an error has already rolled back this transaction,
+    /// later statements then run outside of it and the caller should roll back rather than continue.
+    ///
+    /// Drivers that can not tell always return `false`, see [`Database::is_tx_aborted`].
+    pub fn is_aborted(&self) -> bool {
+        self.database.is_tx_aborted(&self.tx_handle)
+    }
+
+    /// Manual commits the transaction.
+    pub async fn commit(&self) -> Result<()> {
+        self.check_deadline().await?;
+
+        if let Err(err) = timeout_or(
+            cancelable_would_block(|cx| self.database.commit(cx, &self.tx_handle)),
+            TxDeadline::bound(&self.deadline, None),
+            "Commit transaction",
+        )
+        .await
+        {
+            // a commit still waiting at the deadline rolls back the transaction.
+            self.check_deadline().await?;
+
+            return Err(err);
+        }
+
+        self.end_level();
+
+        Ok(())
+    }
+
+    /// Manual rollback the transaction.
+    ///
+    /// Returns `Ok` without calling the driver if the [`TxOptions::deadline`] has already rolled back the transaction.
+    pub async fn rollback(&self) -> Result<()> {
+        let expired = self
+            .deadline
+            .as_ref()
+            .is_some_and(|deadline| deadline.expired.load(Ordering::SeqCst));
+
+        if !expired {
+            cancelable_would_block(|cx| self.database.rollback(cx, &self.tx_handle)).await?;
+        }
+
+        self.end_level();
 
-    todo!()
+        Ok(())
+    }
+
+    /// Returns error if the [`TxOptions::deadline`] of the transaction has passed.
+    async fn check_deadline(&self) -> Result<()> {
+        match &self.deadline {
+            Some(deadline) => deadline.check().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Update [`depth`](Tx::depth) after this transaction or savepoint ends, which also ends the savepoints nested in it.
+    ///
+    /// The depth never grows here, a savepoint already ended by an outer one leaves it unchanged.
+    fn end_level(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+
+        self.depth
+            .fetch_min(self.level.saturating_sub(1), Ordering::SeqCst);
+    }
+
+    /// Commits the transaction, or returns [`TimedOut`](io::ErrorKind::TimedOut) error after `duration`.
+    ///
+    /// On timeout the pending commit is cancelled and the transaction stays open,
+    /// so the caller can retry the commit or roll back.
+    ///
+    /// This function uses the global timer, see [`register_global_timer`](rasi::syscall::register_global_timer).
+    pub async fn commit_timeout(&self, duration: Duration) -> Result<()> {
+        self.commit()
+            .timeout(duration)
+            .await
+            .unwrap_or(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Commit transaction timeout",
+            )))
+    }
+
+    /// Rollback the transaction, or returns [`TimedOut`](io::ErrorKind::TimedOut) error after `duration`.
+    ///
+    /// On timeout the pending rollback is cancelled and the transaction stays open,
+    /// so the caller can retry.
+    ///
+    /// This function uses the global timer, see [`register_global_timer`](rasi::syscall::register_global_timer).
+    pub async fn rollback_timeout(&self, duration: Duration) -> Result<()> {
+        self.rollback()
+            .timeout(duration)
+            .await
+            .unwrap_or(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Rollback transaction timeout",
+            )))
+    }
+}
+
+/// Rolls back a transaction or savepoint that was neither committed nor rolled back,
+/// e.g. when a panic unwinds through the code using it, so the connection can start the next transaction.
+///
+/// The rollback is polled once, a driver whose rollback has to wait leaves the transaction open.
+/// Errors are ignored, e.g. for a savepoint whose transaction has already ended.
+impl Drop for Tx {
+    fn drop(&mut self) {
+        if self.finished.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let expired = self
+            .deadline
+            .as_ref()
+            .is_some_and(|deadline| deadline.expired.load(Ordering::SeqCst));
+
+        if !expired {
+            let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+
+            _ = self.database.rollback(&mut cx, &self.tx_handle);
+        }
+
+        self.end_level();
+    }
+}
+
+/// Represents a prepared statement.
+pub struct Stmt {
+    /// Shared with the [`DbConn::prepare_cached`] cache of the connection.
+    stmt_handle: Arc<Handle>,
+    /// The connection or transaction that this statement was prepared on.
+    conn_or_tx: Arc<Handle>,
+    /// True if `conn_or_tx` is a transaction.
+    in_tx: bool,
+    query: String,
+    database: Arc<Box<dyn Database>>,
+    statement_timeout: StatementTimeout,
+    /// The deadline of the transaction that this statement was prepared in, see [`TxOptions::deadline`].
+    deadline: Option<Arc<TxDeadline>>,
+    /// True if the driver does not prepare statements, see [`Capabilities::server_side_prepare`].
+    emulated: bool,
+}
+
+#[negative_impl]
+impl !Sync for Stmt {}
+
+impl Stmt {
+    async fn prepare(
+        database: Arc<Box<dyn Database>>,
+        conn_or_tx: Arc<Handle>,
+        in_tx: bool,
+        statement_timeout: StatementTimeout,
+        deadline: Option<Arc<TxDeadline>>,
+        query: &str,
+    ) -> Result<Self> {
+        let emulated = !database.capabilities().server_side_prepare;
+
+        let stmt_handle = if emulated {
+            Handle::new(EmulatedStmt)
+        } else {
+            Self::prepare_handle(&database, &conn_or_tx, query).await?
+        };
+
+        Ok(Stmt {
+            stmt_handle: Arc::new(stmt_handle),
+            conn_or_tx,
+            in_tx,
+            query: query.to_owned(),
+            database,
+            statement_timeout,
+            deadline,
+            emulated,
+        })
+    }
+
+    async fn prepare_handle(
+        database: &Arc<Box<dyn Database>>,
+        conn_or_tx: &Handle,
+        query: &str,
+    ) -> Result<Handle> {
+        let stmt_handle = database.start_prepare(conn_or_tx, query)?;
+
+        cancelable_would_block(|cx| database.poll_prepare(cx, &stmt_handle)).await?;
+
+        Ok(stmt_handle)
+    }
+
+    /// Returns the statement object of the driver,
+    /// [`Unsupported`](io::ErrorKind::Unsupported) error if the statement is emulated.
+    fn stmt_handle(&self) -> Result<&Handle> {
+        if self.emulated {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the driver does not prepare statements, see Capabilities::server_side_prepare",
+            ));
+        }
+
+        Ok(&self.stmt_handle)
+    }
+
+    /// Prepares the query of an emulated statement with the literals of `values`, see [`emulate_prepared`].
+    async fn prepare_emulated(&self, values: &[SqlValue<'_>]) -> Result<Handle> {
+        let query = emulate_prepared(&**self.database, &self.query, values)?;
+
+        Self::prepare_handle(&self.database, &self.conn_or_tx, &query).await
+    }
+
+    /// Returns the default statement timeout of the connection, see [`DbConn::set_default_statement_timeout`],
+    /// shortened to the [`TxOptions::deadline`] of the transaction.
+    fn default_timeout(&self) -> Option<Duration> {
+        let timeout = *self
+            .statement_timeout
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        TxDeadline::bound(&self.deadline, timeout)
+    }
+
+    /// Returns error if the [`TxOptions::deadline`] of the transaction has passed.
+    async fn check_deadline(&self) -> Result<()> {
+        match &self.deadline {
+            Some(deadline) => deadline.check().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Creates an independent statement for the same query on the same connection or transaction.
+    ///
+    /// The query is prepared again rather than sharing the driver's statement object,
+    /// so the returned `Stmt` can run concurrently with `self`.
+    pub async fn try_clone(&self) -> Result<Stmt> {
+        Stmt::prepare(
+            self.database.clone(),
+            self.conn_or_tx.clone(),
+            self.in_tx,
+            self.statement_timeout.clone(),
+            self.deadline.clone(),
+            &self.query,
+        )
+        .await
+    }
+
+    /// Returns the number of parameters of this statement, see [`Database::parameter_count`].
+    pub fn parameter_count(&self) -> Result<usize> {
+        self.database.parameter_count(self.stmt_handle()?)
+    }
+
+    /// Returns the name of the parameter whose value is at `index` of the values, see [`Database::parameter_name`].
+    pub fn parameter_name(&self, index: usize) -> Result<Option<String>> {
+        self.database.parameter_name(self.stmt_handle()?, index)
+    }
+
+    /// Returns the result columns of this statement without executing it, see [`Database::stmt_col_types`].
+    pub fn column_types(&self) -> Result<Vec<ColumnType<'static>>> {
+        self.database.stmt_col_types(self.stmt_handle()?)
+    }
+
+    /// Returns the counters of each loop of the query plan, collected by the executions of this statement,
+    /// see [`Database::scan_status`].
+    pub fn scan_status(&self) -> Result<Vec<ScanStatus>> {
+        self.database.scan_status(self.stmt_handle()?)
+    }
+
+    /// Returns true if this statement does not write to the database, see [`Database::is_readonly`].
+    pub fn is_readonly(&self) -> Result<bool> {
+        self.database.is_readonly(self.stmt_handle()?)
+    }
+
+    /// Returns the parameters, result columns and readonly-ness of this statement,
+    /// collected from [`parameter_count`](Stmt::parameter_count), [`parameter_name`](Stmt::parameter_name),
+    /// [`column_types`](Stmt::column_types) and [`is_readonly`](Stmt::is_readonly).
+    ///
+    /// Returns the first error of these, e.g. [`Unsupported`](io::ErrorKind::Unsupported) if the driver
+    /// does not implement one of them.
+    pub fn describe(&self) -> Result<StatementInfo> {
+        let parameter_count = self.parameter_count()?;
+
+        let parameter_names = (0..parameter_count)
+            .map(|index| self.parameter_name(index))
+            .collect::<Result<Vec<_>>>()?;
+
+        let column_types = self.column_types()?;
+
+        Ok(StatementInfo {
+            parameter_count,
+            parameter_names,
+            column_count: column_types.len(),
+            column_names: column_types
+                .iter()
+                .map(|col_type| col_type.name.to_string())
+                .collect(),
+            column_types,
+            is_readonly: self.is_readonly()?,
+        })
+    }
+
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if the number of `values` does not match
+    /// the [`parameter_count`](Database::parameter_count) of this statement.
+    fn check_parameter_count(&self, values: &[SqlValue<'_>]) -> Result<()> {
+        match self.database.parameter_count(&self.stmt_handle) {
+            Ok(count) if count != values.len() => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("expected {} parameters, got {}", count, values.len()),
+            )),
+            Err(err) if err.kind() != io::ErrorKind::Unsupported => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// executes a prepared query statement with the given arguments and returns the query results.
+    ///
+    /// The default statement timeout of the connection applies, see [`query_timeout`](Stmt::query_timeout).
+    pub async fn query(&self, values: &[SqlValue<'_>]) -> Result<ResultSet> {
+        self.query_timeout(values, self.default_timeout()).await
+    }
+
+    /// Like [`query`](Stmt::query), but with `timeout` instead of the default statement timeout, `None` disables it.
+    ///
+    /// Drivers fetch rows while advancing the cursor,
+    /// so the timeout applies to each [`next`](ResultSet::next) call of the returned result set.
+    pub async fn query_timeout(
+        &self,
+        values: &[SqlValue<'_>],
+        timeout: Option<Duration>,
+    ) -> Result<ResultSet> {
+        self.check_deadline().await?;
+
+        let timeout = TxDeadline::bound(&self.deadline, timeout);
+
+        let result_set_handle = if self.emulated {
+            self.database
+                .start_query(&self.prepare_emulated(values).await?, &[])?
+        } else {
+            self.check_parameter_count(values)?;
+            self.database.start_query(&self.stmt_handle, values)?
+        };
+
+        Ok(ResultSet {
+            result_set_handle,
+            database: self.database.clone(),
+            column_aliases: HashMap::new(),
+            column_index: RefCell::new(None),
+            timeout,
+        })
+    }
+
+    /// Like [`query`](Stmt::query), but binds each value of `params` to the parameter with the same name,
+    /// e.g. `(":id", 1.into())` for `WHERE id = :id`, see the driver for the supported placeholders.
+    pub async fn query_named(&self, params: &[(&str, SqlValue<'_>)]) -> Result<ResultSet> {
+        self.check_deadline().await?;
+
+        let result_set_handle = self
+            .database
+            .start_query_named(self.stmt_handle()?, params)?;
+
+        Ok(ResultSet {
+            result_set_handle,
+            database: self.database.clone(),
+            column_aliases: HashMap::new(),
+            column_index: RefCell::new(None),
+            timeout: self.default_timeout(),
+        })
+    }
+
+    /// Like [`exec`](Stmt::exec), but binds each value of `params` to the parameter with the same name.
+    pub async fn exec_named(&self, params: &[(&str, SqlValue<'_>)]) -> Result<(i64, i64)> {
+        self.check_deadline().await?;
+
+        let result_handle = self
+            .database
+            .start_exec_named(self.stmt_handle()?, params)?;
+
+        timeout_or(
+            cancelable_would_block(|cx| self.database.poll_exec(cx, &result_handle)),
+            self.default_timeout(),
+            "Execute statement",
+        )
+        .await
+    }
+
+    /// Executes a prepared statement with the given arguments.
+    ///
+    /// On success, returns the `last_insert_id` and `rows_affected`.
+    /// The default statement timeout of the connection applies, see [`exec_timeout`](Stmt::exec_timeout).
+    pub async fn exec(&self, values: &[SqlValue<'_>]) -> Result<(i64, i64)> {
+        self.exec_timeout(values, self.default_timeout()).await
+    }
+
+    /// Like [`exec`](Stmt::exec), but with `timeout` instead of the default statement timeout, `None` disables it.
+    ///
+    /// Returns [`TimedOut`](io::ErrorKind::TimedOut) error if the execution does not complete in time.
+    pub async fn exec_timeout(
+        &self,
+        values: &[SqlValue<'_>],
+        timeout: Option<Duration>,
+    ) -> Result<(i64, i64)> {
+        self.check_deadline().await?;
+
+        let result_handle = if self.emulated {
+            self.database
+                .start_exec(&self.prepare_emulated(values).await?, &[])?
+        } else {
+            self.check_parameter_count(values)?;
+            self.database.start_exec(&self.stmt_handle, values)?
+        };
+
+        timeout_or(
+            cancelable_would_block(|cx| self.database.poll_exec(cx, &result_handle)),
+            TxDeadline::bound(&self.deadline, timeout),
+            "Execute statement",
+        )
+        .await
+    }
+
+    /// Executes this statement once for each parameter set in `rows`.
+    ///
+    /// On success, returns the `last_insert_id` of the last parameter set and the total `rows_affected`.
+    ///
+    /// The batch is applied as a whole: it runs in its own transaction, or at a savepoint if this statement
+    /// was prepared in a [`Tx`], which is rolled back if any parameter set fails.
+    /// If the driver does not support savepoints, a failed batch in a `Tx` leaves the transaction to the caller.
+    pub async fn exec_batch(&self, rows: &[Vec<SqlValue<'_>>]) -> Result<(i64, i64)> {
+        self.check_deadline().await?;
+
+        let tx_handle = if self.in_tx {
+            match cancelable_would_block(|cx| self.database.savepoint(cx, &self.conn_or_tx)).await {
+                Ok(savepoint) => Some(savepoint),
+                Err(err) if err.kind() == io::ErrorKind::Unsupported => None,
+                Err(err) => return Err(err),
+            }
+        } else {
+            Some(cancelable_would_block(|cx| self.database.begin(cx, &self.conn_or_tx)).await?)
+        };
+
+        let result = self.exec_batch_params(rows).await;
+
+        let Some(tx_handle) = tx_handle else {
+            return result;
+        };
+
+        let result = match result {
+            Ok(result) => cancelable_would_block(|cx| self.database.commit(cx, &tx_handle))
+                .await
+                .map(|()| result),
+            Err(err) => Err(err),
+        };
+
+        // a failed commit may leave the transaction open, which is rolled back as after a failed row.
+        match result {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                match cancelable_would_block(|cx| self.database.rollback(cx, &tx_handle)).await {
+                    Ok(()) => Err(err),
+                    Err(rollback_err) => Err(io::Error::new(
+                        err.kind(),
+                        format!("{}, rollback failed: {}", err, rollback_err),
+                    )),
+                }
+            }
+        }
+    }
+
+    async fn exec_batch_params(&self, rows: &[Vec<SqlValue<'_>>]) -> Result<(i64, i64)> {
+        if self.emulated {
+            let mut result = (0, 0);
+
+            for row in rows {
+                let (last_insert_id, rows_affected) = self.exec(row).await?;
+
+                result = (last_insert_id, result.1 + rows_affected);
+            }
+
+            return Ok(result);
+        }
+
+        let stmt_handle = self.stmt_handle()?;
+
+        let batch_handle = self.database.start_exec_batch_params(stmt_handle, rows)?;
+
+        timeout_or(
+            cancelable_would_block(|cx| {
+                self.database
+                    .poll_exec_batch_params(cx, stmt_handle, &batch_handle)
+            }),
+            self.default_timeout(),
+            "Execute batch",
+        )
+        .await
+    }
+}
+
+/// Represents a query result set.
+pub struct ResultSet {
+    result_set_handle: Handle,
+    database: Arc<Box<dyn Database>>,
+    /// Column name to presented name, see [`with_column_aliases`](ResultSet::with_column_aliases).
+    column_aliases: HashMap<String, String>,
+    /// The column names indexed by name, fetched by the first [`get_by_name`](ResultSet::get_by_name)
+    /// or [`next_row`](ResultSet::next_row).
+    column_index: RefCell<Option<Arc<Columns>>>,
+    /// The timeout of each [`next`](ResultSet::next) call, see [`Stmt::query_timeout`].
+    timeout: Option<Duration>,
+}
+
+#[negative_impl]
+impl !Send for ResultSet {}
+
+#[negative_impl]
+impl !Sync for ResultSet {}
+
+impl ResultSet {
+    /// Presents columns under different names without re-querying.
+    ///
+    /// Columns named by a key of `mapping` are renamed to its value in
+    /// [`columns`](ResultSet::columns), [`column_types`](ResultSet::column_types)
+    /// and everything built on them, e.g. [`get_by_col_name`](ResultSet::get_by_col_name).
+    /// Positional access is unchanged.
+    pub fn with_column_aliases(mut self, mapping: &HashMap<String, String>) -> Self {
+        self.column_aliases.extend(mapping.clone());
+        self.column_index = RefCell::new(None);
+        self
+    }
+
+    fn column_alias(&self, name: String) -> String {
+        self.column_aliases.get(&name).cloned().unwrap_or(name)
+    }
+
+    /// Returns the column names
+    pub async fn columns(&self) -> Result<Vec<String>> {
+        let columns =
+            cancelable_would_block(|cx| self.database.poll_cols(cx, &self.result_set_handle))
+                .await?;
+
+        Ok(columns
+            .into_iter()
+            .map(|name| self.column_alias(name))
+            .collect())
+    }
+
+    /// Returns column information such as column type, length, and nullable
+    pub async fn column_types(&self) -> Result<Vec<ColumnType<'static>>> {
+        let mut col_types =
+            cancelable_would_block(|cx| self.database.poll_col_types(cx, &self.result_set_handle))
+                .await?;
+
+        for col_type in col_types.iter_mut() {
+            if let Some(alias) = self.column_aliases.get(col_type.name.as_ref()) {
+                col_type.name = Cow::Owned(alias.clone());
+            }
+        }
+
+        Ok(col_types)
+    }
+
+    /// Returns the `(table, column)` that the column at `col` is read from,
+    /// or `None` if it is an expression or the driver does not know the origin.
+    ///
+    /// Unlike [`columns`](ResultSet::columns), the origin is not changed by a column alias,
+    /// e.g. `SELECT u.name AS author FROM users u` returns `("users", "name")`.
+    pub fn column_origin(&self, col: usize) -> Result<Option<(String, String)>> {
+        self.database.column_origin(&self.result_set_handle, col)
+    }
+
+    /// prepares the next result row for reading
+    pub async fn next(&self) -> Result<bool> {
+        timeout_or(
+            cancelable_would_block(|cx| self.database.poll_next(cx, &self.result_set_handle)),
+            self.timeout,
+            "Fetch next row",
+        )
+        .await
+    }
+
+    /// Advances the cursor by `n` rows without reading their values.
+    ///
+    /// Returns the number of rows actually skipped, which is less than `n` if the result set is exhausted.
+    pub async fn skip(&self, n: usize) -> Result<usize> {
+        for skipped in 0..n {
+            if !self.next().await? {
+                return Ok(skipped);
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Returns the row id of the current row, see [`Database::poll_rowid`].
+    ///
+    /// The query must select from a single table, which is the only table whose row is identified,
+    /// drivers return an error for other queries, e.g. a join.
+    pub async fn rowid(&self) -> Result<i64> {
+        cancelable_would_block(|cx| self.database.poll_rowid(cx, &self.result_set_handle)).await
+    }
+
+    /// Get column value by col number.
+    pub async fn get(&self, col: usize) -> Result<SqlValue<'static>> {
+        cancelable_would_block(|cx| self.database.poll_value(cx, &self.result_set_handle, col))
+            .await
+    }
+
+    /// Get column value by col number, returns `Ok(None)` if the value is SQL `NULL`.
+    ///
+    /// Unlike [`get`](ResultSet::get), this tells a `NULL` value apart from a missing column,
+    /// which is still reported as an error by the driver.
+    pub async fn get_nullable(&self, col: usize) -> Result<Option<SqlValue<'static>>> {
+        match self.get(col).await? {
+            SqlValue::Null => Ok(None),
+            value => Ok(Some(value)),
+        }
+    }
+
+    /// Get col value by col name or col alias.
+    pub async fn get_by_col_name<C: AsRef<str>>(
+        &self,
+        col_name: C,
+        col_types: &[ColumnType<'_>],
+    ) -> Result<SqlValue<'static>> {
+        let col_name = col_name.as_ref();
+
+        let offset = col_types
+            .iter()
+            .enumerate()
+            .find_map(|(offset, col)| {
+                if col.name == col_name {
+                    Some(offset)
+                } else {
+                    None
+                }
+            })
+            .ok_or(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Unknown column name or alias: {}", col_name),
+            ))?;
+
+        self.get(offset).await
+    }
+
+    /// Get col value by col name or col alias.
+    ///
+    /// Unlike [`get_by_col_name`](ResultSet::get_by_col_name), the column names are fetched from the driver
+    /// on the first call and kept in a map, so each later lookup is a hash map access.
+    /// If several columns have the same name, the first one is returned.
+    pub async fn get_by_name<C: AsRef<str>>(&self, col_name: C) -> Result<SqlValue<'static>> {
+        let offset = self.indexed_columns().await?.offset(col_name.as_ref())?;
+
+        self.get(offset).await
+    }
+
+    /// Returns the cached column names and types, fetching them from the driver on the first call.
+    async fn indexed_columns(&self) -> Result<Arc<Columns>> {
+        if let Some(columns) = self.column_index.borrow().as_ref() {
+            return Ok(columns.clone());
+        }
+
+        let columns = Arc::new(Columns::new(self.column_types().await?));
+
+        *self.column_index.borrow_mut() = Some(columns.clone());
+
+        Ok(columns)
+    }
+
+    /// Get column value by col number and convert it into `T`, e.g. `let id: i64 = rs.get_as(0).await?;`.
+    ///
+    /// Returns [`InvalidData`](io::ErrorKind::InvalidData) error if the value can not be converted,
+    /// use `Option<T>` to read a nullable column.
+    pub async fn get_as<T: TryFrom<SqlValue<'static>, Error = io::Error>>(
+        &self,
+        col: usize,
+    ) -> Result<T> {
+        T::try_from(self.get(col).await?)
+    }
+
+    /// Get col value by col name or col alias and convert it into `T`, see [`get_as`](ResultSet::get_as).
+    pub async fn get_by_name_as<T: TryFrom<SqlValue<'static>, Error = io::Error>, C: AsRef<str>>(
+        &self,
+        col_name: C,
+        col_types: &[ColumnType<'_>],
+    ) -> Result<T> {
+        T::try_from(self.get_by_col_name(col_name, col_types).await?)
+    }
+}
+
+/// The list placeholder recognized by [`expand_in_clause`].
+pub const IN_LIST_PLACEHOLDER: &str = "...?";
+
+/// Rewrites the list placeholder `...?` in `query` into one `?` placeholder per element of `list`,
+/// and returns the rewritten query with the values to bind.
+///
+/// This makes `WHERE id IN (...?)` usable with drivers that can't bind an array to one parameter.
+/// `params` are the values of the other `?` placeholders in order, the elements of `list` are placed
+/// between the values before and after the list placeholder. The returned values borrow the buffers of the inputs.
+///
+/// ```
+/// use rinq_rdbc::{expand_in_clause, SqlValue};
+///
+/// let (query, values) = expand_in_clause(
+///     "SELECT * FROM t WHERE a = ? AND id IN (...?) LIMIT ?",
+///     &[SqlValue::Int(0), SqlValue::Int(10)],
+///     &[SqlValue::Int(1), SqlValue::Int(2)],
+///     100,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(query, "SELECT * FROM t WHERE a = ? AND id IN (?, ?) LIMIT ?");
+/// assert_eq!(values, [0, 1, 2, 10].map(SqlValue::Int));
+/// ```
+///
+/// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error, if `query` does not contain exactly one list placeholder,
+/// `list` is empty, the number of `params` does not match the other placeholders,
+/// or the rewritten query has more than `max_params` placeholders.
+/// Note that placeholders are counted by a plain scan of `?` characters, including any inside string literals.
+pub fn expand_in_clause<'a>(
+    query: &str,
+    params: &'a [SqlValue<'_>],
+    list: &'a [SqlValue<'_>],
+    max_params: usize,
+) -> Result<(String, Vec<SqlValue<'a>>)> {
+    let mut parts = query.split(IN_LIST_PLACEHOLDER);
+
+    let (head, tail) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(head), Some(tail), None) => (head, tail),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Expect exactly one `{}` in query: {}",
+                    IN_LIST_PLACEHOLDER, query
+                ),
+            ))
+        }
+    };
+
+    if list.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Can't expand list placeholder with an empty list",
+        ));
+    }
+
+    let before = head.matches('?').count();
+    let others = before + tail.matches('?').count();
+
+    if params.len() != others {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected {} parameters, got {}", others, params.len()),
+        ));
+    }
+
+    let total = others + list.len();
+
+    if total > max_params {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Too many parameters: {}, limit is {}", total, max_params),
+        ));
+    }
+
+    let values = params[..before]
+        .iter()
+        .chain(list)
+        .chain(&params[before..])
+        .map(SqlValue::as_ref)
+        .collect();
+
+    Ok((
+        format!("{}{}{}", head, vec!["?"; list.len()].join(", "), tail),
+        values,
+    ))
+}
+
+/// Returns true if the operation failed by a transient condition and may succeed when retried.
+///
+/// Drivers report a busy or locked database with [`io::ErrorKind::WouldBlock`], constraint
+/// violations and syntax errors are never retryable.
+pub fn is_retryable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
+
+#[derive(Default)]
+struct GlobalRegister {
+    drivers: RwLock<HashMap<String, Arc<Box<dyn Database>>>>,
+}
+
+static REGISTER: OnceLock<GlobalRegister> = OnceLock::new();
+
+fn get_register() -> &'static GlobalRegister {
+    REGISTER.get_or_init(Default::default)
+}
+
+impl GlobalRegister {
+    /// Locks the drivers for reading.
+    ///
+    /// Every update of the drivers map is a single insert or remove, so the map is consistent
+    /// even if a panic poisoned the lock, and the poison is ignored.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<Box<dyn Database>>>> {
+        self.drivers.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the drivers for writing, ignoring poison as [`read`](GlobalRegister::read) does.
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Arc<Box<dyn Database>>>> {
+        self.drivers.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns the edit distance between `lhs` and `rhs`.
+fn levenshtein(lhs: &str, rhs: &str) -> usize {
+    let rhs = rhs.chars().collect::<Vec<_>>();
+
+    let mut distances = (0..=rhs.len()).collect::<Vec<_>>();
+
+    for (i, l) in lhs.chars().enumerate() {
+        let mut prev = distances[0];
+
+        distances[0] = i + 1;
+
+        for (j, r) in rhs.iter().enumerate() {
+            let substitution = if l == *r { prev } else { prev + 1 };
+
+            prev = distances[j + 1];
+
+            distances[j + 1] = substitution.min(prev + 1).min(distances[j] + 1);
+        }
+    }
+
+    distances[rhs.len()]
+}
+
+/// Create the [`NotFound`](io::ErrorKind::NotFound) error of `open`,
+/// listing the registered drivers and the nearest match of `driver_name`.
+fn unknown_driver(driver_name: &str, registered: &[String]) -> io::Error {
+    let suggestion = registered
+        .iter()
+        .map(|name| (levenshtein(driver_name, name), name))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, name)| format!(" (did you mean `{}`?)", name))
+        .unwrap_or_default();
+
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "Unknown driver: {}{}, registered drivers: [{}]",
+            driver_name,
+            suggestion,
+            registered.join(", ")
+        ),
+    )
+}
+
+/// Open opens a database specified by its database driver name and a driver-specific data source name, usually consisting of at least a database name and connection information.
+pub async fn open<D: AsRef<str>, S: AsRef<str>>(driver_name: D, source_name: S) -> Result<DbConn> {
+    // the read lock must be released before awaiting the connection.
+    let database = get_register().read().get(driver_name.as_ref()).cloned();
+
+    let database = match database {
+        Some(database) => database,
+        None => return Err(unknown_driver(driver_name.as_ref(), &registered_drivers()?)),
+    };
+
+    let conn = database.start_connect(source_name.as_ref())?;
+
+    cancelable_would_block(|cx| database.poll_connect(cx, &conn)).await?;
+
+    Ok(DbConn {
+        conn: Arc::new(conn),
+        database,
+        statement_timeout: Default::default(),
+        stmt_cache: Default::default(),
+    })
+}
+
+/// Register new database driver.
+///
+/// Returns [`AlreadyExists`](io::ErrorKind::AlreadyExists) error, if register same driver name twice,
+/// the previously registered driver is kept.
+pub fn register<N: AsRef<str>, D: Database + 'static>(driver_name: N, database: D) -> Result<()> {
+    let mut drivers = get_register().write();
+
+    let driver_name = driver_name.as_ref();
+
+    if drivers.contains_key(driver_name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("register driver twice: {}", driver_name),
+        ));
+    }
+
+    drivers.insert(driver_name.to_owned(), Arc::new(Box::new(database)));
+
+    Ok(())
+}
+
+/// Returns the sorted names of all registered database drivers.
+pub fn registered_drivers() -> Result<Vec<String>> {
+    let mut names = get_register().read().keys().cloned().collect::<Vec<_>>();
+
+    names.sort();
+
+    Ok(names)
+}
+
+/// Remove a registered database driver, returns `false` if there is no driver named `driver_name`.
+///
+/// Connections already opened with the driver keep working, they hold their own reference to it.
+pub fn deregister<N: AsRef<str>>(driver_name: N) -> Result<bool> {
+    Ok(get_register()
+        .write()
+        .remove(driver_name.as_ref())
+        .is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use futures::{executor::block_on, poll};
+
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::mock::{register_mock_timer, MockDatabase, MockStmt};
+
+    #[test]
+    fn test_last_error() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            assert!(conn.last_error().is_none());
+
+            assert!(conn.prepare("").await.is_err());
+
+            assert_eq!(conn.last_error().unwrap().to_string(), "mock: empty query");
+        });
+    }
+
+    #[test]
+    fn test_expand_in_clause() {
+        let list = [1, 2, 3].map(SqlValue::Int);
+        let params = ["a".into(), "b".into()];
+
+        let (query, values) = expand_in_clause(
+            "SELECT * FROM t WHERE a = ? AND id IN (...?) AND b = ?",
+            &params,
+            &list,
+            100,
+        )
+        .unwrap();
+
+        assert_eq!(
+            query,
+            "SELECT * FROM t WHERE a = ? AND id IN (?, ?, ?) AND b = ?"
+        );
+        assert_eq!(
+            values,
+            [
+                "a".into(),
+                SqlValue::Int(1),
+                SqlValue::Int(2),
+                SqlValue::Int(3),
+                "b".into()
+            ]
+        );
+
+        assert_eq!(
+            expand_in_clause("SELECT * FROM t WHERE id IN (...?)", &[], &list, 3)
+                .unwrap()
+                .0,
+            "SELECT * FROM t WHERE id IN (?, ?, ?)"
+        );
+
+        let err = expand_in_clause(
+            "SELECT * FROM t WHERE a = ? AND id IN (...?)",
+            &[SqlValue::Null],
+            &list,
+            3,
+        )
+        .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        assert!(expand_in_clause("SELECT * FROM t WHERE id IN (...?)", &[], &[], 100).is_err());
+        assert!(expand_in_clause("SELECT * FROM t WHERE id = ?", &[], &list, 100).is_err());
+        assert!(expand_in_clause("SELECT (...?), (...?)", &[], &list, 100).is_err());
+
+        // the values of the other placeholders must match them.
+        assert_eq!(
+            expand_in_clause(
+                "SELECT * FROM t WHERE a = ? AND id IN (...?)",
+                &[],
+                &list,
+                100
+            )
+            .unwrap_err()
+            .to_string(),
+            "expected 1 parameters, got 0"
+        );
+    }
+
+    #[test]
+    fn test_stmt_try_clone() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let stmt = conn.prepare("SELECT * FROM t WHERE id = ?").await.unwrap();
+
+            let cloned = stmt.try_clone().await.unwrap();
+
+            let stmt = stmt.stmt_handle.downcast::<MockStmt>().unwrap();
+            let cloned = cloned.stmt_handle.downcast::<MockStmt>().unwrap();
+
+            // the clone must be a separately prepared statement for the same query.
+            assert_ne!(stmt.id, cloned.id);
+            assert_eq!(stmt.query, cloned.query);
+        });
+    }
+
+    #[test]
+    fn test_prepare_cached() {
+        block_on(async {
+            let conn = MockDatabase::default().pending_prepare().connect();
+
+            let query = "SELECT * FROM t WHERE id = ?";
+
+            let id = |stmt: &Stmt| stmt.stmt_handle.downcast::<MockStmt>().unwrap().id;
+
+            // a miss yields once to poll the prepare.
+            let mut miss = pin!(conn.prepare_cached(query));
+
+            assert!(poll!(miss.as_mut()).is_pending());
+
+            let first = miss.await.unwrap();
+
+            // the cached statement is in use.
+            let mut busy = pin!(conn.prepare_cached(query));
+
+            assert!(poll!(busy.as_mut()).is_pending());
+
+            let second = busy.await.unwrap();
+
+            assert_ne!(id(&first), id(&second));
+
+            let first_id = id(&first);
+
+            drop((first, second));
+
+            // a hit is ready without yielding.
+            let hit = match poll!(pin!(conn.prepare_cached(query))) {
+                Poll::Ready(stmt) => stmt.unwrap(),
+                Poll::Pending => panic!("cached prepare yields"),
+            };
+
+            assert_eq!(id(&hit), first_id);
+            assert_eq!(hit.query, query);
+
+            hit.exec(&[1.into()]).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_begin_with_stmt() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let (tx, stmt) = conn
+                .begin_with_stmt("INSERT INTO t VALUES(?)")
+                .await
+                .unwrap();
+
+            assert!(Arc::ptr_eq(&tx.tx_handle, &stmt.conn_or_tx));
+            assert_eq!(
+                stmt.stmt_handle.downcast::<MockStmt>().unwrap().query,
+                "INSERT INTO t VALUES(?)"
+            );
+
+            assert_eq!(stmt.exec(&[SqlValue::Int(1)]).await.unwrap(), (1, 1));
+
+            tx.commit().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_exec_keep() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let (result, stmt) = conn
+                .exec_keep("INSERT INTO t VALUES(?)", &[SqlValue::Int(1)])
+                .await
+                .unwrap();
+
+            assert_eq!(result, (1, 1));
+
+            assert_eq!(stmt.exec(&[SqlValue::Int(2)]).await.unwrap(), (2, 1));
+
+            // the statement is prepared once.
+            assert_eq!(stmt.stmt_handle.downcast::<MockStmt>().unwrap().id, 0);
+
+            assert!(conn.exec_keep("", &[]).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_one_shot() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture("SELECT id FROM t", &["id"], vec![vec![SqlValue::Int(1)]])
+                .connect();
+
+            assert_eq!(
+                conn.execute("INSERT INTO t VALUES(?)", &[SqlValue::Int(1)])
+                    .await
+                    .unwrap(),
+                (1, 1)
+            );
+
+            let result_set = conn.query_one_shot("SELECT id FROM t", &[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(result_set.get(0).await.unwrap(), SqlValue::Int(1));
+
+            let tx = conn.begin().await.unwrap();
+
+            assert_eq!(
+                tx.execute("INSERT INTO t VALUES(?)", &[SqlValue::Int(2)])
+                    .await
+                    .unwrap(),
+                (2, 1)
+            );
+
+            assert!(tx
+                .query_one_shot("SELECT id FROM t", &[])
+                .await
+                .unwrap()
+                .next()
+                .await
+                .unwrap());
+
+            // the prepare error is returned as is.
+            assert_eq!(
+                conn.execute("", &[]).await.err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+            assert!(tx.query_one_shot("", &[]).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_get_nullable() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name FROM user",
+                    &["id", "name"],
+                    vec![vec![SqlValue::Int(1), SqlValue::Null]],
+                )
+                .connect();
+
+            let stmt = conn.prepare("SELECT id, name FROM user").await.unwrap();
+
+            let rs = stmt.query(&[]).await.unwrap();
+
+            assert!(rs.next().await.unwrap());
+
+            assert!(matches!(
+                rs.get_nullable(0).await,
+                Ok(Some(SqlValue::Int(1)))
+            ));
+            assert!(matches!(rs.get_nullable(1).await, Ok(None)));
+            assert!(rs.get_nullable(2).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_get_as() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name, email FROM user",
+                    &["id", "name", "email"],
+                    vec![vec![SqlValue::Int(1), "alice".into(), SqlValue::Null]],
+                )
+                .connect();
+
+            let stmt = conn
+                .prepare("SELECT id, name, email FROM user")
+                .await
+                .unwrap();
+
+            let rs = stmt.query(&[]).await.unwrap();
+
+            let col_types = rs.column_types().await.unwrap();
+
+            assert!(rs.next().await.unwrap());
+
+            let id: i64 = rs.get_as(0).await.unwrap();
+            let name: String = rs.get_as(1).await.unwrap();
+
+            assert_eq!((id, name.as_str()), (1, "alice"));
+
+            let id: i64 = rs.get_by_name_as("id", &col_types).await.unwrap();
+            let name: String = rs.get_by_name_as("name", &col_types).await.unwrap();
+
+            assert_eq!((id, name.as_str()), (1, "alice"));
+
+            let email: Option<String> = rs.get_by_name_as("email", &col_types).await.unwrap();
+
+            assert_eq!(email, None);
+
+            assert_eq!(
+                rs.get_as::<i64>(1).await.err().unwrap().kind(),
+                io::ErrorKind::InvalidData
+            );
+            assert_eq!(
+                rs.get_by_name_as::<String, _>("missing", &col_types)
+                    .await
+                    .err()
+                    .unwrap()
+                    .kind(),
+                io::ErrorKind::NotFound
+            );
+        });
+    }
+
+    #[test]
+    fn test_get_by_name() {
+        block_on(async {
+            let rows = (0..1000)
+                .map(|id| vec![SqlValue::Int(id), SqlValue::from(format!("user{}", id))])
+                .collect();
+
+            let conn = MockDatabase::default()
+                .fixture("SELECT id, name FROM user", &["id", "name"], rows)
+                .connect();
+
+            let stmt = conn.prepare("SELECT id, name FROM user").await.unwrap();
+
+            let rs = stmt
+                .query(&[])
+                .await
+                .unwrap()
+                .with_column_aliases(&[("id".to_owned(), "userId".to_owned())].into());
+
+            let mut count = 0;
+
+            while rs.next().await.unwrap() {
+                assert_eq!(
+                    rs.get_by_name("userId").await.unwrap(),
+                    SqlValue::Int(count)
+                );
+                assert_eq!(
+                    rs.get_by_name("name").await.unwrap(),
+                    SqlValue::from(format!("user{}", count))
+                );
+
+                count += 1;
+            }
+
+            assert_eq!(count, 1000);
+
+            assert_eq!(
+                rs.get_by_name("id").await.err().unwrap().kind(),
+                io::ErrorKind::NotFound
+            );
+        });
+    }
+
+    #[test]
+    fn test_skip() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id FROM t",
+                    &["id"],
+                    (1..=10).map(|id| vec![SqlValue::Int(id)]).collect(),
+                )
+                .connect();
+
+            let stmt = conn.prepare("SELECT id FROM t").await.unwrap();
+
+            let rs = stmt.query(&[]).await.unwrap();
+
+            assert_eq!(rs.skip(5).await.unwrap(), 5);
+
+            assert!(rs.next().await.unwrap());
+            assert!(matches!(rs.get(0).await, Ok(SqlValue::Int(6))));
+
+            assert_eq!(rs.skip(10).await.unwrap(), 4);
+            assert!(!rs.next().await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_column_aliases() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT user_id, name FROM user",
+                    &["user_id", "name"],
+                    vec![vec![SqlValue::Int(7), SqlValue::Null]],
+                )
+                .connect();
+
+            let stmt = conn
+                .prepare("SELECT user_id, name FROM user")
+                .await
+                .unwrap();
+
+            let rs = stmt
+                .query(&[])
+                .await
+                .unwrap()
+                .with_column_aliases(&[("user_id".to_owned(), "userId".to_owned())].into());
+
+            assert_eq!(rs.columns().await.unwrap(), ["userId", "name"]);
+
+            let col_types = rs.column_types().await.unwrap();
+
+            assert!(rs.next().await.unwrap());
+
+            assert!(matches!(
+                rs.get_by_col_name("userId", &col_types).await,
+                Ok(SqlValue::Int(7))
+            ));
+            assert!(rs.get_by_col_name("user_id", &col_types).await.is_err());
+            assert!(matches!(rs.get(0).await, Ok(SqlValue::Int(7))));
+        });
+    }
+
+    #[test]
+    fn test_tx_deadline() {
+        register_mock_timer();
+
+        block_on(async {
+            let rollbacks = Arc::new(AtomicUsize::new(0));
+            let commit_lock = Arc::new(AtomicBool::new(false));
+
+            let conn = MockDatabase::default()
+                .rollbacks(rollbacks.clone())
+                .commit_lock(commit_lock.clone())
+                .connect();
+
+            let options = TxOptions::default().deadline(Duration::from_millis(50));
+
+            let tx = conn.begin_with_options(options.clone()).await.unwrap();
+
+            tx.execute("INSERT INTO t VALUES (1)", &[]).await.unwrap();
+
+            let stmt = tx.prepare("INSERT INTO t VALUES (2)").await.unwrap();
+
+            std::thread::sleep(Duration::from_millis(60));
+
+            // the first operation after the deadline rolls back, later ones only fail.
+            let err = stmt.exec(&[]).await.unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+
+            for err in [
+                tx.execute("INSERT INTO t VALUES (3)", &[])
+                    .await
+                    .unwrap_err(),
+                tx.savepoint().await.err().unwrap(),
+                tx.commit().await.unwrap_err(),
+            ] {
+                assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            }
+
+            tx.rollback().await.unwrap();
+
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+
+            // a commit waiting for a lock times out at the deadline.
+            commit_lock.store(true, Ordering::SeqCst);
+
+            let tx = conn.begin_with_options(options).await.unwrap();
+
+            let err = tx.commit().await.unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 2);
+
+            // no deadline by default.
+            let tx = conn.begin().await.unwrap();
+
+            commit_lock.store(false, Ordering::SeqCst);
+
+            tx.commit().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_tx_drop() {
+        register_mock_timer();
+
+        let rollbacks = Arc::new(AtomicUsize::new(0));
+
+        let conn = MockDatabase::default()
+            .rollbacks(rollbacks.clone())
+            .connect();
+
+        // a panic while the transaction is open rolls it back.
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            block_on(async {
+                let tx = conn.begin().await.unwrap();
+
+                tx.execute("INSERT INTO t VALUES (1)", &[]).await.unwrap();
+
+                panic!("abort the transaction");
+            })
+        }));
+
+        assert!(panicked.is_err());
+        assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+
+        block_on(async {
+            conn.execute("INSERT INTO t VALUES (1)", &[]).await.unwrap();
+
+            // an ended transaction is not rolled back again.
+            conn.begin().await.unwrap().commit().await.unwrap();
+            conn.begin().await.unwrap().rollback().await.unwrap();
+
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 2);
+
+            // the deadline has already rolled back the transaction.
+            let tx = conn
+                .begin_with_options(TxOptions::default().deadline(Duration::from_millis(10)))
+                .await
+                .unwrap();
+
+            std::thread::sleep(Duration::from_millis(20));
+
+            assert!(tx.execute("INSERT INTO t VALUES (1)", &[]).await.is_err());
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 3);
+
+            drop(tx);
+
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn test_default_statement_timeout() {
+        register_mock_timer();
+
+        block_on(async {
+            let conn = MockDatabase::default()
+                .exec_delay(Duration::from_millis(100))
+                .connect();
+
+            let stmt = conn.prepare("INSERT INTO t VALUES (1)").await.unwrap();
+
+            conn.set_default_statement_timeout(Some(Duration::from_millis(10)));
+
+            let err = stmt.exec(&[]).await.unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+            // statements prepared later and in transactions inherit it too.
+            let tx = conn.begin().await.unwrap();
+
+            let err = tx
+                .prepare("INSERT INTO t VALUES (2)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+            // overridden per call.
+            stmt.exec_timeout(&[], None).await.unwrap();
+
+            conn.set_default_statement_timeout(None);
+
+            stmt.exec(&[]).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_describe_unsupported() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let stmt = conn.prepare("SELECT :id").await.unwrap();
+
+            assert_eq!(
+                stmt.describe().err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+            assert_eq!(
+                stmt.is_readonly().err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+            assert_eq!(
+                stmt.scan_status().err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_rowid_unsupported() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture("SELECT id FROM t", &["id"], vec![vec![SqlValue::Int(1)]])
+                .connect();
+
+            let result_set = conn.query_one_shot("SELECT id FROM t", &[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(
+                result_set.rowid().await.err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_watch_table_unsupported() {
+        let conn = MockDatabase::default().connect();
+
+        assert_eq!(
+            conn.watch_table("t").err().unwrap().kind(),
+            io::ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn test_named_unsupported() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let stmt = conn.prepare("SELECT :id").await.unwrap();
+
+            assert_eq!(
+                stmt.query_named(&[(":id", 1.into())])
+                    .await
+                    .err()
+                    .unwrap()
+                    .kind(),
+                io::ErrorKind::Unsupported
+            );
+            assert_eq!(
+                stmt.exec_named(&[(":id", 1.into())])
+                    .await
+                    .err()
+                    .unwrap()
+                    .kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_last_insert_id_unsupported() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            assert_eq!(
+                conn.last_insert_id().await.unwrap_err().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_savepoint_unsupported() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let tx = conn.begin().await.unwrap();
+
+            assert_eq!(
+                tx.savepoint().await.err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_begin_with() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            conn.begin_with(IsolationLevel::ReadCommitted)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                conn.begin_with(IsolationLevel::Serializable)
+                    .await
+                    .err()
+                    .unwrap()
+                    .kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_commit_timeout() {
+        register_mock_timer();
+
+        block_on(async {
+            let locked = Arc::new(AtomicBool::new(true));
+
+            let conn = MockDatabase::default()
+                .commit_lock(locked.clone())
+                .connect();
+
+            let tx = conn.begin().await.unwrap();
+
+            let err = tx
+                .commit_timeout(Duration::from_millis(10))
+                .await
+                .unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+            locked.store(false, Ordering::SeqCst);
+
+            tx.commit_timeout(Duration::from_millis(10)).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_register() {
+        register("mock-register", MockDatabase::default()).unwrap();
+
+        let conn = block_on(open("mock-register", "")).unwrap();
+
+        block_on(conn.prepare("SELECT 1")).unwrap();
+
+        let err = register("mock-register", MockDatabase::default()).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        // the first driver stays registered.
+        block_on(open("mock-register", "")).unwrap();
+    }
+
+    #[test]
+    fn test_registered_drivers() {
+        register("mock-registered-b", MockDatabase::default()).unwrap();
+        register("mock-registered-a", MockDatabase::default()).unwrap();
+
+        let names = registered_drivers().unwrap();
+
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let a = names.iter().position(|name| name == "mock-registered-a");
+        let b = names.iter().position(|name| name == "mock-registered-b");
+
+        assert!(a.unwrap() < b.unwrap());
+
+        deregister("mock-registered-a").unwrap();
+
+        assert!(!registered_drivers()
+            .unwrap()
+            .contains(&"mock-registered-a".to_owned()));
+    }
+
+    #[test]
+    fn test_deregister() {
+        register("mock-deregister", MockDatabase::default()).unwrap();
+
+        let conn = block_on(open("mock-deregister", "")).unwrap();
+
+        assert!(deregister("mock-deregister").unwrap());
+        assert!(!deregister("mock-deregister").unwrap());
+
+        // the connection keeps the driver alive.
+        block_on(conn.prepare("SELECT 1")).unwrap();
+
+        let err = match block_on(open("mock-deregister", "")) {
+            Err(err) => err,
+            Ok(_) => panic!("Expect unknown driver error"),
+        };
+
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        // the name can be registered again.
+        register("mock-deregister", MockDatabase::default()).unwrap();
+    }
+
+    #[test]
+    fn test_open_unknown_driver() {
+        register("mock-suggest", MockDatabase::default()).unwrap();
+
+        let err = match block_on(open("mock-sugest", "")) {
+            Err(err) => err,
+            Ok(_) => panic!("Expect unknown driver error"),
+        };
+
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let message = err.to_string();
+
+        assert!(
+            message.contains("(did you mean `mock-suggest`?)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("mock-suggest"), "{}", message);
+
+        assert_eq!(levenshtein("sqlite", "sqlite"), 0);
+        assert_eq!(levenshtein("sqlit", "sqlite"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+
+        assert_eq!(
+            unknown_driver("mysql", &["sqlite".to_owned()]).to_string(),
+            "Unknown driver: mysql, registered drivers: [sqlite]"
+        );
+    }
+
+    #[test]
+    fn test_register_poison_recovery() {
+        _ = std::thread::spawn(|| {
+            let _drivers = get_register().write();
+            panic!("poison the register");
+        })
+        .join();
+
+        assert!(get_register().drivers.is_poisoned());
+
+        register("mock-poison", MockDatabase::default()).unwrap();
+
+        block_on(open("mock-poison", "")).unwrap();
+    }
+
+    #[test]
+    fn test_exec_batch() {
+        block_on(async {
+            let conn = MockDatabase::default().connect();
+
+            let stmt = conn.prepare("INSERT INTO t VALUES(?)").await.unwrap();
+
+            let rows = (0..3).map(|i| vec![SqlValue::Int(i)]).collect::<Vec<_>>();
+
+            assert_eq!(stmt.exec_batch(&rows).await.unwrap(), (3, 3));
+
+            assert_eq!(stmt.exec_batch(&[]).await.unwrap(), (0, 0));
+        });
+    }
+
+    #[test]
+    fn test_exec_batch_commit_error() {
+        block_on(async {
+            let rollbacks = Arc::new(AtomicUsize::new(0));
+
+            let conn = MockDatabase::default()
+                .failing_commit()
+                .rollbacks(rollbacks.clone())
+                .connect();
+
+            let stmt = conn.prepare("INSERT INTO t VALUES(?)").await.unwrap();
+
+            let err = stmt
+                .exec_batch(&[vec![SqlValue::Int(1)]])
+                .await
+                .unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+            assert_eq!(rollbacks.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn test_value_or() {
+        assert!(matches!(
+            SqlValue::Null.or(SqlValue::Int(0)),
+            SqlValue::Int(0)
+        ));
+        assert!(matches!(
+            SqlValue::Int(1).or(SqlValue::Int(0)),
+            SqlValue::Int(1)
+        ));
+
+        assert!(matches!(
+            SqlValue::Null.or_else(|| SqlValue::String(Cow::Borrowed("none"))),
+            SqlValue::String(v) if v == "none"
+        ));
+        assert!(matches!(
+            SqlValue::Bool(false).or_else(|| unreachable!()),
+            SqlValue::Bool(false)
+        ));
+    }
+
+    #[test]
+    fn test_value_len() {
+        let value = SqlValue::String(Cow::Borrowed("héllo, 世界"));
+
+        assert_eq!(value.byte_len(), Some(14));
+        assert_eq!(value.char_len(), Some(9));
+
+        let value = SqlValue::Binary(Cow::Owned(vec![0; 4]));
+
+        assert_eq!(value.byte_len(), Some(4));
+        assert_eq!(value.char_len(), None);
+
+        assert_eq!(SqlValue::Int(1).byte_len(), None);
+        assert_eq!(SqlValue::Null.char_len(), None);
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn test_column_type_serialize() {
+        let col_type = ColumnType {
+            database_type_name: Cow::Borrowed("DECIMAL"),
+            decimal_size: Some((2, 10)),
+            length: None,
+            name: Cow::Owned("price".to_owned()),
+            nullable: Some(false),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&col_type).unwrap(),
+            serde_json::json!({
+                "database_type_name": "DECIMAL",
+                "decimal_size": [2, 10],
+                "length": null,
+                "name": "price",
+                "nullable": false,
+            })
+        );
+    }
+
+    #[cfg(feature = "with-bytes")]
+    #[test]
+    fn test_bytes() {
+        let buf = bytes::Bytes::from_static(b"hello");
+
+        let value = SqlValue::from(&buf);
+
+        assert!(matches!(value, SqlValue::Binary(Cow::Borrowed(v)) if v.as_ptr() == buf.as_ptr()));
+
+        assert_eq!(value.into_bytes(), Some(buf.clone()));
+
+        let value = SqlValue::from(buf.clone());
+
+        let ptr = match &value {
+            SqlValue::Binary(Cow::Owned(v)) => v.as_ptr(),
+            _ => panic!("Expect owned binary"),
+        };
+
+        let round_trip = value.into_bytes().unwrap();
+
+        // the owned buffer is moved into `Bytes` without copying.
+        assert_eq!(round_trip.as_ptr(), ptr);
+        assert_eq!(round_trip, buf);
+
+        assert_eq!(SqlValue::Int(1).into_bytes(), None);
+    }
+
+    #[test]
+    fn test_into_owned() {
+        fn owned(value: SqlValue<'static>) -> SqlValue<'static> {
+            value
+        }
+
+        fn ptr(value: &SqlValue<'_>) -> *const u8 {
+            match value {
+                SqlValue::Binary(v) => v.as_ptr(),
+                _ => panic!("Expect binary"),
+            }
+        }
+
+        let text = String::from("hello");
+
+        let value = SqlValue::String(Cow::Borrowed(&text)).into_owned();
+
+        drop(text);
+
+        assert!(matches!(owned(value), SqlValue::String(Cow::Owned(v)) if v == "hello"));
+
+        let buf = SqlValue::Binary(Cow::Owned(vec![1, 2, 3]));
+
+        let borrowed = buf.as_ref();
+
+        assert!(matches!(borrowed, SqlValue::Binary(Cow::Borrowed(_))));
+        assert_eq!(ptr(&borrowed), ptr(&buf));
+
+        // an owned buffer is moved, not copied.
+        let before = ptr(&buf);
+
+        assert_eq!(ptr(&buf.into_owned()), before);
+
+        assert!(matches!(
+            SqlValue::Int(7).as_ref().into_owned(),
+            SqlValue::Int(7)
+        ));
+    }
+
+    #[test]
+    fn test_debug() {
+        let values = [
+            (SqlValue::Bool(true), "Bool(true)"),
+            (SqlValue::Int(-1), "Int(-1)"),
+            (
+                SqlValue::BigInt(i128::MAX),
+                "BigInt(170141183460469231731687303715884105727)",
+            ),
+            (SqlValue::Float(1.5), "Float(1.5)"),
+            (
+                SqlValue::String(Cow::Borrowed("a\"b")),
+                "String(\"a\\\"b\")",
+            ),
+            (
+                SqlValue::Binary(Cow::Borrowed(&[0xde, 0xad])),
+                "Binary(len=2, dead)",
+            ),
+            (SqlValue::Null, "Null"),
+        ];
+
+        for (value, expect) in values {
+            assert_eq!(format!("{:?}", value), expect);
+        }
+
+        let large = SqlValue::Binary(Cow::Owned((0..=255).collect()));
+
+        assert_eq!(
+            format!("{:?}", large),
+            "Binary(len=256, 000102030405060708090a0b0c0d0e0f..)"
+        );
+
+        #[cfg(feature = "with-decimal")]
+        assert!(format!("{:?}", SqlValue::Decimal("1.25".parse().unwrap())).starts_with("Decimal("));
+
+        let column_type = ColumnType {
+            database_type_name: Cow::Borrowed("INTEGER"),
+            decimal_size: None,
+            length: None,
+            name: Cow::Borrowed("id"),
+            nullable: Some(false),
+        };
+
+        assert!(format!("{:?}", column_type).contains("name: \"id\""));
+    }
+
+    #[test]
+    fn test_from_primitive() {
+        let text = String::from("hi");
+
+        let value = SqlValue::from(text.as_str());
+
+        assert!(matches!(value, SqlValue::String(Cow::Borrowed(v)) if v.as_ptr() == text.as_ptr()));
+
+        assert_eq!(SqlValue::from(-8i8), SqlValue::Int(-8));
+        assert_eq!(SqlValue::from(i64::MAX), SqlValue::Int(i64::MAX));
+        assert_eq!(SqlValue::from(0.5f32), SqlValue::Float(0.5));
+        assert_eq!(SqlValue::from(true), SqlValue::Bool(true));
+        assert_eq!(
+            SqlValue::from(vec![1u8, 2]),
+            SqlValue::Binary(Cow::Borrowed(&[1, 2]))
+        );
+
+        assert_eq!(SqlValue::from(None::<i64>), SqlValue::Null);
+        assert_eq!(SqlValue::from(None::<&str>), SqlValue::Null);
+        assert_eq!(SqlValue::from(Some(42)), SqlValue::Int(42));
+        assert_eq!(
+            SqlValue::from(Some("hi")),
+            SqlValue::String(Cow::Borrowed("hi"))
+        );
+        // a nested option maps both `None`s to `Null`.
+        assert_eq!(SqlValue::from(Some(None::<bool>)), SqlValue::Null);
+    }
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(i64::try_from(SqlValue::Int(42)).unwrap(), 42);
+        assert_eq!(i64::try_from(SqlValue::BigInt(-42)).unwrap(), -42);
+        assert_eq!(f64::try_from(SqlValue::Float(0.5)).unwrap(), 0.5);
+        assert!(bool::try_from(SqlValue::Bool(true)).unwrap());
+        assert!(bool::try_from(SqlValue::Int(1)).unwrap());
+        assert_eq!(String::try_from(SqlValue::from("hi")).unwrap(), "hi");
+        assert_eq!(Vec::<u8>::try_from(SqlValue::from(vec![1u8])).unwrap(), [1]);
+
+        assert_eq!(Option::<i64>::try_from(SqlValue::Null).unwrap(), None);
+        assert_eq!(
+            Option::<String>::try_from(SqlValue::from("hi")).unwrap(),
+            Some("hi".to_owned())
+        );
+
+        let mismatches = [
+            i64::try_from(SqlValue::from("42")).err(),
+            i64::try_from(SqlValue::BigInt(i128::MAX)).err(),
+            i64::try_from(SqlValue::Null).err(),
+            f64::try_from(SqlValue::Int(1)).err(),
+            bool::try_from(SqlValue::Int(2)).err(),
+            String::try_from(SqlValue::from(&b"hi"[..])).err(),
+            Vec::<u8>::try_from(SqlValue::from("hi")).err(),
+            Option::<i64>::try_from(SqlValue::Float(1.0)).err(),
+        ];
+
+        for err in mismatches {
+            assert_eq!(err.unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+
+        // the error names the variant but not the value.
+        let err = String::try_from(SqlValue::Int(123456)).err().unwrap();
+
+        assert_eq!(err.to_string(), "Expect String value, got Int");
+    }
+
+    #[test]
+    fn test_partial_eq() {
+        let owned = String::from("abc");
+
+        let equal = [
+            (SqlValue::Int(1), SqlValue::Int(1)),
+            (SqlValue::Float(0.5), SqlValue::Float(0.5)),
+            (SqlValue::Null, SqlValue::Null),
+            (
+                SqlValue::String(Cow::Borrowed("abc")),
+                SqlValue::String(Cow::Owned(owned.clone())),
+            ),
+            (
+                SqlValue::Binary(Cow::Borrowed(b"abc")),
+                SqlValue::Binary(Cow::Owned(owned.clone().into_bytes())),
+            ),
+        ];
+
+        for (a, b) in equal {
+            assert_eq!(a, b);
+        }
+
+        let unequal = [
+            (SqlValue::Int(1), SqlValue::Float(1.0)),
+            (SqlValue::Int(1), SqlValue::BigInt(1)),
+            (SqlValue::Int(1), SqlValue::Bool(true)),
+            (SqlValue::Int(1), SqlValue::Null),
+            (SqlValue::Float(f64::NAN), SqlValue::Float(f64::NAN)),
+            (
+                SqlValue::String(Cow::Borrowed("abc")),
+                SqlValue::Binary(Cow::Borrowed(b"abc")),
+            ),
+            (
+                SqlValue::String(Cow::Borrowed("abc")),
+                SqlValue::String(Cow::Borrowed("ABC")),
+            ),
+        ];
+
+        for (a, b) in unequal {
+            assert_ne!(a, b);
+        }
+
+        #[cfg(feature = "with-decimal")]
+        assert_eq!(
+            SqlValue::Decimal("1.50".parse().unwrap()),
+            SqlValue::Decimal("1.5".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_redacted() {
+        let password = SqlValue::String(Cow::Borrowed("hunter2-secret"));
+
+        assert_eq!(password.redacted().to_string(), "String(len=14)");
+        assert_eq!(format!("{:?}", password.redacted()), "String(len=14)");
+        assert!(format!("{:?}", password).contains("hunter2-secret"));
+
+        let token = SqlValue::Binary(Cow::Borrowed(&[0xde, 0xad, 0xbe, 0xef]));
+
+        assert_eq!(token.redacted().to_string(), "Binary(len=4)");
+
+        for (value, expect) in [
+            (SqlValue::Int(424242), "Int"),
+            (SqlValue::BigInt(424242), "BigInt"),
+            (SqlValue::Float(4242.42), "Float"),
+            (SqlValue::Bool(true), "Bool"),
+            (SqlValue::Null, "Null"),
+        ] {
+            assert_eq!(format!("{:?}", value.redacted()), expect);
+        }
+    }
+
+    #[test]
+    fn test_system_time() {
+        let now = SystemTime::now();
+        let millis = now.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+
+        let value = SqlValue::from(now);
+
+        assert!(matches!(value, SqlValue::Int(v) if v == millis));
+
+        assert_eq!(
+            value.as_system_time(),
+            Some(UNIX_EPOCH + Duration::from_millis(millis as u64))
+        );
+
+        let before_epoch = UNIX_EPOCH - Duration::from_millis(1500);
+
+        assert!(matches!(SqlValue::from(before_epoch), SqlValue::Int(-1500)));
+        assert_eq!(SqlValue::Int(-1500).as_system_time(), Some(before_epoch));
+
+        assert_eq!(SqlValue::Null.as_system_time(), None);
+    }
 }
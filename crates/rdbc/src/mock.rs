@@ -0,0 +1,576 @@
+//! An in-memory [`Database`] driver for unit tests.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Result},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+        Arc, Mutex, Once,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rasi::syscall::{ready, register_global_timer, CancelablePoll, Handle, Timer};
+
+use crate::{Capabilities, ColumnType, Database, DbConn, SqlValue};
+
+/// The connection object created by [`MockDatabase`].
+#[derive(Default)]
+pub(crate) struct MockConn {
+    last_error: Mutex<Option<String>>,
+}
+
+/// The statement object created by [`MockDatabase`].
+pub(crate) struct MockStmt {
+    pub(crate) id: usize,
+    pub(crate) query: String,
+    /// Set by the first [`poll_prepare`](Database::poll_prepare).
+    polled: AtomicBool,
+}
+
+/// The exec result object created by [`MockDatabase`].
+struct MockExec {
+    last_insert_id: i64,
+    /// The exec stays pending until this instant.
+    ready_at: Instant,
+}
+
+/// The rows returned by a query registered with [`MockDatabase::fixture`] or [`MockDatabase::table`].
+pub(crate) struct MockTable {
+    columns: Vec<String>,
+    /// The declared column types, empty strings unless set with [`MockDatabase::declared_types`].
+    types: Vec<String>,
+    /// The declared nullability, checked by [`MockTable::insert`].
+    nullable: Vec<Option<bool>>,
+    rows: Vec<Vec<SqlValue<'static>>>,
+}
+
+impl MockTable {
+    /// Create an empty table with the column names, declared types and nullability of `schema`.
+    pub(crate) fn with_schema(schema: &[ColumnType<'_>]) -> Self {
+        Self {
+            columns: schema.iter().map(|col| col.name.to_string()).collect(),
+            types: schema
+                .iter()
+                .map(|col| col.database_type_name.to_string())
+                .collect(),
+            nullable: schema.iter().map(|col| col.nullable).collect(),
+            rows: vec![],
+        }
+    }
+
+    /// Append `row`, checking each value against the declared type of its column.
+    ///
+    /// The type is matched by the sqlite affinity rules: a type containing `INT` accepts integers,
+    /// `CHAR`, `CLOB` or `TEXT` accepts strings, `BLOB` accepts binaries, `REAL`, `FLOA` or `DOUB`
+    /// accepts numbers, and any other type accepts any value.
+    pub(crate) fn insert(mut self, row: Vec<SqlValue<'static>>) -> Result<Self> {
+        if row.len() != self.columns.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "mock: expect {} values, got {}",
+                    self.columns.len(),
+                    row.len()
+                ),
+            ));
+        }
+
+        for (col, value) in row.iter().enumerate() {
+            let accepted = match value {
+                SqlValue::Null => self.nullable[col] != Some(false),
+                value => accepts(&self.types[col].to_ascii_uppercase(), value),
+            };
+
+            if !accepted {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "mock: column {} of type {} does not accept {}",
+                        self.columns[col],
+                        self.types[col],
+                        value.redacted()
+                    ),
+                ));
+            }
+        }
+
+        self.rows.push(row);
+
+        Ok(self)
+    }
+}
+
+/// Returns true if a column declared as `type_name` accepts the non-null `value`.
+fn accepts(type_name: &str, value: &SqlValue<'_>) -> bool {
+    let integer = matches!(
+        value,
+        SqlValue::Bool(_) | SqlValue::Int(_) | SqlValue::BigInt(_)
+    );
+
+    if type_name.contains("INT") {
+        integer
+    } else if ["CHAR", "CLOB", "TEXT"]
+        .iter()
+        .any(|t| type_name.contains(t))
+    {
+        matches!(value, SqlValue::String(_))
+    } else if type_name.contains("BLOB") {
+        matches!(value, SqlValue::Binary(_))
+    } else if ["REAL", "FLOA", "DOUB"]
+        .iter()
+        .any(|t| type_name.contains(t))
+    {
+        integer || matches!(value, SqlValue::Float(_))
+    } else {
+        true
+    }
+}
+
+/// The result set object created by [`MockDatabase`].
+struct MockResultSet {
+    table: Arc<MockTable>,
+    /// The index of the next row, the current row is `cursor - 1`.
+    cursor: Mutex<usize>,
+}
+
+/// An in-memory database driver that records prepared queries and replays fixture rows.
+#[derive(Default)]
+pub(crate) struct MockDatabase {
+    prepared: AtomicUsize,
+    last_insert_id: AtomicI64,
+    fixtures: HashMap<String, Arc<MockTable>>,
+    /// While set, commits stay pending as if another connection holds the lock.
+    commit_lock: Arc<AtomicBool>,
+    /// While set, commits fail and leave the transaction open.
+    failing_commit: bool,
+    /// How long each exec stays pending.
+    exec_delay: Duration,
+    /// The number of rollbacks.
+    rollbacks: Arc<AtomicUsize>,
+    /// While set, the first poll of each prepare is pending.
+    pending_prepare: bool,
+    /// While set, the driver reports no server-side prepare and rejects parameters.
+    no_prepare: bool,
+}
+
+impl MockDatabase {
+    /// Make `query` return `rows` with column names `columns`.
+    pub(crate) fn fixture(
+        mut self,
+        query: &str,
+        columns: &[&str],
+        rows: Vec<Vec<SqlValue<'static>>>,
+    ) -> Self {
+        self.fixtures.insert(
+            query.to_owned(),
+            Arc::new(MockTable {
+                columns: columns.iter().map(|c| c.to_string()).collect(),
+                types: vec![String::new(); columns.len()],
+                nullable: vec![None; columns.len()],
+                rows,
+            }),
+        );
+
+        self
+    }
+
+    /// Make `query` return the rows of `table`.
+    pub(crate) fn table(mut self, query: &str, table: MockTable) -> Self {
+        self.fixtures.insert(query.to_owned(), Arc::new(table));
+
+        self
+    }
+
+    /// Set the declared column types of the fixture registered for `query`.
+    pub(crate) fn declared_types(mut self, query: &str, types: &[&str]) -> Self {
+        let table = self
+            .fixtures
+            .get_mut(query)
+            .and_then(Arc::get_mut)
+            .expect("mock: declared types of a registered fixture");
+
+        table.types = types.iter().map(|t| t.to_string()).collect();
+
+        self
+    }
+
+    /// Make commits stay pending while `locked` is set.
+    pub(crate) fn commit_lock(mut self, locked: Arc<AtomicBool>) -> Self {
+        self.commit_lock = locked;
+        self
+    }
+
+    /// Make commits fail.
+    pub(crate) fn failing_commit(mut self) -> Self {
+        self.failing_commit = true;
+        self
+    }
+
+    /// Count the rollbacks in `rollbacks`.
+    pub(crate) fn rollbacks(mut self, rollbacks: Arc<AtomicUsize>) -> Self {
+        self.rollbacks = rollbacks;
+        self
+    }
+
+    /// Make the first poll of each prepare pending.
+    pub(crate) fn pending_prepare(mut self) -> Self {
+        self.pending_prepare = true;
+        self
+    }
+
+    /// Report no [`server_side_prepare`](crate::Capabilities::server_side_prepare) and reject parameters.
+    pub(crate) fn no_prepare(mut self) -> Self {
+        self.no_prepare = true;
+        self
+    }
+
+    /// Make each exec stay pending for `delay`.
+    pub(crate) fn exec_delay(mut self, delay: Duration) -> Self {
+        self.exec_delay = delay;
+        self
+    }
+
+    /// Create a new connection to this database.
+    pub(crate) fn connect(self) -> DbConn {
+        let database: Arc<Box<dyn Database>> = Arc::new(Box::new(self));
+
+        DbConn {
+            conn: Arc::new(database.start_connect("mock").unwrap()),
+            database,
+            statement_timeout: Default::default(),
+            stmt_cache: Default::default(),
+        }
+    }
+}
+
+impl MockDatabase {
+    fn check_no_parameters(&self, values: &[SqlValue<'_>]) -> Result<()> {
+        if self.no_prepare && !values.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "mock: parameters without server-side prepare",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Database for MockDatabase {
+    fn start_connect(&self, _source_name: &str) -> Result<Handle> {
+        Ok(Handle::new(MockConn::default()))
+    }
+
+    fn poll_connect(&self, _cx: &mut Context<'_>, _handle: &Handle) -> CancelablePoll<Result<()>> {
+        ready(|| Ok(()))
+    }
+
+    fn begin(&self, _cx: &mut Context<'_>, _conn: &Handle) -> CancelablePoll<Result<Handle>> {
+        ready(|| Ok(Handle::new(())))
+    }
+
+    fn rollback(&self, _cx: &mut Context<'_>, _tx: &Handle) -> CancelablePoll<Result<()>> {
+        self.rollbacks.fetch_add(1, Ordering::SeqCst);
+
+        ready(|| Ok(()))
+    }
+
+    fn commit(&self, _cx: &mut Context<'_>, _tx: &Handle) -> CancelablePoll<Result<()>> {
+        if self.commit_lock.load(Ordering::SeqCst) {
+            return CancelablePoll::Pending(Handle::new(()));
+        }
+
+        if self.failing_commit {
+            return ready(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "mock: the database is locked",
+                ))
+            });
+        }
+
+        ready(|| Ok(()))
+    }
+
+    fn start_prepare(&self, conn_or_tx: &Handle, query: &str) -> Result<Handle> {
+        if query.trim().is_empty() {
+            let message = "mock: empty query".to_owned();
+
+            if let Some(conn) = conn_or_tx.downcast::<MockConn>() {
+                *conn.last_error.lock().unwrap() = Some(message.clone());
+            }
+
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        Ok(Handle::new(MockStmt {
+            id: self.prepared.fetch_add(1, Ordering::SeqCst),
+            query: query.to_owned(),
+            polled: AtomicBool::new(false),
+        }))
+    }
+
+    fn poll_prepare(&self, cx: &mut Context<'_>, stmt: &Handle) -> CancelablePoll<Result<()>> {
+        let stmt = stmt.downcast::<MockStmt>().expect("mock: statement handle");
+
+        if self.pending_prepare && !stmt.polled.swap(true, Ordering::SeqCst) {
+            cx.waker().wake_by_ref();
+            return CancelablePoll::Pending(Handle::new(()));
+        }
+
+        ready(|| Ok(()))
+    }
+
+    fn start_query(&self, stmt: &Handle, values: &[SqlValue<'_>]) -> Result<Handle> {
+        self.check_no_parameters(values)?;
+
+        let stmt = stmt.downcast::<MockStmt>().expect("mock: statement handle");
+
+        let table = self.fixtures.get(&stmt.query).ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("mock: no fixture for {}", stmt.query),
+        ))?;
+
+        Ok(Handle::new(MockResultSet {
+            table: table.clone(),
+            cursor: Mutex::new(0),
+        }))
+    }
+
+    fn poll_next(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<bool>> {
+        let result_set = result_set.downcast::<MockResultSet>().unwrap();
+
+        let mut cursor = result_set.cursor.lock().unwrap();
+
+        if *cursor < result_set.table.rows.len() {
+            *cursor += 1;
+            ready(|| Ok(true))
+        } else {
+            ready(|| Ok(false))
+        }
+    }
+
+    fn poll_value(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+        col_num: usize,
+    ) -> CancelablePoll<Result<SqlValue<'static>>> {
+        let result_set = result_set.downcast::<MockResultSet>().unwrap();
+
+        let cursor = *result_set.cursor.lock().unwrap();
+
+        let value = cursor
+            .checked_sub(1)
+            .and_then(|row| result_set.table.rows.get(row))
+            .and_then(|row| row.get(col_num))
+            .map(|v| v.as_ref().into_owned())
+            .ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("mock: no value at column {}", col_num),
+            ));
+
+        ready(|| value)
+    }
+
+    /// Every exec inserts one row.
+    fn start_exec(&self, _stmt: &Handle, values: &[SqlValue<'_>]) -> Result<Handle> {
+        self.check_no_parameters(values)?;
+
+        Ok(Handle::new(MockExec {
+            last_insert_id: self.last_insert_id.fetch_add(1, Ordering::SeqCst) + 1,
+            ready_at: Instant::now() + self.exec_delay,
+        }))
+    }
+
+    fn poll_exec(
+        &self,
+        cx: &mut Context<'_>,
+        result: &Handle,
+    ) -> CancelablePoll<Result<(i64, i64)>> {
+        let exec = result.downcast::<MockExec>().unwrap();
+
+        // a busy wait, the executor polls again right away.
+        if Instant::now() < exec.ready_at {
+            cx.waker().wake_by_ref();
+            return CancelablePoll::Pending(Handle::new(()));
+        }
+
+        let last_insert_id = exec.last_insert_id;
+
+        ready(|| Ok((last_insert_id, 1)))
+    }
+
+    fn poll_cols(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<Vec<String>>> {
+        let result_set = result_set.downcast::<MockResultSet>().unwrap();
+
+        ready(|| Ok(result_set.table.columns.clone()))
+    }
+
+    fn poll_col_types(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<Vec<ColumnType<'static>>>> {
+        let result_set = result_set.downcast::<MockResultSet>().unwrap();
+
+        let col_types = result_set
+            .table
+            .columns
+            .iter()
+            .zip(&result_set.table.types)
+            .zip(&result_set.table.nullable)
+            .map(|((name, database_type_name), &nullable)| ColumnType {
+                database_type_name: Cow::Owned(database_type_name.clone()),
+                decimal_size: None,
+                length: None,
+                name: Cow::Owned(name.clone()),
+                nullable,
+            })
+            .collect();
+
+        ready(|| Ok(col_types))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            server_side_prepare: !self.no_prepare,
+            ..Default::default()
+        }
+    }
+
+    fn last_error(&self, conn: &Handle) -> Option<io::Error> {
+        let conn = conn.downcast::<MockConn>()?;
+
+        let message = conn.last_error.lock().unwrap().clone()?;
+
+        Some(io::Error::other(message))
+    }
+}
+
+/// A [`Timer`] that wakes each deadline from a sleeping thread.
+struct MockTimer;
+
+impl Timer for MockTimer {
+    fn deadline(&self, waker: Waker, deadline: Instant) -> Result<Option<Handle>> {
+        if deadline <= Instant::now() {
+            return Ok(None);
+        }
+
+        let fired = Arc::new(AtomicBool::new(false));
+
+        let thread_fired = fired.clone();
+
+        thread::spawn(move || {
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            thread_fired.store(true, Ordering::SeqCst);
+            waker.wake();
+        });
+
+        Ok(Some(Handle::new(fired)))
+    }
+
+    fn timeout_wait(&self, _waker: Waker, handle: &Handle) -> Poll<()> {
+        if handle
+            .downcast::<Arc<AtomicBool>>()
+            .unwrap()
+            .load(Ordering::SeqCst)
+        {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Register [`MockTimer`] as the global timer, can be called more than once.
+pub(crate) fn register_mock_timer() {
+    static REGISTER: Once = Once::new();
+
+    REGISTER.call_once(|| register_global_timer(MockTimer));
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    fn column(name: &'static str, database_type_name: &'static str) -> ColumnType<'static> {
+        ColumnType {
+            database_type_name: database_type_name.into(),
+            decimal_size: None,
+            length: None,
+            name: name.into(),
+            nullable: Some(name == "note"),
+        }
+    }
+
+    #[test]
+    fn test_with_schema() {
+        let schema = [
+            column("id", "INTEGER"),
+            column("name", "VARCHAR(32)"),
+            column("score", "REAL"),
+            column("note", "TEXT"),
+        ];
+
+        let err = MockTable::with_schema(&schema)
+            .insert(vec!["1".into(), "alice".into(), 1.5.into(), SqlValue::Null])
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("column id of type INTEGER"));
+
+        let err = MockTable::with_schema(&schema)
+            .insert(vec![1.into(), SqlValue::Null, 1.5.into(), SqlValue::Null])
+            .err()
+            .unwrap();
+
+        assert!(err.to_string().contains("column name"));
+
+        assert!(MockTable::with_schema(&schema)
+            .insert(vec![1.into(), "alice".into()])
+            .is_err());
+
+        let table = MockTable::with_schema(&schema)
+            .insert(vec![1.into(), "alice".into(), 1.5.into(), SqlValue::Null])
+            .unwrap()
+            .insert(vec![2.into(), "bob".into(), 2.into(), "new".into()])
+            .unwrap();
+
+        let conn = MockDatabase::default()
+            .table("SELECT * FROM users", table)
+            .connect();
+
+        block_on(async {
+            let rs = conn
+                .query_one_shot("SELECT * FROM users", &[])
+                .await
+                .unwrap();
+
+            let types = rs.column_types().await.unwrap();
+
+            assert_eq!(types[1].database_type_name, "VARCHAR(32)");
+            assert_eq!(types[3].nullable, Some(true));
+
+            assert!(rs.next().await.unwrap());
+            assert!(rs.next().await.unwrap());
+            assert_eq!(rs.get(1).await.unwrap(), SqlValue::String("bob".into()));
+            assert!(!rs.next().await.unwrap());
+        });
+    }
+}
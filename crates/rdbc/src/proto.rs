@@ -0,0 +1,478 @@
+//! The protobuf mapping of [`SqlValue`], see [`SqlValue::to_proto`].
+//!
+//! The types mirror the messages of `proto/sql_value.proto` and encode to the same wire format,
+//! so peers can use the code generated from that schema.
+
+use std::io::{self, Result};
+
+#[cfg(feature = "with-chrono")]
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike};
+
+use crate::SqlValue;
+
+/// The `rinq.rdbc.SqlValue` message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProtoSqlValue {
+    /// The `kind` oneof, `None` if no field is set.
+    pub kind: Option<ProtoKind>,
+}
+
+/// The fields of the `kind` oneof of [`ProtoSqlValue`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtoKind {
+    BoolValue(bool),
+    IntValue(i64),
+    /// The 16 big-endian bytes of an `i128`.
+    BigIntValue(Vec<u8>),
+    FloatValue(f64),
+    /// The decimal text of the exact value.
+    DecimalValue(String),
+    BinaryValue(Vec<u8>),
+    StringValue(String),
+    /// Always `0`.
+    NullValue(i32),
+    /// The days since 1970-01-01.
+    DateValue(i32),
+    /// The nanoseconds since midnight.
+    TimeValue(i64),
+    TimestampValue(ProtoTimestamp),
+    /// The 16 bytes of a uuid.
+    UuidValue(Vec<u8>),
+}
+
+/// The `rinq.rdbc.Timestamp` message, an instant in UTC as `google.protobuf.Timestamp`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtoTimestamp {
+    pub seconds: i64,
+    pub nanos: i32,
+}
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LEN: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// The days from 0001-01-01 to 1970-01-01.
+#[cfg(feature = "with-chrono")]
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+impl<'a> SqlValue<'a> {
+    /// Returns the protobuf message of this value, which converts back with [`from_proto`](SqlValue::from_proto) without loss.
+    pub fn to_proto(&self) -> ProtoSqlValue {
+        let kind = match self {
+            SqlValue::Bool(v) => ProtoKind::BoolValue(*v),
+            SqlValue::Int(v) => ProtoKind::IntValue(*v),
+            SqlValue::BigInt(v) => ProtoKind::BigIntValue(v.to_be_bytes().to_vec()),
+            SqlValue::Float(v) => ProtoKind::FloatValue(*v),
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => ProtoKind::DecimalValue(v.to_string()),
+            SqlValue::Binary(v) => ProtoKind::BinaryValue(v.to_vec()),
+            SqlValue::String(v) => ProtoKind::StringValue(v.to_string()),
+            SqlValue::Null => ProtoKind::NullValue(0),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(v) => {
+                ProtoKind::DateValue(v.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE)
+            }
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Time(v) => ProtoKind::TimeValue(
+                v.num_seconds_from_midnight() as i64 * 1_000_000_000 + v.nanosecond() as i64,
+            ),
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Timestamp(v) => ProtoKind::TimestampValue(ProtoTimestamp {
+                seconds: v.timestamp(),
+                nanos: v.timestamp_subsec_nanos() as i32,
+            }),
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => ProtoKind::UuidValue(v.as_bytes().to_vec()),
+        };
+
+        ProtoSqlValue { kind: Some(kind) }
+    }
+}
+
+impl SqlValue<'static> {
+    /// Converts a protobuf message created by [`to_proto`](SqlValue::to_proto) back to a value.
+    ///
+    /// Returns [`InvalidData`](io::ErrorKind::InvalidData) error if no field is set or a field is malformed,
+    /// and [`Unsupported`](io::ErrorKind::Unsupported) error for a decimal, temporal or uuid value
+    /// if the matching `with-*` feature is disabled.
+    pub fn from_proto(value: ProtoSqlValue) -> Result<Self> {
+        let kind = value
+            .kind
+            .ok_or_else(|| invalid_data("proto: SqlValue without kind"))?;
+
+        let value = match kind {
+            ProtoKind::BoolValue(v) => SqlValue::Bool(v),
+            ProtoKind::IntValue(v) => SqlValue::Int(v),
+            ProtoKind::BigIntValue(v) => SqlValue::BigInt(i128::from_be_bytes(
+                v.try_into()
+                    .map_err(|_| invalid_data("proto: big_int_value is not 16 bytes"))?,
+            )),
+            ProtoKind::FloatValue(v) => SqlValue::Float(v),
+            #[cfg(feature = "with-decimal")]
+            ProtoKind::DecimalValue(v) => SqlValue::Decimal(
+                v.parse()
+                    .map_err(|_| invalid_data(format!("proto: invalid decimal_value {}", v)))?,
+            ),
+            ProtoKind::BinaryValue(v) => SqlValue::Binary(v.into()),
+            ProtoKind::StringValue(v) => SqlValue::String(v.into()),
+            ProtoKind::NullValue(_) => SqlValue::Null,
+            #[cfg(feature = "with-chrono")]
+            ProtoKind::DateValue(v) => SqlValue::Date(
+                v.checked_add(UNIX_EPOCH_DAYS_FROM_CE)
+                    .and_then(NaiveDate::from_num_days_from_ce_opt)
+                    .ok_or_else(|| invalid_data("proto: date_value out of range"))?,
+            ),
+            #[cfg(feature = "with-chrono")]
+            ProtoKind::TimeValue(v) => SqlValue::Time(
+                u32::try_from(v.div_euclid(1_000_000_000))
+                    .ok()
+                    .and_then(|secs| {
+                        NaiveTime::from_num_seconds_from_midnight_opt(
+                            secs,
+                            v.rem_euclid(1_000_000_000) as u32,
+                        )
+                    })
+                    .ok_or_else(|| invalid_data("proto: time_value out of range"))?,
+            ),
+            #[cfg(feature = "with-chrono")]
+            ProtoKind::TimestampValue(v) => SqlValue::Timestamp(
+                u32::try_from(v.nanos)
+                    .ok()
+                    .and_then(|nanos| DateTime::from_timestamp(v.seconds, nanos))
+                    .ok_or_else(|| invalid_data("proto: timestamp_value out of range"))?,
+            ),
+            #[cfg(feature = "with-uuid")]
+            ProtoKind::UuidValue(v) => SqlValue::Uuid(
+                uuid::Uuid::from_slice(&v)
+                    .map_err(|_| invalid_data("proto: uuid_value is not 16 bytes"))?,
+            ),
+            #[allow(unreachable_patterns)]
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("proto: {:?} requires a disabled feature", kind),
+                ))
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+impl ProtoSqlValue {
+    /// Encodes this message in the protobuf wire format.
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![];
+
+        let Some(kind) = &self.kind else {
+            return buf;
+        };
+
+        match kind {
+            ProtoKind::BoolValue(v) => put_varint_field(&mut buf, 1, *v as u64),
+            ProtoKind::IntValue(v) => put_varint_field(&mut buf, 2, zigzag(*v)),
+            ProtoKind::BigIntValue(v) => put_len_field(&mut buf, 3, v),
+            ProtoKind::FloatValue(v) => {
+                put_key(&mut buf, 4, FIXED64);
+                buf.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+            ProtoKind::DecimalValue(v) => put_len_field(&mut buf, 5, v.as_bytes()),
+            ProtoKind::BinaryValue(v) => put_len_field(&mut buf, 6, v),
+            ProtoKind::StringValue(v) => put_len_field(&mut buf, 7, v.as_bytes()),
+            ProtoKind::NullValue(v) => put_varint_field(&mut buf, 8, *v as i64 as u64),
+            ProtoKind::DateValue(v) => put_varint_field(&mut buf, 9, zigzag(*v as i64)),
+            ProtoKind::TimeValue(v) => put_varint_field(&mut buf, 10, *v as u64),
+            ProtoKind::TimestampValue(v) => {
+                let mut timestamp = vec![];
+
+                if v.seconds != 0 {
+                    put_varint_field(&mut timestamp, 1, v.seconds as u64);
+                }
+
+                if v.nanos != 0 {
+                    put_varint_field(&mut timestamp, 2, v.nanos as i64 as u64);
+                }
+
+                put_len_field(&mut buf, 11, &timestamp);
+            }
+            ProtoKind::UuidValue(v) => put_len_field(&mut buf, 12, v),
+        }
+
+        buf
+    }
+
+    /// Decodes a message from the protobuf wire format, skipping unknown fields.
+    ///
+    /// If more than one field of the oneof is present, the last one wins.
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let mut reader = Reader(buf);
+
+        let mut kind = None;
+
+        while let Some((field, wire_type)) = reader.key()? {
+            kind = Some(match (field, wire_type) {
+                (1, VARINT) => ProtoKind::BoolValue(reader.varint()? != 0),
+                (2, VARINT) => ProtoKind::IntValue(unzigzag(reader.varint()?)),
+                (3, LEN) => ProtoKind::BigIntValue(reader.len_field()?.to_vec()),
+                (4, FIXED64) => ProtoKind::FloatValue(f64::from_bits(reader.fixed64()?)),
+                (5, LEN) => ProtoKind::DecimalValue(utf8(reader.len_field()?)?),
+                (6, LEN) => ProtoKind::BinaryValue(reader.len_field()?.to_vec()),
+                (7, LEN) => ProtoKind::StringValue(utf8(reader.len_field()?)?),
+                (8, VARINT) => ProtoKind::NullValue(reader.varint()? as i32),
+                (9, VARINT) => ProtoKind::DateValue(unzigzag(reader.varint()?) as i32),
+                (10, VARINT) => ProtoKind::TimeValue(reader.varint()? as i64),
+                (11, LEN) => ProtoKind::TimestampValue(decode_timestamp(reader.len_field()?)?),
+                (12, LEN) => ProtoKind::UuidValue(reader.len_field()?.to_vec()),
+                _ => {
+                    reader.skip(wire_type)?;
+                    continue;
+                }
+            });
+        }
+
+        Ok(Self { kind })
+    }
+}
+
+fn decode_timestamp(buf: &[u8]) -> Result<ProtoTimestamp> {
+    let mut reader = Reader(buf);
+
+    let mut timestamp = ProtoTimestamp::default();
+
+    while let Some((field, wire_type)) = reader.key()? {
+        match (field, wire_type) {
+            (1, VARINT) => timestamp.seconds = reader.varint()? as i64,
+            (2, VARINT) => timestamp.nanos = reader.varint()? as i32,
+            _ => reader.skip(wire_type)?,
+        }
+    }
+
+    Ok(timestamp)
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn utf8(buf: &[u8]) -> Result<String> {
+    String::from_utf8(buf.to_vec()).map_err(|_| invalid_data("proto: string is not utf-8"))
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+
+    buf.push(v as u8);
+}
+
+fn put_key(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    put_varint(buf, field << 3 | wire_type);
+}
+
+fn put_varint_field(buf: &mut Vec<u8>, field: u64, v: u64) {
+    put_key(buf, field, VARINT);
+    put_varint(buf, v);
+}
+
+fn put_len_field(buf: &mut Vec<u8>, field: u64, v: &[u8]) {
+    put_key(buf, field, LEN);
+    put_varint(buf, v.len() as u64);
+    buf.extend_from_slice(v);
+}
+
+/// Reads the fields of an encoded message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Returns the field number and wire type of the next field, `None` at the end of the message.
+    fn key(&mut self) -> Result<Option<(u64, u64)>> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+
+        Ok(Some((key >> 3, key & 0x7)))
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut v = 0;
+
+        for (i, byte) in self.0.iter().enumerate().take(10) {
+            v |= ((byte & 0x7f) as u64) << (7 * i);
+
+            if byte & 0x80 == 0 {
+                self.0 = &self.0[i + 1..];
+                return Ok(v);
+            }
+        }
+
+        Err(invalid_data("proto: invalid varint"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_data("proto: truncated message"));
+        }
+
+        let (head, tail) = self.0.split_at(len);
+
+        self.0 = tail;
+
+        Ok(head)
+    }
+
+    fn fixed64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len_field(&mut self) -> Result<&'a [u8]> {
+        let len = usize::try_from(self.varint()?)
+            .map_err(|_| invalid_data("proto: truncated message"))?;
+
+        self.take(len)
+    }
+
+    fn skip(&mut self, wire_type: u64) -> Result<()> {
+        match wire_type {
+            VARINT => _ = self.varint()?,
+            FIXED64 => _ = self.take(8)?,
+            LEN => _ = self.len_field()?,
+            FIXED32 => _ = self.take(4)?,
+            _ => {
+                return Err(invalid_data(format!(
+                    "proto: unsupported wire type {}",
+                    wire_type
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: SqlValue<'static>) -> SqlValue<'static> {
+        let buf = value.to_proto().encode_to_vec();
+
+        SqlValue::from_proto(ProtoSqlValue::decode(&buf).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let values = [
+            SqlValue::Bool(false),
+            SqlValue::Bool(true),
+            SqlValue::Int(0),
+            SqlValue::Int(-1),
+            SqlValue::Int(i64::MIN),
+            SqlValue::Int(i64::MAX),
+            SqlValue::BigInt(i128::MIN),
+            SqlValue::BigInt(-1),
+            SqlValue::Float(-0.5),
+            SqlValue::Float(f64::MAX),
+            SqlValue::Binary(vec![].into()),
+            SqlValue::Binary(vec![0, 255, 1].into()),
+            SqlValue::String("".into()),
+            SqlValue::String("héllo".into()),
+            SqlValue::Null,
+        ];
+
+        for value in values {
+            assert_eq!(round_trip(value.as_ref().into_owned()), value);
+        }
+
+        // non-finite floats keep their bits rather than being stringified.
+        assert!(matches!(round_trip(SqlValue::Float(f64::NAN)), SqlValue::Float(v) if v.is_nan()));
+    }
+
+    #[cfg(feature = "with-decimal")]
+    #[test]
+    fn test_round_trip_decimal() {
+        for text in ["0", "-12.50", "123456789012345678901234567890.000001"] {
+            let value = SqlValue::Decimal(text.parse().unwrap());
+
+            assert_eq!(
+                value.to_proto().kind,
+                Some(ProtoKind::DecimalValue(text.to_owned()))
+            );
+
+            match round_trip(value) {
+                SqlValue::Decimal(v) => assert_eq!(v.to_string(), text),
+                value => panic!("expect decimal, got {:?}", value),
+            }
+        }
+    }
+
+    #[cfg(feature = "with-chrono")]
+    #[test]
+    fn test_round_trip_temporal() {
+        let values = [
+            SqlValue::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+            SqlValue::Date(NaiveDate::from_ymd_opt(1969, 12, 31).unwrap()),
+            SqlValue::Date(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()),
+            SqlValue::Time(NaiveTime::from_hms_nano_opt(0, 0, 0, 0).unwrap()),
+            SqlValue::Time(NaiveTime::from_hms_nano_opt(23, 59, 59, 999_999_999).unwrap()),
+            SqlValue::Timestamp(DateTime::from_timestamp(-1, 500).unwrap()),
+            SqlValue::Timestamp(DateTime::from_timestamp(1_700_000_000, 123_456_789).unwrap()),
+        ];
+
+        for value in values {
+            assert_eq!(round_trip(value.as_ref().into_owned()), value);
+        }
+
+        assert_eq!(
+            SqlValue::Date(NaiveDate::from_ymd_opt(1969, 12, 31).unwrap())
+                .to_proto()
+                .kind,
+            Some(ProtoKind::DateValue(-1))
+        );
+    }
+
+    #[cfg(feature = "with-uuid")]
+    #[test]
+    fn test_round_trip_uuid() {
+        let value = SqlValue::Uuid(uuid::Uuid::from_u128(0x0123_4567_89ab_cdef));
+
+        assert_eq!(round_trip(value.as_ref().into_owned()), value);
+    }
+
+    #[test]
+    fn test_wire_format() {
+        assert_eq!(SqlValue::Int(-1).to_proto().encode_to_vec(), [0x10, 0x01]);
+        assert_eq!(
+            SqlValue::String("hi".into()).to_proto().encode_to_vec(),
+            [0x3a, 0x02, b'h', b'i']
+        );
+        assert_eq!(SqlValue::Null.to_proto().encode_to_vec(), [0x40, 0x00]);
+
+        // unknown fields are skipped.
+        let value = ProtoSqlValue::decode(&[0x98, 0x06, 0x01, 0x08, 0x01]).unwrap();
+
+        assert_eq!(value.kind, Some(ProtoKind::BoolValue(true)));
+
+        let err = SqlValue::from_proto(ProtoSqlValue::decode(&[]).unwrap()).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert!(ProtoSqlValue::decode(&[0x3a, 0x05, b'h']).is_err());
+        assert!(SqlValue::from_proto(ProtoSqlValue {
+            kind: Some(ProtoKind::BigIntValue(vec![1]))
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Debug,
+    hash::Hash,
+    io::{self, Result},
+    ops::Index,
+    sync::Arc,
+};
+
+use futures::{stream, Stream};
+use rasi::utils::cancelable_would_block;
+
+use crate::{ColumnType, DbConn, ResultSet, SqlValue, Stmt};
+
+/// The column names and types of a result set with an index by name, shared by the rows read from it.
+pub(crate) struct Columns {
+    names: Vec<String>,
+    types: Vec<ColumnType<'static>>,
+    /// Column name to col number, the first column wins if several have the same name.
+    index: HashMap<String, usize>,
+}
+
+impl Columns {
+    pub(crate) fn new(types: Vec<ColumnType<'static>>) -> Self {
+        let names: Vec<String> = types.iter().map(|t| t.name.to_string()).collect();
+
+        let mut index = HashMap::with_capacity(names.len());
+
+        for (offset, name) in names.iter().enumerate() {
+            index.entry(name.clone()).or_insert(offset);
+        }
+
+        Self {
+            names,
+            types,
+            index,
+        }
+    }
+
+    /// Returns the col number of `col_name`, or [`NotFound`](io::ErrorKind::NotFound) error.
+    pub(crate) fn offset(&self, col_name: &str) -> Result<usize> {
+        self.index.get(col_name).copied().ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Unknown column name or alias: {}", col_name),
+        ))
+    }
+}
+
+/// A type that can be built from a [`Row`], see [`ResultSet::collect`].
+///
+/// With the `with-derive` feature, `#[derive(FromRow)]` implements it for a struct
+/// by reading each field from the column of the same name.
+pub trait FromRow: Sized {
+    /// Builds a value from the columns of `row`.
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+/// A snapshot of one row of a [`ResultSet`], returned by [`ResultSet::next_row`].
+///
+/// The row owns its values, so it stays readable after the cursor advances.
+pub struct Row {
+    columns: Arc<Columns>,
+    values: Vec<SqlValue<'static>>,
+}
+
+impl Row {
+    /// Returns the column names, aliased as by [`ResultSet::columns`].
+    pub fn columns(&self) -> &[String] {
+        &self.columns.names
+    }
+
+    /// Returns the `(type, value)` of each column, in column order.
+    ///
+    /// The column types are read once per result set with the first row, see [`ResultSet::column_types`].
+    pub fn cells(&self) -> impl Iterator<Item = (&ColumnType<'static>, &SqlValue<'static>)> {
+        self.columns.types.iter().zip(&self.values)
+    }
+
+    /// Returns the values of this row, in column order.
+    pub fn values(&self) -> &[SqlValue<'static>] {
+        &self.values
+    }
+
+    /// Consumes this row and returns its values, in column order.
+    pub fn into_values(self) -> Vec<SqlValue<'static>> {
+        self.values
+    }
+
+    /// Converts the value at col number `col` into `T`, see [`ResultSet::get_as`].
+    ///
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if `col` is out of range.
+    pub fn get<T: TryFrom<SqlValue<'static>, Error = io::Error>>(&self, col: usize) -> Result<T> {
+        let value = self.values.get(col).ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Column {} out of range, the row has {} columns",
+                col,
+                self.values.len()
+            ),
+        ))?;
+
+        T::try_from(value.as_ref().into_owned())
+    }
+
+    /// Converts the value of column `col_name` into `T`, the name may be a column alias.
+    pub fn get_by_name<T: TryFrom<SqlValue<'static>, Error = io::Error>>(
+        &self,
+        col_name: &str,
+    ) -> Result<T> {
+        self.get(self.columns.offset(col_name)?)
+    }
+}
+
+/// Returns the value at col number `col`.
+///
+/// # Panics
+///
+/// Panics if `col` is out of range, use [`Row::get`] to handle it as an error.
+impl Index<usize> for Row {
+    type Output = SqlValue<'static>;
+
+    fn index(&self, col: usize) -> &Self::Output {
+        &self.values[col]
+    }
+}
+
+/// Returns the value of column `col_name`, the name may be a column alias.
+///
+/// # Panics
+///
+/// Panics if there is no such column, use [`Row::get_by_name`] to handle it as an error.
+impl Index<&str> for Row {
+    type Output = SqlValue<'static>;
+
+    fn index(&self, col_name: &str) -> &Self::Output {
+        match self.columns.offset(col_name) {
+            Ok(col) => &self.values[col],
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl ResultSet {
+    /// Advances to the next row and reads all of its values, returns `Ok(None)` at the end of the result set.
+    pub async fn next_row(&self) -> Result<Option<Row>> {
+        if !self.next().await? {
+            return Ok(None);
+        }
+
+        let columns = self.indexed_columns().await?;
+
+        let mut values = Vec::with_capacity(columns.names.len());
+
+        for col in 0..columns.names.len() {
+            values.push(self.get(col).await?);
+        }
+
+        Ok(Some(Row { columns, values }))
+    }
+
+    /// Returns a stream of the remaining rows, each row is read by [`next_row`](ResultSet::next_row)
+    /// when the stream is polled for it.
+    ///
+    /// The stream ends after the last row or after the first error.
+    pub fn rows(&self) -> impl Stream<Item = Result<Row>> + '_ {
+        stream::unfold(Some(self), |result_set| async move {
+            let result_set = result_set?;
+
+            match result_set.next_row().await {
+                Ok(Some(row)) => Some((Ok(row), Some(result_set))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Reads the remaining rows and converts each one into `T` with [`FromRow`].
+    pub async fn collect<T: FromRow>(&self) -> Result<Vec<T>> {
+        let mut rows = vec![];
+
+        while let Some(row) = self.next_row().await? {
+            rows.push(T::from_row(&row)?);
+        }
+
+        Ok(rows)
+    }
+
+    /// Reads the remaining rows into any collection of [`FromRow`] values,
+    /// e.g. `let names: BTreeSet<Name> = rs.collect_into().await?;`.
+    pub async fn collect_into<C, R>(self) -> Result<C>
+    where
+        C: Default + Extend<R>,
+        R: FromRow,
+    {
+        let mut collection = C::default();
+
+        while let Some(row) = self.next_row().await? {
+            collection.extend(Some(R::from_row(&row)?));
+        }
+
+        Ok(collection)
+    }
+
+    /// Reads the remaining rows into a map from the value at col number `key_col` to the value at `val_col`,
+    /// e.g. `let names: HashMap<i64, String> = rs.into_keyed_map(0, 1).await?;`.
+    ///
+    /// Returns [`InvalidData`](io::ErrorKind::InvalidData) error if two rows have the same key.
+    pub async fn into_keyed_map<K, V>(self, key_col: usize, val_col: usize) -> Result<HashMap<K, V>>
+    where
+        K: TryFrom<SqlValue<'static>, Error = io::Error> + Eq + Hash + Debug,
+        V: TryFrom<SqlValue<'static>, Error = io::Error>,
+    {
+        let mut map = HashMap::new();
+
+        while let Some(row) = self.next_row().await? {
+            match map.entry(row.get::<K>(key_col)?) {
+                Entry::Occupied(entry) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Duplicate key {:?} in column {}", entry.key(), key_col),
+                    ))
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(row.get::<V>(val_col)?);
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl Stmt {
+    /// Runs the statement with `values` and returns both the `last_insert_id` and `rows_affected`,
+    /// as by [`exec`](Stmt::exec), and the rows it returned.
+    ///
+    /// This is the way to run an `INSERT`, `UPDATE` or `DELETE` with a `RETURNING` clause,
+    /// [`exec`](Stmt::exec) discards the returned rows and [`query`](Stmt::query) the affected count.
+    pub async fn exec_query(&self, values: &[SqlValue<'_>]) -> Result<((i64, i64), Vec<Row>)> {
+        let result_set = self.query(values).await?;
+
+        let mut rows = vec![];
+
+        while let Some(row) = result_set.next_row().await? {
+            rows.push(row);
+        }
+
+        let result = cancelable_would_block(|cx| {
+            self.database
+                .poll_query_result(cx, &result_set.result_set_handle)
+        })
+        .await?;
+
+        Ok((result, rows))
+    }
+}
+
+/// The state of [`DbConn::into_row_stream`].
+enum RowStream<Q> {
+    Start(DbConn, Q, Vec<SqlValue<'static>>),
+    /// The connection and statement are kept alive until the stream is dropped.
+    Rows(DbConn, Stmt, ResultSet),
+}
+
+impl DbConn {
+    /// Prepares `query`, runs it with `values` and returns a stream of its rows which owns the connection,
+    /// so unlike [`ResultSet::rows`] it can be returned from the function that opened the connection.
+    ///
+    /// The query is only run when the stream is first polled, the stream ends after the last row or after the first error.
+    pub fn into_row_stream<Q: AsRef<str>>(
+        self,
+        query: Q,
+        values: Vec<SqlValue<'static>>,
+    ) -> impl Stream<Item = Result<Row>> {
+        stream::unfold(
+            Some(RowStream::Start(self, query, values)),
+            |state| async move {
+                let (conn, stmt, result_set) = match state? {
+                    RowStream::Start(conn, query, values) => {
+                        let stmt = match conn.prepare(query).await {
+                            Ok(stmt) => stmt,
+                            Err(err) => return Some((Err(err), None)),
+                        };
+
+                        match stmt.query(&values).await {
+                            Ok(result_set) => (conn, stmt, result_set),
+                            Err(err) => return Some((Err(err), None)),
+                        }
+                    }
+                    RowStream::Rows(conn, stmt, result_set) => (conn, stmt, result_set),
+                };
+
+                match result_set.next_row().await {
+                    Ok(Some(row)) => Some((Ok(row), Some(RowStream::Rows(conn, stmt, result_set)))),
+                    Ok(None) => None,
+                    Err(err) => Some((Err(err), None)),
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mock::MockDatabase;
+
+    struct User {
+        id: i64,
+        name: Option<String>,
+    }
+
+    impl FromRow for User {
+        fn from_row(row: &Row) -> Result<Self> {
+            Ok(Self {
+                id: row.get_by_name("id")?,
+                name: row.get_by_name("name")?,
+            })
+        }
+    }
+
+    #[test]
+    fn test_collect() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name FROM user",
+                    &["id", "name"],
+                    vec![
+                        vec![SqlValue::Int(1), "alice".into()],
+                        vec![SqlValue::Int(2), SqlValue::Null],
+                    ],
+                )
+                .connect();
+
+            let rs = conn
+                .prepare("SELECT id, name FROM user")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let users = rs.collect::<User>().await.unwrap();
+
+            assert_eq!(users.len(), 2);
+            assert_eq!((users[0].id, users[0].name.as_deref()), (1, Some("alice")));
+            assert_eq!((users[1].id, users[1].name.as_deref()), (2, None));
+        });
+    }
+
+    #[test]
+    fn test_rows() {
+        use futures::TryStreamExt;
+
+        block_on(async {
+            let rows = (0..10_000)
+                .map(|id| vec![SqlValue::Int(id), SqlValue::Float(id as f64)])
+                .collect();
+
+            let conn = MockDatabase::default()
+                .fixture("SELECT id, score FROM t", &["id", "score"], rows)
+                .connect();
+
+            let rs = conn
+                .prepare("SELECT id, score FROM t")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let sum = rs
+                .rows()
+                .try_fold(0, |sum, row| async move { Ok(sum + row.get::<i64>(0)?) })
+                .await
+                .unwrap();
+
+            assert_eq!(sum, (0..10_000).sum::<i64>());
+
+            // the result set is exhausted.
+            assert!(rs.rows().try_collect::<Vec<_>>().await.unwrap().is_empty());
+
+            let rs = conn
+                .prepare("SELECT id, score FROM t")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let err = rs
+                .rows()
+                .try_for_each(|row| async move { row.get::<String>(1).map(drop) })
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    fn test_next_row() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name FROM user",
+                    &["id", "name"],
+                    vec![
+                        vec![SqlValue::Int(1), "alice".into()],
+                        vec![SqlValue::Int(2), SqlValue::Null],
+                    ],
+                )
+                .connect();
+
+            let rs = conn
+                .prepare("SELECT id, name FROM user")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let mut rows = vec![];
+
+            while let Some(row) = rs.next_row().await.unwrap() {
+                rows.push(row);
+            }
+
+            assert!(rs.next_row().await.unwrap().is_none());
+
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].columns(), ["id", "name"]);
+
+            assert_eq!(rows[0].get::<i64>(0).unwrap(), 1);
+            assert_eq!(rows[0].get_by_name::<String>("name").unwrap(), "alice");
+            assert_eq!(rows[1].get_by_name::<i64>("id").unwrap(), 2);
+            assert_eq!(rows[1].get_by_name::<Option<String>>("name").unwrap(), None);
+
+            assert_eq!(
+                rows[1].get::<i64>(2).err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+            assert_eq!(
+                rows[1].get_by_name::<i64>("email").err().unwrap().kind(),
+                io::ErrorKind::NotFound
+            );
+
+            assert_eq!(
+                rows.pop().unwrap().into_values(),
+                [SqlValue::Int(2), SqlValue::Null]
+            );
+        });
+    }
+
+    fn col_type(name: &str, database_type_name: &str) -> ColumnType<'static> {
+        ColumnType {
+            database_type_name: database_type_name.to_owned().into(),
+            decimal_size: None,
+            length: None,
+            name: name.to_owned().into(),
+            nullable: None,
+        }
+    }
+
+    fn user_row() -> Row {
+        Row {
+            columns: Arc::new(Columns::new(vec![
+                col_type("id", "INTEGER"),
+                col_type("name", "TEXT"),
+            ])),
+            values: vec![SqlValue::Int(1), "alice".into()],
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        let row = user_row();
+
+        assert_eq!(row[0], SqlValue::Int(1));
+        assert_eq!(row[1], SqlValue::from("alice"));
+        assert_eq!(row["id"], SqlValue::Int(1));
+        assert_eq!(row["name"], row[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_index_out_of_range() {
+        let _ = &user_row()[2];
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown column name or alias: email")]
+    fn test_index_unknown_column() {
+        let _ = &user_row()["email"];
+    }
+
+    #[test]
+    fn test_cells() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name, score FROM user",
+                    &["id", "name", "score"],
+                    vec![vec![SqlValue::Int(1), "alice".into(), SqlValue::Null]],
+                )
+                .declared_types(
+                    "SELECT id, name, score FROM user",
+                    &["INTEGER", "TEXT", "REAL"],
+                )
+                .connect();
+
+            let rs = conn
+                .prepare("SELECT id, name, score FROM user")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let row = rs.next_row().await.unwrap().unwrap();
+
+            let cells = row
+                .cells()
+                .map(|(col_type, value)| {
+                    (
+                        col_type.name.as_ref(),
+                        col_type.database_type_name.as_ref(),
+                        value,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                cells,
+                [
+                    ("id", "INTEGER", &SqlValue::Int(1)),
+                    ("name", "TEXT", &SqlValue::from("alice")),
+                    ("score", "REAL", &SqlValue::Null),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_collect_into() {
+        use std::collections::BTreeSet;
+
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Name(String);
+
+        impl FromRow for Name {
+            fn from_row(row: &Row) -> Result<Self> {
+                row.get_by_name("name").map(Name)
+            }
+        }
+
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT name FROM user",
+                    &["name"],
+                    vec![vec!["bob".into()], vec!["alice".into()], vec!["bob".into()]],
+                )
+                .connect();
+
+            let query = || async {
+                conn.prepare("SELECT name FROM user")
+                    .await
+                    .unwrap()
+                    .query(&[])
+                    .await
+                    .unwrap()
+            };
+
+            let names: Vec<Name> = query().await.collect_into().await.unwrap();
+
+            assert_eq!(
+                names,
+                [Name("bob".into()), Name("alice".into()), Name("bob".into())]
+            );
+
+            let names: BTreeSet<Name> = query().await.collect_into().await.unwrap();
+
+            assert_eq!(
+                names.into_iter().collect::<Vec<_>>(),
+                [Name("alice".into()), Name("bob".into())]
+            );
+        });
+    }
+
+    #[test]
+    fn test_into_keyed_map() {
+        block_on(async {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT id, name FROM user",
+                    &["id", "name"],
+                    vec![
+                        vec![SqlValue::Int(1), "alice".into()],
+                        vec![SqlValue::Int(2), "bob".into()],
+                    ],
+                )
+                .fixture(
+                    "SELECT team, name FROM user",
+                    &["team", "name"],
+                    vec![
+                        vec![SqlValue::Int(1), "alice".into()],
+                        vec![SqlValue::Int(1), "bob".into()],
+                    ],
+                )
+                .connect();
+
+            let query = |sql| {
+                let conn = &conn;
+
+                async move { conn.prepare(sql).await.unwrap().query(&[]).await.unwrap() }
+            };
+
+            let names = query("SELECT id, name FROM user")
+                .await
+                .into_keyed_map::<i64, String>(0, 1)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                names,
+                HashMap::from([(1, "alice".to_owned()), (2, "bob".to_owned())])
+            );
+
+            let err = query("SELECT team, name FROM user")
+                .await
+                .into_keyed_map::<i64, String>(0, 1)
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    fn test_into_row_stream() {
+        use futures::TryStreamExt;
+
+        fn user_names() -> impl Stream<Item = Result<Row>> + 'static {
+            let conn = MockDatabase::default()
+                .fixture(
+                    "SELECT name FROM user WHERE id > ?",
+                    &["name"],
+                    vec![vec!["alice".into()], vec!["bob".into()]],
+                )
+                .connect();
+
+            conn.into_row_stream("SELECT name FROM user WHERE id > ?", vec![SqlValue::Int(0)])
+        }
+
+        block_on(async {
+            let names = user_names()
+                .and_then(|row| async move { row.get::<String>(0) })
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+
+            assert_eq!(names, ["alice", "bob"]);
+
+            let conn = MockDatabase::default().connect();
+
+            let err = conn
+                .into_row_stream("SELECT name FROM missing", vec![])
+                .try_collect::<Vec<_>>()
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        });
+    }
+}
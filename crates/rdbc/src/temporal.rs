@@ -0,0 +1,68 @@
+//! Date and time values, see [`SqlValue::Date`], [`SqlValue::Time`] and [`SqlValue::Timestamp`].
+//!
+//! Drivers without native temporal types store them as ISO 8601 text in the formats below,
+//! so that values written by one driver read back the same in another.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+
+use crate::SqlValue;
+
+/// The text format of [`SqlValue::Date`], e.g. `2024-02-29`.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The text format of [`SqlValue::Time`], e.g. `13:45:00` or `13:45:00.250`.
+///
+/// The fraction of the second is omitted if it is zero.
+pub const TIME_FORMAT: &str = "%H:%M:%S%.f";
+
+/// The text format of [`SqlValue::Timestamp`], e.g. `2024-02-29T13:45:00Z` or `2024-02-29T13:45:00.250Z`.
+///
+/// The fraction of the second is omitted if it is zero.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.fZ";
+
+impl From<NaiveDate> for SqlValue<'static> {
+    fn from(value: NaiveDate) -> Self {
+        SqlValue::Date(value)
+    }
+}
+
+impl From<NaiveTime> for SqlValue<'static> {
+    fn from(value: NaiveTime) -> Self {
+        SqlValue::Time(value)
+    }
+}
+
+impl From<DateTime<Utc>> for SqlValue<'static> {
+    fn from(value: DateTime<Utc>) -> Self {
+        SqlValue::Timestamp(value)
+    }
+}
+
+/// Returns the ISO 8601 text of a temporal value, or `None` for other variants.
+pub(crate) fn format_temporal(value: &SqlValue<'_>) -> Option<String> {
+    match value {
+        SqlValue::Date(v) => Some(v.format(DATE_FORMAT).to_string()),
+        SqlValue::Time(v) => Some(v.format(TIME_FORMAT).to_string()),
+        SqlValue::Timestamp(v) => Some(v.format(TIMESTAMP_FORMAT).to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_temporal() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        let time = NaiveTime::from_hms_milli_opt(13, 45, 0, 250).unwrap();
+
+        assert_eq!(format_temporal(&date.into()).unwrap(), "2024-02-29");
+        assert_eq!(format_temporal(&time.into()).unwrap(), "13:45:00.250");
+        assert_eq!(
+            format_temporal(&date.and_hms_opt(13, 45, 0).unwrap().and_utc().into()).unwrap(),
+            "2024-02-29T13:45:00Z"
+        );
+        assert!(format_temporal(&SqlValue::Int(1)).is_none());
+    }
+}
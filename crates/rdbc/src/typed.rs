@@ -0,0 +1,148 @@
+use std::{io::Result, marker::PhantomData};
+
+use crate::{DbConn, FromRow, ResultSet, SqlValue, Stmt};
+
+/// A type that can be bound as the parameters of a statement, see [`TypedStmt`].
+///
+/// It is implemented for tuples of up to 8 values convertible into [`SqlValue`],
+/// e.g. `(i64, &str)`, for `()` and for `Vec<SqlValue>`.
+pub trait ToRow<'a> {
+    /// Returns the values of the parameters, in placeholder order.
+    fn to_row(self) -> Vec<SqlValue<'a>>;
+}
+
+impl<'a> ToRow<'a> for () {
+    fn to_row(self) -> Vec<SqlValue<'a>> {
+        vec![]
+    }
+}
+
+impl<'a> ToRow<'a> for Vec<SqlValue<'a>> {
+    fn to_row(self) -> Vec<SqlValue<'a>> {
+        self
+    }
+}
+
+macro_rules! tuple_to_row {
+    ($(($($t:ident),+)),*) => {
+        $(
+            impl<'a, $($t: Into<SqlValue<'a>>),+> ToRow<'a> for ($($t,)+) {
+                #[allow(non_snake_case)]
+                fn to_row(self) -> Vec<SqlValue<'a>> {
+                    let ($($t,)+) = self;
+
+                    vec![$($t.into()),+]
+                }
+            }
+        )*
+    };
+}
+
+tuple_to_row!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H)
+);
+
+/// A prepared statement whose parameters are a `P` and whose rows are read as `R`,
+/// created by [`DbConn::prepare_typed`].
+///
+/// This is a thin layer over [`Stmt`], the values are converted with [`ToRow`] and the rows with [`FromRow`].
+pub struct TypedStmt<P, R> {
+    stmt: Stmt,
+    _marker: PhantomData<fn(P) -> R>,
+}
+
+impl<P, R> TypedStmt<P, R> {
+    /// Executes the statement with `params`, see [`Stmt::exec`].
+    ///
+    /// On success, returns the `last_insert_id` and `rows_affected`.
+    pub async fn exec<'a>(&self, params: P) -> Result<(i64, i64)>
+    where
+        P: ToRow<'a>,
+    {
+        self.stmt.exec(&params.to_row()).await
+    }
+
+    /// Returns the untyped statement.
+    pub fn into_inner(self) -> Stmt {
+        self.stmt
+    }
+}
+
+impl<P, R: FromRow> TypedStmt<P, R> {
+    /// Runs the statement with `params` and reads all rows as `R`, see [`Stmt::query`].
+    pub async fn query<'a>(&self, params: P) -> Result<Vec<R>>
+    where
+        P: ToRow<'a>,
+    {
+        self.stmt.query(&params.to_row()).await?.collect().await
+    }
+
+    /// Runs the statement with `params` and returns the first row as `R`, or `None` if there is no row.
+    pub async fn query_one<'a>(&self, params: P) -> Result<Option<R>>
+    where
+        P: ToRow<'a>,
+    {
+        match self.stmt.query(&params.to_row()).await?.next_row().await? {
+            Some(row) => R::from_row(&row).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl DbConn {
+    /// Creates a prepared statement which binds a `P` as its parameters and reads its rows as `R`,
+    /// e.g. `conn.prepare_typed::<(i64,), User>("SELECT * FROM user WHERE id = ?")`.
+    ///
+    /// The types are not checked against the query, a mismatch is reported by the first execution.
+    /// `R` is only required to implement [`FromRow`] to read rows, use `()` for a statement that is only executed.
+    pub async fn prepare_typed<P, R>(&self, query: &str) -> Result<TypedStmt<P, R>> {
+        Ok(TypedStmt {
+            stmt: self.prepare(query).await?,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// A query together with the values of its parameters, built by the `sql!` macro of the `with-derive` feature,
+/// e.g. `sql!("SELECT * FROM user WHERE id = ?", id).query(&conn).await?`.
+///
+/// The macro checks that the number of values matches the number of parameters of the query.
+#[derive(Debug)]
+pub struct SqlQuery<'a> {
+    sql: &'static str,
+    values: Vec<SqlValue<'a>>,
+}
+
+impl<'a> SqlQuery<'a> {
+    /// Creates a query with `values`, the number of values is not checked.
+    pub fn new(sql: &'static str, values: Vec<SqlValue<'a>>) -> Self {
+        Self { sql, values }
+    }
+
+    /// Returns the sql of the query.
+    pub fn sql(&self) -> &'static str {
+        self.sql
+    }
+
+    /// Returns the values of the parameters, in parameter order.
+    pub fn values(&self) -> &[SqlValue<'a>] {
+        &self.values
+    }
+
+    /// Executes the query once on `conn`, see [`DbConn::execute`].
+    pub async fn execute(&self, conn: &DbConn) -> Result<(i64, i64)> {
+        conn.execute(self.sql, &self.values).await
+    }
+
+    /// Runs the query once on `conn`, see [`DbConn::query_one_shot`].
+    pub async fn query(&self, conn: &DbConn) -> Result<ResultSet> {
+        conn.query_one_shot(self.sql, &self.values).await
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Result};
+
+use crate::{Database, DbConn, SqlValue};
+
+impl DbConn {
+    /// Inserts a row into `table`, or updates the existing row with the same `keys`.
+    ///
+    /// `values` are the column names and values of the row, `keys` names the columns of the
+    /// unique constraint, and `update_on_conflict` names the columns which take the new values
+    /// of a conflicting row. If `update_on_conflict` is empty, a conflicting row is left unchanged.
+    ///
+    /// If the driver supports [`upsert_on_conflict`](crate::Capabilities::upsert_on_conflict),
+    /// this runs `INSERT ... ON CONFLICT (keys) DO UPDATE`. Otherwise it runs `INSERT OR REPLACE`,
+    /// which deletes the conflicting row and inserts the new one, so the columns not in `values`
+    /// are reset to their defaults rather than kept, whatever `update_on_conflict` is.
+    ///
+    /// On success, returns the `last_insert_id` and `rows_affected`, see [`Stmt::exec`](crate::Stmt::exec).
+    pub async fn upsert(
+        &self,
+        table: &str,
+        keys: &[&str],
+        values: &[(&str, SqlValue<'_>)],
+        update_on_conflict: &[&str],
+    ) -> Result<(i64, i64)> {
+        let columns = values.iter().map(|(col, _)| *col).collect::<Vec<_>>();
+
+        let query = upsert_query(
+            &**self.database,
+            table,
+            keys,
+            &columns,
+            update_on_conflict,
+            self.capabilities().upsert_on_conflict,
+        )?;
+
+        let values = values
+            .iter()
+            .map(|(_, value)| value.as_ref())
+            .collect::<Vec<_>>();
+
+        self.execute(query, &values).await
+    }
+}
+
+/// Returns the query of [`DbConn::upsert`], `on_conflict` selects the `ON CONFLICT` clause over `INSERT OR REPLACE`.
+fn upsert_query(
+    database: &dyn Database,
+    table: &str,
+    keys: &[&str],
+    columns: &[&str],
+    update_on_conflict: &[&str],
+    on_conflict: bool,
+) -> Result<String> {
+    if keys.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "upsert: no key column",
+        ));
+    }
+
+    if let Some(col) = keys
+        .iter()
+        .chain(update_on_conflict)
+        .find(|col| !columns.contains(col))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("upsert: column {} has no value", col),
+        ));
+    }
+
+    let insert = format!(
+        "INTO {} ({}) VALUES ({})",
+        database.quote_identifier(table),
+        columns
+            .iter()
+            .map(|col| database.quote_identifier(col))
+            .collect::<Vec<_>>()
+            .join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+
+    if !on_conflict {
+        let verb = if update_on_conflict.is_empty() {
+            "INSERT OR IGNORE"
+        } else {
+            "INSERT OR REPLACE"
+        };
+
+        return Ok(format!("{} {}", verb, insert));
+    }
+
+    let action = if update_on_conflict.is_empty() {
+        "DO NOTHING".to_owned()
+    } else {
+        format!(
+            "DO UPDATE SET {}",
+            update_on_conflict
+                .iter()
+                .map(|col| format!("{0} = excluded.{0}", database.quote_identifier(col)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+
+    Ok(format!(
+        "INSERT {} ON CONFLICT ({}) {}",
+        insert,
+        keys.iter()
+            .map(|col| database.quote_identifier(col))
+            .collect::<Vec<_>>()
+            .join(", "),
+        action
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::mock::MockDatabase;
+
+    use super::*;
+
+    #[test]
+    fn test_upsert_query() {
+        let database = MockDatabase::default();
+
+        assert_eq!(
+            upsert_query(&database, "kv", &["k"], &["k", "v"], &["v"], true).unwrap(),
+            r#"INSERT INTO "kv" ("k", "v") VALUES (?, ?) ON CONFLICT ("k") DO UPDATE SET "v" = excluded."v""#
+        );
+
+        assert_eq!(
+            upsert_query(&database, "kv", &["k"], &["k", "v"], &[], true).unwrap(),
+            r#"INSERT INTO "kv" ("k", "v") VALUES (?, ?) ON CONFLICT ("k") DO NOTHING"#
+        );
+
+        assert_eq!(
+            upsert_query(&database, "k\"v", &["k"], &["k", "v"], &["v"], false).unwrap(),
+            r#"INSERT OR REPLACE INTO "k""v" ("k", "v") VALUES (?, ?)"#
+        );
+
+        assert_eq!(
+            upsert_query(&database, "kv", &["k"], &["k", "v"], &[], false).unwrap(),
+            r#"INSERT OR IGNORE INTO "kv" ("k", "v") VALUES (?, ?)"#
+        );
+
+        assert!(upsert_query(&database, "kv", &[], &["k", "v"], &["v"], true).is_err());
+        assert!(upsert_query(&database, "kv", &["id"], &["k", "v"], &["v"], true).is_err());
+        assert!(upsert_query(&database, "kv", &["k"], &["k", "v"], &["w"], true).is_err());
+    }
+
+    #[test]
+    fn test_upsert_fallback() {
+        let conn = MockDatabase::default().connect();
+
+        assert!(!conn.capabilities().upsert_on_conflict);
+
+        block_on(async {
+            assert_eq!(
+                conn.upsert("kv", &["k"], &[("k", 1.into()), ("v", "a".into())], &["v"])
+                    .await
+                    .unwrap(),
+                (1, 1)
+            );
+        });
+    }
+}
@@ -0,0 +1,23 @@
+use std::env;
+
+fn main() {
+    // the bundled amalgamation is compiled and linked by `sqlite3-src`.
+    if env::var_os("CARGO_FEATURE_SYSTEM").is_none()
+        || env::var_os("CARGO_FEATURE_BUNDLED").is_some()
+    {
+        return;
+    }
+
+    let library = if env::var_os("CARGO_FEATURE_SQLCIPHER").is_some() {
+        "sqlcipher"
+    } else {
+        "sqlite3"
+    };
+
+    if let Err(err) = pkg_config::probe_library(library) {
+        panic!(
+            "feature `system` requires a {} library found by pkg-config: {}",
+            library, err
+        );
+    }
+}
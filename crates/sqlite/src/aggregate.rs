@@ -0,0 +1,401 @@
+use std::{
+    borrow::Cow,
+    ffi::{c_int, c_void, CString},
+    fmt::Debug,
+    io,
+    mem::size_of,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr::null_mut,
+    slice::from_raw_parts,
+    sync::{Arc, Mutex},
+};
+
+use rinq_rdbc::SqlValue;
+use sqlite3_sys as ffi;
+
+use crate::{sqlite_transient, to_io_error, DbConn, Sqlite};
+
+/// A custom aggregate function, see [`Sqlite::create_aggregate`].
+///
+/// Each aggregation, e.g. each group of a `GROUP BY`, starts with a clone of the registered prototype,
+/// calls [`step`](Self::step) once per row and ends with [`finalize`](Self::finalize).
+pub trait AggregateImpl: Send + 'static {
+    /// Add the arguments of one row to the aggregation.
+    fn step(&mut self, args: &[SqlValue<'_>]);
+
+    /// Returns the result of the aggregation, the prototype is finalized if there are no rows.
+    fn finalize(self) -> SqlValue<'static>;
+}
+
+impl Sqlite {
+    /// Register the aggregate function `name` with `arg_count` arguments on each connection opened by this driver,
+    /// `-1` accepts any number of arguments.
+    ///
+    /// Each aggregation starts with a clone of `prototype`. Registering a name again with the same
+    /// `arg_count` replaces the function, which can also replace the built-ins, e.g. `sum`.
+    ///
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if `name` contains a nul byte
+    /// or `arg_count` is not in the range `-1..=127`.
+    pub fn create_aggregate<A: AggregateImpl + Clone>(
+        mut self,
+        name: &str,
+        arg_count: i32,
+        prototype: A,
+    ) -> io::Result<Self> {
+        let c_name = CString::new(name).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("sqlite: nul byte in aggregate name: {:?}", name),
+            )
+        })?;
+
+        if !(-1..=127).contains(&arg_count) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "sqlite: aggregate {} has {} arguments, expect -1..=127",
+                    name, arg_count
+                ),
+            ));
+        }
+
+        Arc::make_mut(&mut self.options)
+            .functions
+            .push(Arc::new(Aggregate {
+                name: c_name,
+                arg_count,
+                prototype: Mutex::new(prototype),
+            }));
+
+        Ok(self)
+    }
+}
+
+/// A function registered on each connection by [`SqliteOptions::apply`](crate::SqliteOptions).
+pub(crate) trait RegisterFunction: Debug + Send + Sync {
+    /// Register the function on `conn`, which keeps the function alive until the connection is closed.
+    fn register(self: Arc<Self>, conn: &DbConn) -> io::Result<()>;
+}
+
+/// The registration of an [`AggregateImpl`], which is the user data of the sqlite function.
+struct Aggregate<A> {
+    name: CString,
+    arg_count: c_int,
+    prototype: Mutex<A>,
+}
+
+impl<A> Debug for Aggregate<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Aggregate")
+            .field("name", &self.name)
+            .field("arg_count", &self.arg_count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A: AggregateImpl + Clone> Aggregate<A> {
+    /// Returns a clone of the prototype, which starts a new aggregation.
+    fn start(&self) -> A {
+        // the prototype is only cloned, so a poisoned lock still holds a valid prototype.
+        self.prototype
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+impl<A: AggregateImpl + Clone> RegisterFunction for Aggregate<A> {
+    fn register(self: Arc<Self>, conn: &DbConn) -> io::Result<()> {
+        unsafe {
+            // the user data is released by `drop_aggregate`, also when the registration fails.
+            let rc = ffi::sqlite3_create_function_v2(
+                conn.to_c_handle(),
+                self.name.as_ptr(),
+                self.arg_count,
+                ffi::SQLITE_UTF8,
+                Arc::into_raw(self) as *mut c_void,
+                None,
+                Some(aggregate_step::<A>),
+                Some(aggregate_final::<A>),
+                Some(drop_aggregate::<A>),
+            );
+
+            if rc != ffi::SQLITE_OK {
+                return Err(to_io_error(conn.to_c_handle()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the state of the aggregation of `ctx`, which points to the aggregation or null if it is not started.
+///
+/// Returns null if the state can not be allocated.
+unsafe fn state_of<A>(ctx: *mut ffi::sqlite3_context, alloc: bool) -> *mut *mut A {
+    let size = if alloc {
+        size_of::<*mut A>() as c_int
+    } else {
+        0
+    };
+
+    // sqlite zeroes the state when it is allocated by the first call of an aggregation.
+    ffi::sqlite3_aggregate_context(ctx, size) as *mut *mut A
+}
+
+extern "C" fn aggregate_step<A: AggregateImpl + Clone>(
+    ctx: *mut ffi::sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    unsafe {
+        let aggregate = &*(ffi::sqlite3_user_data(ctx) as *const Aggregate<A>);
+
+        let state = state_of::<A>(ctx, true);
+
+        if state.is_null() {
+            ffi::sqlite3_result_error_nomem(ctx);
+            return;
+        }
+
+        let args = match values(argc, argv) {
+            Ok(args) => args,
+            Err(err) => return result_error(ctx, &err),
+        };
+
+        let stepped = catch_unwind(AssertUnwindSafe(|| {
+            if (*state).is_null() {
+                *state = Box::into_raw(Box::new(aggregate.start()));
+            }
+
+            (**state).step(&args);
+        }));
+
+        if stepped.is_err() {
+            result_error(ctx, &panicked(aggregate));
+        }
+    }
+}
+
+extern "C" fn aggregate_final<A: AggregateImpl + Clone>(ctx: *mut ffi::sqlite3_context) {
+    unsafe {
+        let aggregate = &*(ffi::sqlite3_user_data(ctx) as *const Aggregate<A>);
+
+        let state = state_of::<A>(ctx, false);
+
+        // sqlite calls `aggregate_final` once for each aggregation, also if a step fails.
+        let started = if state.is_null() || (*state).is_null() {
+            None
+        } else {
+            Some(Box::from_raw(std::mem::replace(&mut *state, null_mut())))
+        };
+
+        let value = catch_unwind(AssertUnwindSafe(|| match started {
+            Some(started) => started.finalize(),
+            None => aggregate.start().finalize(),
+        }));
+
+        match value {
+            Ok(value) => set_result(ctx, &value),
+            Err(_) => result_error(ctx, &panicked(aggregate)),
+        }
+    }
+}
+
+extern "C" fn drop_aggregate<A>(aggregate: *mut c_void) {
+    unsafe { drop(Arc::from_raw(aggregate as *const Aggregate<A>)) }
+}
+
+fn panicked<A>(aggregate: &Aggregate<A>) -> io::Error {
+    io::Error::other(format!(
+        "sqlite: aggregate {} panicked",
+        aggregate.name.to_string_lossy()
+    ))
+}
+
+/// Report `err` as the error of the function called with `ctx`.
+pub(crate) unsafe fn result_error(ctx: *mut ffi::sqlite3_context, err: &io::Error) {
+    let message = err.to_string();
+
+    ffi::sqlite3_result_error(ctx, message.as_ptr().cast(), message.len() as c_int);
+}
+
+/// Decode the `argc` arguments of a function call by their storage class, text and blobs are copied.
+unsafe fn values(
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) -> io::Result<Vec<SqlValue<'static>>> {
+    if argc == 0 {
+        return Ok(vec![]);
+    }
+
+    from_raw_parts(argv, argc as usize)
+        .iter()
+        .map(|value| value_of(*value))
+        .collect()
+}
+
+pub(crate) unsafe fn value_of(value: *mut ffi::sqlite3_value) -> io::Result<SqlValue<'static>> {
+    match ffi::sqlite3_value_type(value) {
+        ffi::SQLITE_INTEGER => Ok(SqlValue::Int(ffi::sqlite3_value_int64(value))),
+        ffi::SQLITE_FLOAT => Ok(SqlValue::Float(ffi::sqlite3_value_double(value))),
+        ffi::SQLITE_TEXT => {
+            let text = value_bytes(ffi::sqlite3_value_text(value), value);
+
+            let text = String::from_utf8(text.to_vec())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.utf8_error()))?;
+
+            Ok(SqlValue::String(Cow::Owned(text)))
+        }
+        ffi::SQLITE_BLOB => {
+            let blob = value_bytes(ffi::sqlite3_value_blob(value).cast(), value);
+
+            Ok(SqlValue::Binary(Cow::Owned(blob.to_vec())))
+        }
+        _ => Ok(SqlValue::Null),
+    }
+}
+
+/// Returns the bytes of a text or blob value, `ptr` must be returned by `sqlite3_value_text/blob` just before.
+unsafe fn value_bytes<'a>(ptr: *const u8, value: *mut ffi::sqlite3_value) -> &'a [u8] {
+    // an empty blob is returned as null pointer.
+    if ptr.is_null() {
+        return &[];
+    }
+
+    from_raw_parts(ptr, ffi::sqlite3_value_bytes(value) as usize)
+}
+
+/// Set `value` as the result of the function called with `ctx`, the value is stored like a bound parameter.
+pub(crate) unsafe fn set_result(ctx: *mut ffi::sqlite3_context, value: &SqlValue<'_>) {
+    match value {
+        SqlValue::Bool(v) => ffi::sqlite3_result_int64(ctx, *v as i64),
+        SqlValue::Int(v) => ffi::sqlite3_result_int64(ctx, *v),
+        SqlValue::BigInt(v) => match i64::try_from(*v) {
+            Ok(v) => ffi::sqlite3_result_int64(ctx, v),
+            Err(_) => result_error(
+                ctx,
+                &io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "sqlite: aggregate result is out of the 64-bit integer range",
+                ),
+            ),
+        },
+        SqlValue::Float(v) => ffi::sqlite3_result_double(ctx, *v),
+        SqlValue::String(v) => ffi::sqlite3_result_text64(
+            ctx,
+            v.as_ptr().cast(),
+            v.len() as u64,
+            sqlite_transient(),
+            ffi::SQLITE_UTF8 as u8,
+        ),
+        SqlValue::Binary(v) => {
+            ffi::sqlite3_result_blob64(ctx, v.as_ptr().cast(), v.len() as u64, sqlite_transient())
+        }
+        SqlValue::Null => ffi::sqlite3_result_null(ctx),
+        #[allow(unreachable_patterns)]
+        _ => result_error(
+            ctx,
+            &io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "sqlite: aggregate result {} is not supported",
+                    value.redacted()
+                ),
+            ),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    /// Multiplies the integer arguments, `NULL`s are skipped.
+    #[derive(Clone)]
+    struct Product(i64);
+
+    impl AggregateImpl for Product {
+        fn step(&mut self, args: &[SqlValue<'_>]) {
+            if let SqlValue::Int(v) = args[0] {
+                self.0 *= v;
+            }
+        }
+
+        fn finalize(self) -> SqlValue<'static> {
+            SqlValue::Int(self.0)
+        }
+    }
+
+    #[test]
+    fn test_create_aggregate() {
+        assert_eq!(
+            Sqlite::default()
+                .create_aggregate("product", 128, Product(1))
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        rinq_rdbc::register(
+            "sqlite-product",
+            Sqlite::default()
+                .create_aggregate("product", 1, Product(1))
+                .unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-product", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE t(k TEXT, v INTEGER)", &[])
+                .await
+                .unwrap();
+
+            conn.execute(
+                "INSERT INTO t VALUES ('a', 2), ('a', 3), ('a', NULL), ('b', 5), ('b', 7)",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            let stmt = conn
+                .prepare("SELECT k, product(v) FROM t GROUP BY k ORDER BY k")
+                .await
+                .unwrap();
+
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            let mut rows = vec![];
+
+            while result_set.next().await.unwrap() {
+                rows.push((
+                    result_set.get(0).await.unwrap(),
+                    result_set.get(1).await.unwrap(),
+                ));
+            }
+
+            assert_eq!(
+                rows,
+                vec![
+                    (SqlValue::String("a".into()), SqlValue::Int(6)),
+                    (SqlValue::String("b".into()), SqlValue::Int(35)),
+                ]
+            );
+
+            // an aggregation without rows finalizes the prototype.
+            let stmt = conn
+                .prepare("SELECT product(v) FROM t WHERE k = 'c'")
+                .await
+                .unwrap();
+
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(result_set.get(0).await.unwrap(), SqlValue::Int(1));
+        });
+    }
+}
@@ -0,0 +1,211 @@
+use std::{borrow::Cow, future::Future, io, path::Path};
+
+use rinq_rdbc::{DbConn, SqlValue};
+
+/// Sqlite `ATTACH` commands of a [`DbConn`] opened with the [`Sqlite`](crate::Sqlite) driver.
+///
+/// Tables of an attached database are addressed as `schema.table`.
+pub trait Attach {
+    /// Attach the database at `path` under the schema name `schema`.
+    ///
+    /// `path` may be `:memory:` or an in-memory URI such as `file:name?mode=memory`,
+    /// every `:memory:` attach creates a separate empty database that lives until it is detached.
+    ///
+    /// Returns error if `path` is not valid UTF-8 or `schema` is already in use.
+    fn attach<P: AsRef<Path>>(&self, path: P, schema: &str)
+        -> impl Future<Output = io::Result<()>>;
+
+    /// Detach the database attached as `schema`.
+    fn detach(&self, schema: &str) -> impl Future<Output = io::Result<()>>;
+
+    /// Returns the `(schema, path)` of the `main`, `temp` and attached databases, see `PRAGMA database_list`.
+    ///
+    /// The path is `None` for in-memory and temporary databases, whose file name is empty.
+    /// `temp` is only listed once a temporary table or index has been created.
+    fn database_list(&self) -> impl Future<Output = io::Result<Vec<(String, Option<String>)>>>;
+}
+
+/// Quote `schema` as an sql identifier, schema names can not be bound as parameters.
+fn quote_identifier(schema: &str) -> String {
+    format!("\"{}\"", schema.replace('"', "\"\""))
+}
+
+impl Attach for DbConn {
+    async fn attach<P: AsRef<Path>>(&self, path: P, schema: &str) -> io::Result<()> {
+        let path = path.as_ref();
+
+        let path = path.to_str().ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("sqlite: attach a non UTF-8 path: {:?}", path),
+        ))?;
+
+        self.prepare(format!("ATTACH DATABASE ? AS {}", quote_identifier(schema)))
+            .await?
+            .exec(&[SqlValue::String(Cow::Borrowed(path))])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn detach(&self, schema: &str) -> io::Result<()> {
+        self.prepare(format!("DETACH DATABASE {}", quote_identifier(schema)))
+            .await?
+            .exec(&[])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn database_list(&self) -> io::Result<Vec<(String, Option<String>)>> {
+        let result_set = self
+            .prepare("SELECT name, file FROM pragma_database_list ORDER BY seq")
+            .await?
+            .query(&[])
+            .await?;
+
+        let mut databases = vec![];
+
+        while result_set.next().await? {
+            let schema: String = result_set.get_as(0).await?;
+            let path: Option<String> = result_set.get_as(1).await?;
+
+            databases.push((schema, path.filter(|path| !path.is_empty())));
+        }
+
+        Ok(databases)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    async fn exec(conn: &DbConn, query: &str) -> io::Result<()> {
+        conn.prepare(query).await?.exec(&[]).await?;
+
+        Ok(())
+    }
+
+    async fn names(conn: &DbConn, query: &str) -> io::Result<Vec<String>> {
+        let result_set = conn.prepare(query).await?.query(&[]).await?;
+
+        let mut names = vec![];
+
+        while result_set.next().await? {
+            match result_set.get(0).await? {
+                SqlValue::String(name) => names.push(name.into_owned()),
+                _ => panic!("expect text name"),
+            }
+        }
+
+        Ok(names)
+    }
+
+    #[test]
+    fn test_attach_memory() {
+        crate::tests::register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            exec(
+                &conn,
+                "CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT)",
+            )
+            .await
+            .unwrap();
+            exec(
+                &conn,
+                "INSERT INTO user (name) VALUES ('alice'), ('bob'), ('carol')",
+            )
+            .await
+            .unwrap();
+
+            conn.attach(":memory:", "scratch").await.unwrap();
+
+            // the schema name is taken.
+            assert!(conn.attach(":memory:", "scratch").await.is_err());
+
+            exec(&conn, "CREATE TABLE scratch.t (user_id INTEGER)")
+                .await
+                .unwrap();
+            exec(&conn, "INSERT INTO scratch.t VALUES (1), (3)")
+                .await
+                .unwrap();
+
+            assert_eq!(
+                names(
+                    &conn,
+                    "SELECT name FROM user JOIN scratch.t ON user.id = t.user_id ORDER BY id"
+                )
+                .await
+                .unwrap(),
+                ["alice", "carol"]
+            );
+
+            // a second in-memory attach is a separate database.
+            conn.attach("file:other?mode=memory", "other")
+                .await
+                .unwrap();
+
+            assert!(names(&conn, "SELECT name FROM other.sqlite_master")
+                .await
+                .unwrap()
+                .is_empty());
+
+            conn.detach("other").await.unwrap();
+            conn.detach("scratch").await.unwrap();
+
+            assert!(exec(&conn, "SELECT * FROM scratch.t").await.is_err());
+
+            // the main database is untouched.
+            assert_eq!(
+                names(&conn, "SELECT name FROM user").await.unwrap().len(),
+                3
+            );
+        });
+    }
+
+    #[test]
+    fn test_database_list() {
+        crate::tests::register_once();
+
+        let path = std::env::temp_dir().join(format!(
+            "rinq-database-list-{}.db",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            assert_eq!(
+                conn.database_list().await.unwrap(),
+                [("main".to_owned(), None)]
+            );
+
+            conn.attach(&path, "archive").await.unwrap();
+            conn.attach(":memory:", "scratch").await.unwrap();
+
+            let databases = conn.database_list().await.unwrap();
+
+            assert_eq!(databases.len(), 3);
+            assert_eq!(databases[0], ("main".to_owned(), None));
+            assert_eq!(databases[1].0, "archive");
+            assert!(databases[1]
+                .1
+                .as_deref()
+                .unwrap()
+                .ends_with(path.file_name().unwrap().to_str().unwrap()));
+            assert_eq!(databases[2], ("scratch".to_owned(), None));
+
+            conn.detach("archive").await.unwrap();
+        });
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+//! Decimal columns, sqlite has no decimal storage class.
+//!
+//! A column is a decimal column if the first word of its declared type is `DECIMAL` or `NUMERIC`.
+//! Such a column has numeric affinity by default, so sqlite converts a bound decimal text to `INTEGER`
+//! or `REAL`, which drops trailing zeros and any digits after the 15th significant one.
+//! Trailing zeros are restored from the declared scale, e.g. `DECIMAL(10, 2)`.
+//! Declare the column with text affinity, e.g. `DECIMAL TEXT` or `DECIMAL TEXT(30, 6)`, to store the text as is.
+
+#[cfg(feature = "with-decimal")]
+use std::str::FromStr;
+
+#[cfg(feature = "with-decimal")]
+use bigdecimal::BigDecimal;
+#[cfg(feature = "with-decimal")]
+use rinq_rdbc::SqlValue;
+
+/// Returns true if the first word of the declared type `decltype` is `DECIMAL` or `NUMERIC`.
+pub(crate) fn is_decimal_type(decltype: &str) -> bool {
+    let name = decltype
+        .split(|c: char| c == '(' || c.is_whitespace())
+        .find(|word| !word.is_empty())
+        .unwrap_or_default();
+
+    name.eq_ignore_ascii_case("DECIMAL") || name.eq_ignore_ascii_case("NUMERIC")
+}
+
+/// Returns the `(precision, scale)` of a declared type like `DECIMAL(10, 2)`, the scale of `DECIMAL(10)` is `0`.
+pub(crate) fn decimal_size(decltype: &str) -> Option<(i64, i64)> {
+    if !is_decimal_type(decltype) {
+        return None;
+    }
+
+    let (_, size) = decltype.split_once('(')?;
+    let (size, _) = size.split_once(')')?;
+
+    let (precision, scale) = size.split_once(',').unwrap_or((size, "0"));
+
+    Some((precision.trim().parse().ok()?, scale.trim().parse().ok()?))
+}
+
+/// Convert a value read from a decimal column to [`SqlValue::Decimal`].
+///
+/// Text keeps its scale as written, an `INTEGER` or `REAL` is padded with trailing zeros
+/// to the scale of `decimal_size` if present. Other values and text that is not a number are returned unchanged.
+#[cfg(feature = "with-decimal")]
+pub(crate) fn to_decimal(
+    value: SqlValue<'static>,
+    decimal_size: Option<(i64, i64)>,
+) -> SqlValue<'static> {
+    let decimal = match &value {
+        SqlValue::Int(v) => BigDecimal::from(*v),
+        // `f64` display is the shortest text that reads back to the same value.
+        SqlValue::Float(v) => match BigDecimal::from_str(&v.to_string()) {
+            Ok(decimal) => decimal,
+            Err(_) => return value,
+        },
+        SqlValue::String(v) => match BigDecimal::from_str(v.trim()) {
+            Ok(decimal) => decimal,
+            Err(_) => return value,
+        },
+        _ => return value,
+    };
+
+    let decimal = match decimal_size {
+        Some((_, scale)) if scale > decimal.fractional_digit_count() => decimal.with_scale(scale),
+        _ => decimal,
+    };
+
+    SqlValue::Decimal(decimal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_size() {
+        assert!(is_decimal_type("decimal"));
+        assert!(is_decimal_type("NUMERIC (10)"));
+        assert!(is_decimal_type("DECIMAL TEXT"));
+        assert!(!is_decimal_type("TEXT"));
+        assert!(!is_decimal_type("DECIMALS"));
+
+        assert_eq!(decimal_size("DECIMAL(10, 2)"), Some((10, 2)));
+        assert_eq!(decimal_size("numeric(10)"), Some((10, 0)));
+        assert_eq!(decimal_size("DECIMAL TEXT(30, 6)"), Some((30, 6)));
+        assert_eq!(decimal_size("DECIMAL"), None);
+        assert_eq!(decimal_size("VARCHAR(10)"), None);
+    }
+}
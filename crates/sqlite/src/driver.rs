@@ -0,0 +1,2693 @@
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    io::{self, Result},
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+    task::{Context, Poll},
+};
+
+use rasi::syscall::{ready, CancelablePoll, Handle};
+use rinq_rdbc::{
+    Capabilities, ChangeEvent, ColumnType, Database, IsolationLevel, ScanStatus, SqlValue,
+};
+
+use crate::{
+    decimal::decimal_size,
+    limit::ConnectionLimit,
+    watch::{Watch, Watchers},
+    DbConn, DbStmt, SqliteOptions,
+};
+
+/// The sqlite driver of the rdbc [`Database`] api.
+///
+/// The sqlite api is synchronous, so every `poll_*` function completes immediately,
+/// except for [`poll_connect`](Sqlite::poll_connect) waiting for [`SqliteOptions::max_connections`].
+#[derive(Debug, Default)]
+pub struct Sqlite {
+    pub(crate) options: Arc<SqliteOptions>,
+    limit: Option<Arc<ConnectionLimit>>,
+}
+
+impl Sqlite {
+    /// Create a driver that applies `options` to each connection it opens,
+    /// register it with [`rinq_rdbc::register`] under a name of your choice.
+    ///
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if a pragma of `options`
+    /// is not allowed, see [`SqliteOptions::pragma`].
+    pub fn with_options(options: SqliteOptions) -> Result<Self> {
+        options.validate()?;
+
+        Ok(Self {
+            limit: options
+                .connection_limit()
+                .map(|max| Arc::new(ConnectionLimit::new(max))),
+            options: Arc::new(options),
+        })
+    }
+}
+
+/// Register [`Sqlite`] driver with name `sqlite`, see [`rinq_rdbc::register`].
+pub fn register() -> Result<()> {
+    rinq_rdbc::register("sqlite", Sqlite::default())
+}
+
+/// The connection object created by [`Sqlite::start_connect`].
+///
+/// With [`SqliteOptions::max_connections`], the connection is opened by [`Sqlite::poll_connect`]
+/// once the limit allows it, otherwise it is opened right away.
+struct DbConnect {
+    source_name: String,
+    conn: OnceLock<DbConn>,
+}
+
+/// The transaction object created by [`Sqlite::begin`].
+///
+/// sqlite transactions belong to the connection, so statements prepared
+/// with this handle run on the same connection inside the transaction.
+struct DbTx {
+    conn: DbConn,
+    /// The id of the transaction in the [`TxStack`] of the connection, shared by its savepoints.
+    id: u64,
+    /// The savepoint name if this is a nested transaction created by [`Sqlite::savepoint`].
+    savepoint: Option<String>,
+}
+
+impl DbTx {
+    /// Locks the [`TxStack`] of the connection, ignoring poison as the stack is only updated after each statement.
+    fn lock_stack(&self) -> MutexGuard<'_, TxStack> {
+        self.conn
+            .tx_stack
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the position of the savepoint of this handle in `stack`, `0` for the transaction itself.
+    ///
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if the transaction has ended,
+    /// or the savepoint has been released or rolled back, also by an outer savepoint.
+    fn position(&self, stack: &TxStack) -> Result<usize> {
+        let position = match &self.savepoint {
+            _ if stack.tx != Some(self.id) => None,
+            Some(name) => stack
+                .savepoints
+                .iter()
+                .position(|active| active == name)
+                .map(|i| i + 1),
+            None => Some(0),
+        };
+
+        position.ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "sqlite: the transaction or savepoint has already ended",
+        ))
+    }
+}
+
+/// The transaction and savepoints started on a connection by [`Sqlite`].
+#[derive(Default)]
+pub(crate) struct TxStack {
+    /// The id of the active transaction, `None` if there is none.
+    tx: Option<u64>,
+    /// The names of the active savepoints, the innermost last.
+    savepoints: Vec<String>,
+    /// The last id given to a transaction or savepoint.
+    last_id: u64,
+}
+
+impl TxStack {
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+/// The position of a [`DbRows`] cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cursor {
+    BeforeFirst,
+    OnRow,
+    AfterLast,
+}
+
+/// The result set object created by [`Sqlite::start_query`].
+///
+/// The rows are read from the statement directly,
+/// so starting another query with the same statement invalidates this result set.
+struct DbRows {
+    stmt: DbStmt,
+    cursor: Mutex<Cursor>,
+    /// The column types, resolved by the first [`poll_col_types`](Database::poll_col_types).
+    col_types: OnceLock<Vec<ColumnType<'static>>>,
+}
+
+/// The exec result object created by [`Sqlite::start_exec`].
+struct DbExec {
+    last_insert_id: i64,
+    rows_affected: i64,
+}
+
+fn downcast<'a, T: Send + Sync + 'static>(handle: &'a Handle, name: &str) -> Result<&'a T> {
+    handle.downcast::<T>().ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("sqlite: expect {} handle", name),
+    ))
+}
+
+/// Returns the open connection of the connection object `conn`.
+fn downcast_conn<'a>(conn: &'a Handle, name: &str) -> Result<&'a DbConn> {
+    downcast::<DbConnect>(conn, name)?
+        .conn
+        .get()
+        .ok_or(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "sqlite: the connection is not open yet",
+        ))
+}
+
+/// Returns the connection of a connection or transaction handle.
+fn conn_of(conn_or_tx: &Handle) -> Result<&DbConn> {
+    match conn_or_tx.downcast::<DbTx>() {
+        Some(tx) => Ok(&tx.conn),
+        None => downcast_conn(conn_or_tx, "connection or transaction"),
+    }
+}
+
+/// Reset `stmt` for a new execution with `values`.
+fn rebind(stmt: &DbStmt, values: &[SqlValue<'_>]) -> Result<()> {
+    stmt.reset();
+    stmt.bind(values)
+}
+
+/// Start reading the rows of `stmt`, whose parameters are bound.
+fn start_rows(stmt: &DbStmt) -> Handle {
+    Handle::new(DbRows {
+        stmt: stmt.clone(),
+        cursor: Mutex::new(Cursor::BeforeFirst),
+        col_types: OnceLock::new(),
+    })
+}
+
+/// Step `stmt`, whose parameters are bound, to completion and reset it.
+fn run_exec(stmt: &DbStmt) -> Result<Handle> {
+    while stmt.step()? {}
+
+    let exec = DbExec {
+        last_insert_id: stmt.conn.last_insert_rowid(),
+        rows_affected: stmt.conn.changes(),
+    };
+
+    stmt.reset();
+
+    Ok(Handle::new(exec))
+}
+
+/// Returns the name and declared type of each result column of `stmt`.
+fn col_types(stmt: &DbStmt) -> Vec<ColumnType<'static>> {
+    (0..stmt.column_count())
+        .map(|col| {
+            let decltype = stmt.column_decltype(col);
+
+            ColumnType {
+                decimal_size: decimal_size(&decltype),
+                database_type_name: Cow::Owned(decltype),
+                length: None,
+                name: Cow::Owned(stmt.column_name(col)),
+                nullable: None,
+            }
+        })
+        .collect()
+}
+
+impl Database for Sqlite {
+    fn start_connect(&self, source_name: &str) -> Result<Handle> {
+        let conn = OnceLock::new();
+
+        if self.limit.is_none() {
+            _ = conn.set(DbConn::new(source_name, self.options.clone())?);
+        }
+
+        Ok(Handle::new(DbConnect {
+            source_name: source_name.to_owned(),
+            conn,
+        }))
+    }
+
+    /// Pending while [`SqliteOptions::max_connections`] connections of this driver are open.
+    fn poll_connect(&self, cx: &mut Context<'_>, handle: &Handle) -> CancelablePoll<Result<()>> {
+        let connect = match downcast::<DbConnect>(handle, "connection") {
+            Ok(connect) => connect,
+            Err(err) => return CancelablePoll::Ready(Err(err)),
+        };
+
+        let (Some(limit), None) = (&self.limit, connect.conn.get()) else {
+            return ready(|| Ok(()));
+        };
+
+        let permit = match limit.poll_acquire(cx) {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => return CancelablePoll::Pending(Handle::new(())),
+        };
+
+        ready(|| {
+            let conn = DbConn::new(&connect.source_name, self.options.clone())?;
+
+            _ = connect.conn.set(DbConn {
+                _permit: Some(Arc::new(permit)),
+                ..conn
+            });
+
+            Ok(())
+        })
+    }
+
+    /// Starts a deferred transaction, use [`savepoint`](Sqlite::savepoint) to nest transactions.
+    fn begin(&self, cx: &mut Context<'_>, conn: &Handle) -> CancelablePoll<Result<Handle>> {
+        self.begin_with(cx, conn, IsolationLevel::default())
+    }
+
+    /// sqlite transactions are always serializable, `level` selects when the write lock is taken:
+    /// `Serializable` starts with `BEGIN IMMEDIATE` to take it up front,
+    /// the weaker levels start with `BEGIN DEFERRED` to take it on the first write.
+    fn begin_with(
+        &self,
+        _cx: &mut Context<'_>,
+        conn: &Handle,
+        level: IsolationLevel,
+    ) -> CancelablePoll<Result<Handle>> {
+        ready(|| {
+            let conn = downcast_conn(conn, "connection")?;
+
+            match level {
+                IsolationLevel::Serializable => conn.exec(c"BEGIN IMMEDIATE")?,
+                _ => conn.exec(c"BEGIN DEFERRED")?,
+            }
+
+            let mut stack = conn.tx_stack.lock().unwrap_or_else(PoisonError::into_inner);
+
+            let id = stack.next_id();
+
+            stack.tx = Some(id);
+            stack.savepoints.clear();
+
+            Ok(Handle::new(DbTx {
+                conn: conn.clone(),
+                id,
+                savepoint: None,
+            }))
+        })
+    }
+
+    /// Creates a savepoint named `sp_<n>`, the names are unique on the connection.
+    fn savepoint(&self, _cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<Handle>> {
+        ready(|| {
+            let tx = downcast::<DbTx>(tx, "transaction")?;
+
+            let mut stack = tx.lock_stack();
+
+            tx.position(&stack)?;
+
+            let name = format!("sp_{}", stack.next_id());
+
+            tx.conn
+                .exec(&CString::new(format!("SAVEPOINT {}", name))?)?;
+
+            stack.savepoints.push(name.clone());
+
+            Ok(Handle::new(DbTx {
+                conn: tx.conn.clone(),
+                id: tx.id,
+                savepoint: Some(name),
+            }))
+        })
+    }
+
+    /// Rolls back to and releases the savepoint of a nested transaction, or rolls back the transaction.
+    ///
+    /// Either also ends the savepoints nested in it.
+    fn rollback(&self, _cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<()>> {
+        ready(|| {
+            let tx = downcast::<DbTx>(tx, "transaction")?;
+
+            let mut stack = tx.lock_stack();
+
+            let position = tx.position(&stack)?;
+
+            match &tx.savepoint {
+                Some(name) => {
+                    // `ROLLBACK TO` keeps the savepoint on the stack.
+                    tx.conn.exec(&CString::new(format!(
+                        "ROLLBACK TO {}; RELEASE {}",
+                        name, name
+                    ))?)?;
+
+                    stack.savepoints.truncate(position - 1);
+                }
+                None => {
+                    tx.conn.exec(c"ROLLBACK")?;
+
+                    stack.tx = None;
+                    stack.savepoints.clear();
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Releases the savepoint of a nested transaction, or commits the transaction.
+    ///
+    /// Either also ends the savepoints nested in it.
+    fn commit(&self, _cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<()>> {
+        ready(|| {
+            let tx = downcast::<DbTx>(tx, "transaction")?;
+
+            let mut stack = tx.lock_stack();
+
+            let position = tx.position(&stack)?;
+
+            match &tx.savepoint {
+                Some(name) => {
+                    tx.conn.exec(&CString::new(format!("RELEASE {}", name))?)?;
+
+                    stack.savepoints.truncate(position - 1);
+                }
+                None => {
+                    tx.conn.exec(c"COMMIT")?;
+
+                    stack.tx = None;
+                    stack.savepoints.clear();
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn start_prepare(&self, conn_or_tx: &Handle, query: &str) -> Result<Handle> {
+        let conn = conn_of(conn_or_tx)?;
+
+        Ok(Handle::new(conn.prepare(&CString::new(query)?)?))
+    }
+
+    fn poll_prepare(&self, _cx: &mut Context<'_>, _stmt: &Handle) -> CancelablePoll<Result<()>> {
+        ready(|| Ok(()))
+    }
+
+    /// Returns the largest parameter index, a numbered parameter `?N` counts as `N` parameters
+    /// and a name used more than once counts once.
+    fn parameter_count(&self, stmt: &Handle) -> Result<usize> {
+        Ok(downcast::<DbStmt>(stmt, "statement")?.parameter_count())
+    }
+
+    /// Returns the name as written in the sql, including the `:`, `@` or `$` prefix,
+    /// a numbered parameter `?N` is named `?N`.
+    fn parameter_name(&self, stmt: &Handle, index: usize) -> Result<Option<String>> {
+        let stmt = downcast::<DbStmt>(stmt, "statement")?;
+
+        if index >= stmt.parameter_count() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("sqlite: no parameter at index {}", index),
+            ));
+        }
+
+        Ok(stmt.parameter_name(index))
+    }
+
+    fn stmt_col_types(&self, stmt: &Handle) -> Result<Vec<ColumnType<'static>>> {
+        Ok(col_types(downcast::<DbStmt>(stmt, "statement")?))
+    }
+
+    /// Returns [`Unsupported`](io::ErrorKind::Unsupported) error unless the `with-scanstatus` feature is enabled,
+    /// see [`DbStmt::scan_status`].
+    fn scan_status(&self, stmt: &Handle) -> Result<Vec<ScanStatus>> {
+        downcast::<DbStmt>(stmt, "statement")?.scan_status()
+    }
+
+    /// See `sqlite3_stmt_readonly`, `BEGIN` and `COMMIT` are read-only as they do not write by themselves.
+    fn is_readonly(&self, stmt: &Handle) -> Result<bool> {
+        Ok(downcast::<DbStmt>(stmt, "statement")?.is_readonly())
+    }
+
+    fn start_query(&self, stmt: &Handle, values: &[SqlValue<'_>]) -> Result<Handle> {
+        let stmt = downcast::<DbStmt>(stmt, "statement")?;
+
+        rebind(stmt, values)?;
+
+        Ok(start_rows(stmt))
+    }
+
+    /// Parameters are named `:name`, `@name` or `$name` in the sql, see [`start_exec_named`](Sqlite::start_exec_named).
+    fn start_query_named(&self, stmt: &Handle, params: &[(&str, SqlValue<'_>)]) -> Result<Handle> {
+        let stmt = downcast::<DbStmt>(stmt, "statement")?;
+
+        stmt.reset();
+        stmt.bind_named(params)?;
+
+        Ok(start_rows(stmt))
+    }
+
+    fn poll_next(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<bool>> {
+        ready(|| {
+            let rows = downcast::<DbRows>(result_set, "result set")?;
+
+            let mut cursor = rows.cursor.lock().unwrap();
+
+            // `sqlite3_step` would restart the statement after `SQLITE_DONE`.
+            if *cursor == Cursor::AfterLast {
+                return Ok(false);
+            }
+
+            let has_row = rows.stmt.step()?;
+
+            *cursor = if has_row {
+                Cursor::OnRow
+            } else {
+                Cursor::AfterLast
+            };
+
+            Ok(has_row)
+        })
+    }
+
+    fn poll_value(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+        col_num: usize,
+    ) -> CancelablePoll<Result<SqlValue<'static>>> {
+        ready(|| {
+            let rows = downcast::<DbRows>(result_set, "result set")?;
+
+            if *rows.cursor.lock().unwrap() != Cursor::OnRow {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "sqlite: result set is not on a row",
+                ));
+            }
+
+            if col_num >= rows.stmt.column_count() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("sqlite: no value at column {}", col_num),
+                ));
+            }
+
+            rows.stmt.column_value(col_num)
+        })
+    }
+
+    /// Steps the statement to completion, rows returned by the statement are discarded.
+    ///
+    /// `rows affected` is the value of `sqlite3_changes` after the execution,
+    /// which is left unchanged by statements other than `INSERT`, `UPDATE` or `DELETE`.
+    fn start_exec(&self, stmt: &Handle, values: &[SqlValue<'_>]) -> Result<Handle> {
+        let stmt = downcast::<DbStmt>(stmt, "statement")?;
+
+        rebind(stmt, values)?;
+
+        run_exec(stmt)
+    }
+
+    /// A name is written as in the sql, e.g. `:id`, `@id` or `$id`, a name without a prefix
+    /// matches the parameter with any of them. Parameters not named in `params` are bound to `NULL`.
+    fn start_exec_named(&self, stmt: &Handle, params: &[(&str, SqlValue<'_>)]) -> Result<Handle> {
+        let stmt = downcast::<DbStmt>(stmt, "statement")?;
+
+        stmt.reset();
+        stmt.bind_named(params)?;
+
+        run_exec(stmt)
+    }
+
+    fn poll_exec(
+        &self,
+        _cx: &mut Context<'_>,
+        result: &Handle,
+    ) -> CancelablePoll<Result<(i64, i64)>> {
+        ready(|| {
+            let exec = downcast::<DbExec>(result, "exec result")?;
+
+            Ok((exec.last_insert_id, exec.rows_affected))
+        })
+    }
+
+    fn poll_cols(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<Vec<String>>> {
+        ready(|| {
+            let stmt = &downcast::<DbRows>(result_set, "result set")?.stmt;
+
+            Ok((0..stmt.column_count())
+                .map(|col| stmt.column_name(col))
+                .collect())
+        })
+    }
+
+    /// `database_type_name` is the declared type and `decimal_size` is parsed from it,
+    /// `length` and `nullable` are not available.
+    ///
+    /// The types are resolved on the first call and cached by the result set,
+    /// [`poll_cols`](Database::poll_cols) only reads the column names.
+    fn poll_col_types(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<Vec<ColumnType<'static>>>> {
+        ready(|| {
+            let rows = downcast::<DbRows>(result_set, "result set")?;
+
+            Ok(rows.col_types.get_or_init(|| col_types(&rows.stmt)).clone())
+        })
+    }
+
+    /// Returns the hidden `rowid` column selected with [`SqliteOptions::track_rowid`],
+    /// [`Unsupported`](io::ErrorKind::Unsupported) error if it is disabled, and
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) error if the query could not be rewritten.
+    fn poll_rowid(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<i64>> {
+        ready(|| {
+            let rows = downcast::<DbRows>(result_set, "result set")?;
+
+            if !rows.stmt.conn.options.is_tracking_rowid() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "sqlite: rowid requires SqliteOptions::track_rowid",
+                ));
+            }
+
+            if *rows.cursor.lock().unwrap() != Cursor::OnRow {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "sqlite: result set is not on a row",
+                ));
+            }
+
+            rows.stmt.rowid().unwrap_or_else(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "sqlite: rowid is only selected from a single rowid table",
+                ))
+            })
+        })
+    }
+
+    /// Returns `None` unless the `with-column-metadata` feature is enabled, see [`DbStmt::column_origin`].
+    fn column_origin(&self, result_set: &Handle, col: usize) -> Result<Option<(String, String)>> {
+        let stmt = &downcast::<DbRows>(result_set, "result set")?.stmt;
+
+        if col >= stmt.column_count() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("sqlite: no column {}", col),
+            ));
+        }
+
+        Ok(stmt.column_origin(col))
+    }
+
+    fn poll_last_insert_id(
+        &self,
+        _cx: &mut Context<'_>,
+        conn: &Handle,
+    ) -> CancelablePoll<Result<i64>> {
+        ready(|| Ok(downcast_conn(conn, "connection")?.last_insert_rowid()))
+    }
+
+    /// Changes made by triggers and rolled back transactions are counted as well.
+    fn poll_total_changes(
+        &self,
+        _cx: &mut Context<'_>,
+        conn: &Handle,
+    ) -> CancelablePoll<Result<i64>> {
+        ready(|| Ok(downcast_conn(conn, "connection")?.total_changes()))
+    }
+
+    /// A deferred transaction starts reading on its first read of the database, which is done here by reading the schema.
+    ///
+    /// In WAL mode, the transaction keeps reading the database as of that read until it ends,
+    /// while other connections keep committing. In rollback journal mode, the read lock blocks
+    /// other connections from committing instead. The snapshot is kept if the transaction has already read or written.
+    fn poll_snapshot(&self, _cx: &mut Context<'_>, tx: &Handle) -> CancelablePoll<Result<()>> {
+        ready(|| {
+            let tx = downcast::<DbTx>(tx, "transaction")?;
+
+            tx.position(&tx.lock_stack())?;
+
+            tx.conn.exec(c"SELECT count(*) FROM sqlite_schema")
+        })
+    }
+
+    /// `rows_affected` is the value of `sqlite3_changes`, as by [`start_exec`](Sqlite::start_exec),
+    /// so no other statement may run on the connection between reading the last row and this call.
+    fn poll_query_result(
+        &self,
+        _cx: &mut Context<'_>,
+        result_set: &Handle,
+    ) -> CancelablePoll<Result<(i64, i64)>> {
+        ready(|| {
+            let rows = downcast::<DbRows>(result_set, "result set")?;
+
+            if *rows.cursor.lock().unwrap() != Cursor::AfterLast {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "sqlite: result set is not read to the end",
+                ));
+            }
+
+            let conn = &rows.stmt.conn;
+
+            Ok((conn.last_insert_rowid(), conn.changes()))
+        })
+    }
+
+    /// Backed by `sqlite3_update_hook`, which reports the changes of rowid tables only:
+    /// `WITHOUT ROWID` tables, truncating `DELETE`s without a `WHERE` clause and changes made
+    /// by the conflict resolution of `REPLACE` are not reported. Changes are reported
+    /// when a statement makes them, so the changes of a rolled back transaction are reported as well.
+    fn start_watch(&self, conn: &Handle, table: &str) -> Result<Handle> {
+        Ok(Handle::new(Watchers::subscribe(
+            downcast_conn(conn, "connection")?,
+            table,
+        )))
+    }
+
+    fn poll_change(
+        &self,
+        cx: &mut Context<'_>,
+        watch: &Handle,
+    ) -> CancelablePoll<Result<ChangeEvent>> {
+        let watch = match downcast::<Arc<Watch>>(watch, "watch") {
+            Ok(watch) => watch,
+            Err(err) => return CancelablePoll::Ready(Err(err)),
+        };
+
+        match watch.poll_next(cx) {
+            Poll::Ready(result) => CancelablePoll::Ready(result),
+            Poll::Pending => CancelablePoll::Pending(Handle::new(())),
+        }
+    }
+
+    /// The values are read by the sql function `session_value(key)`, which returns `NULL` for a key that is not set,
+    /// e.g. `INSERT INTO audit VALUES (new.id, session_value('user_id'))` in a trigger.
+    fn set_session_value(&self, conn: &Handle, key: &str, value: SqlValue<'static>) -> Result<()> {
+        downcast_conn(conn, "connection")?.session.set(key, value);
+
+        Ok(())
+    }
+
+    fn session_value(&self, conn: &Handle, key: &str) -> Result<Option<SqlValue<'static>>> {
+        Ok(downcast_conn(conn, "connection")?.session.get(key))
+    }
+
+    /// `ON CONFLICT` upserts are supported since sqlite 3.24.
+    fn capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::default();
+
+        capabilities.upsert_on_conflict = crate::sqlite_version_number() >= 3024000;
+
+        capabilities
+    }
+
+    /// Returns the `sqlite3_errmsg` and extended result code of the most recent failed api call.
+    fn last_error(&self, conn: &Handle) -> Option<io::Error> {
+        conn.downcast::<DbConnect>()?.conn.get()?.last_error()
+    }
+
+    /// Constraint violations and most other errors only fail the statement,
+    /// errors like `SQLITE_FULL`, `SQLITE_IOERR` or `RAISE(ROLLBACK)` roll back the whole transaction,
+    /// which is detected by the connection being back in autocommit mode.
+    fn is_tx_aborted(&self, tx: &Handle) -> bool {
+        tx.downcast::<DbTx>()
+            .is_some_and(|tx| !tx.conn.in_transaction())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::tests::register_once;
+
+    #[test]
+    fn test_register() {
+        register_once();
+
+        assert_eq!(register().unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn test_query() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT, avatar BLOB)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let insert = conn
+                .prepare("INSERT INTO user (name, avatar) VALUES ('alice', x'0102')")
+                .await
+                .unwrap();
+
+            assert_eq!(insert.exec(&[]).await.unwrap(), (1, 1));
+            assert_eq!(insert.exec(&[]).await.unwrap(), (2, 1));
+            assert_eq!(conn.last_insert_id().await.unwrap(), 2);
+
+            let result_set = conn
+                .prepare("SELECT id, name, avatar, 1.5 AS score FROM user ORDER BY id")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                result_set.columns().await.unwrap(),
+                ["id", "name", "avatar", "score"]
+            );
+
+            let col_types = result_set.column_types().await.unwrap();
+
+            assert_eq!(col_types[0].database_type_name, "INTEGER");
+            assert_eq!(col_types[3].database_type_name, "");
+
+            assert!(result_set.next().await.unwrap());
+
+            assert!(matches!(result_set.get(0).await.unwrap(), SqlValue::Int(1)));
+            assert!(
+                matches!(result_set.get(1).await.unwrap(), SqlValue::String(name) if name == "alice")
+            );
+            assert!(
+                matches!(result_set.get(2).await.unwrap(), SqlValue::Binary(avatar) if *avatar == [1, 2])
+            );
+            assert!(matches!(result_set.get(3).await.unwrap(), SqlValue::Float(v) if v == 1.5));
+            assert_eq!(
+                result_set.get(4).await.err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            assert_eq!(result_set.skip(usize::MAX).await.unwrap(), 1);
+            assert!(!result_set.next().await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_stmt_try_clone() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE user (id INTEGER PRIMARY KEY, team TEXT)", &[])
+                .await
+                .unwrap();
+
+            conn.execute(
+                "INSERT INTO user (team) VALUES ('a'), ('b'), ('a'), ('b'), ('a')",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            let stmt = conn
+                .prepare("SELECT id FROM user WHERE team = ? ORDER BY id")
+                .await
+                .unwrap();
+
+            let cloned = stmt.try_clone().await.unwrap();
+
+            // both cursors are open at the same time and stepped in turn.
+            let a = stmt.query(&["a".into()]).await.unwrap();
+            let b = cloned.query(&["b".into()]).await.unwrap();
+
+            let mut ids_a = vec![];
+            let mut ids_b = vec![];
+
+            loop {
+                let more_a = a.next().await.unwrap();
+
+                if more_a {
+                    ids_a.push(a.get(0).await.unwrap());
+                }
+
+                let more_b = b.next().await.unwrap();
+
+                if more_b {
+                    ids_b.push(b.get(0).await.unwrap());
+                }
+
+                if !more_a && !more_b {
+                    break;
+                }
+            }
+
+            assert_eq!(ids_a, [1.into(), 3.into(), 5.into()]);
+            assert_eq!(ids_b, [2.into(), 4.into()]);
+        });
+    }
+
+    #[test]
+    fn test_bind_values() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            let stmt = conn.prepare("SELECT ?, ?, ?, ?, ?, ?, ?, ?").await.unwrap();
+
+            let result_set = stmt
+                .query(&[
+                    SqlValue::Bool(true),
+                    SqlValue::Bool(false),
+                    SqlValue::Int(i64::MIN),
+                    SqlValue::Float(-0.25),
+                    SqlValue::String(Cow::Owned("it's 世界".to_owned())),
+                    SqlValue::Binary(Cow::Owned(vec![0, 0xff, 0])),
+                    SqlValue::Binary(Cow::Borrowed(&[])),
+                    SqlValue::Null,
+                ])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+
+            assert!(matches!(result_set.get(0).await.unwrap(), SqlValue::Int(1)));
+            assert!(matches!(result_set.get(1).await.unwrap(), SqlValue::Int(0)));
+            assert!(matches!(
+                result_set.get(2).await.unwrap(),
+                SqlValue::Int(i64::MIN)
+            ));
+            assert!(matches!(result_set.get(3).await.unwrap(), SqlValue::Float(v) if v == -0.25));
+            assert!(
+                matches!(result_set.get(4).await.unwrap(), SqlValue::String(v) if v == "it's 世界")
+            );
+            assert!(
+                matches!(result_set.get(5).await.unwrap(), SqlValue::Binary(v) if *v == [0, 0xff, 0])
+            );
+            assert!(
+                matches!(result_set.get(6).await.unwrap(), SqlValue::Binary(v) if v.is_empty())
+            );
+            assert!(matches!(result_set.get(7).await.unwrap(), SqlValue::Null));
+
+            // fewer values than parameters.
+            let err = stmt.query(&[SqlValue::Int(7)]).await.err().unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), "expected 8 parameters, got 1");
+
+            // more values than parameters.
+            let values = (0..9).map(|_| SqlValue::Null).collect::<Vec<_>>();
+
+            let err = stmt.exec(&values).await.err().unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), "expected 8 parameters, got 9");
+
+            // a numbered parameter counts up to its number, a repeated name counts once.
+            let stmt = conn.prepare("SELECT ?3, :a, :a").await.unwrap();
+
+            assert!(stmt.query(&[1.into(), 2.into(), 3.into()]).await.is_err());
+            assert!(stmt
+                .query(&[1.into(), 2.into(), 3.into(), 4.into()])
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn test_nullable_column() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE item (id INTEGER PRIMARY KEY, price REAL, note TEXT)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let insert = conn
+                .prepare("INSERT INTO item (price, note) VALUES (?, ?)")
+                .await
+                .unwrap();
+
+            insert
+                .exec(&[SqlValue::Float(9.5), SqlValue::Null])
+                .await
+                .unwrap();
+
+            insert
+                .exec(&[SqlValue::Null, SqlValue::String(Cow::Borrowed("free"))])
+                .await
+                .unwrap();
+
+            let result_set = conn
+                .prepare("SELECT price, note FROM item ORDER BY id")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            // reading before the first row.
+            assert!(result_set.get(0).await.is_err());
+
+            assert!(result_set.next().await.unwrap());
+            assert!(matches!(result_set.get(0).await.unwrap(), SqlValue::Float(v) if v == 9.5));
+            assert!(result_set.get_nullable(1).await.unwrap().is_none());
+
+            assert!(result_set.next().await.unwrap());
+            assert!(result_set.get_nullable(0).await.unwrap().is_none());
+            assert!(matches!(result_set.get(1).await.unwrap(), SqlValue::String(v) if v == "free"));
+
+            assert!(!result_set.next().await.unwrap());
+
+            // reading after the last row.
+            assert!(result_set.get(0).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_bind_big_int() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            let stmt = conn.prepare("SELECT ?").await.unwrap();
+
+            for v in [i64::MAX, i64::MIN, 0] {
+                let result_set = stmt.query(&[SqlValue::BigInt(v as i128)]).await.unwrap();
+
+                assert!(result_set.next().await.unwrap());
+                assert!(matches!(result_set.get(0).await.unwrap(), SqlValue::Int(got) if got == v));
+            }
+
+            for v in [i64::MAX as i128 + 1, i64::MIN as i128 - 1, i128::MAX] {
+                let err = stmt.query(&[SqlValue::BigInt(v)]).await.err().unwrap();
+
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                // the error names the type of the parameter but never prints its value.
+                assert!(err.to_string().contains("BigInt parameter 1"));
+                assert!(!err.to_string().contains(&v.to_string()));
+            }
+        });
+    }
+
+    #[cfg(feature = "with-uuid")]
+    #[test]
+    fn test_uuid_round_trip() {
+        use uuid::Uuid;
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE account (id UUID PRIMARY KEY, name TEXT)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let id = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+
+            conn.prepare("INSERT INTO account VALUES (?, 'alice'), (X'00112233', 'bob')")
+                .await
+                .unwrap()
+                .exec(&[id.into()])
+                .await
+                .unwrap();
+
+            let result_set = conn
+                .prepare("SELECT id, typeof(id), length(id) FROM account WHERE name = 'alice'")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert!(matches!(result_set.get(0).await.unwrap(), SqlValue::Uuid(v) if v == id));
+            assert!(matches!(result_set.get(1).await.unwrap(), SqlValue::String(v) if v == "blob"));
+            assert!(matches!(
+                result_set.get(2).await.unwrap(),
+                SqlValue::Int(16)
+            ));
+
+            let result_set = conn
+                .prepare("SELECT id FROM account WHERE name = 'bob'")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+
+            let err = result_set.get(0).await.err().unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[cfg(feature = "with-chrono")]
+    #[test]
+    fn test_temporal_round_trip() {
+        use chrono::{NaiveDate, NaiveTime};
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare(
+                "CREATE TABLE event (d DATE, t TIME, ts TIMESTAMP, created DATETIME DEFAULT CURRENT_TIMESTAMP)",
+            )
+            .await
+            .unwrap()
+            .exec(&[])
+            .await
+            .unwrap();
+
+            let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+            let time = NaiveTime::from_hms_micro_opt(23, 59, 59, 999_999).unwrap();
+            let timestamp = date.and_time(time).and_utc();
+
+            conn.prepare("INSERT INTO event (d, t, ts) VALUES (?, ?, ?)")
+                .await
+                .unwrap()
+                .exec(&[date.into(), time.into(), timestamp.into()])
+                .await
+                .unwrap();
+
+            let result_set = conn
+                .prepare("SELECT d, t, ts, created, typeof(ts), ts FROM event")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+
+            assert!(matches!(result_set.get(0).await.unwrap(), SqlValue::Date(v) if v == date));
+            assert!(matches!(result_set.get(1).await.unwrap(), SqlValue::Time(v) if v == time));
+            assert!(
+                matches!(result_set.get(2).await.unwrap(), SqlValue::Timestamp(v) if v == timestamp)
+            );
+            assert!(matches!(
+                result_set.get(3).await.unwrap(),
+                SqlValue::Timestamp(_)
+            ));
+            assert!(matches!(result_set.get(4).await.unwrap(), SqlValue::String(v) if v == "text"));
+        });
+    }
+
+    #[test]
+    fn test_system_time_round_trip() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE event (at INTEGER)", &[])
+                .await
+                .unwrap();
+
+            // `SystemTime` is stored as milliseconds since the epoch.
+            let now = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+            let before_epoch = UNIX_EPOCH - Duration::from_millis(1500);
+
+            let stmt = conn
+                .prepare("INSERT INTO event (at) VALUES (?)")
+                .await
+                .unwrap();
+
+            for at in [now, before_epoch] {
+                stmt.exec(&[SqlValue::from(at)]).await.unwrap();
+            }
+
+            let result_set = conn
+                .query_one_shot("SELECT at, typeof(at) FROM event ORDER BY rowid", &[])
+                .await
+                .unwrap();
+
+            for expected in [now, before_epoch] {
+                assert!(result_set.next().await.unwrap());
+
+                let value = result_set.get(0).await.unwrap();
+
+                assert_eq!(value.as_system_time(), Some(expected));
+                assert_eq!(result_set.get(1).await.unwrap(), "integer".into());
+            }
+
+            assert!(!result_set.next().await.unwrap());
+        });
+    }
+
+    #[cfg(feature = "with-decimal")]
+    #[test]
+    fn test_decimal_round_trip() {
+        use std::str::FromStr;
+
+        use bigdecimal::BigDecimal;
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE amount (exact DECIMAL TEXT, price NUMERIC(10, 2))")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let insert = conn
+                .prepare("INSERT INTO amount VALUES (?, ?)")
+                .await
+                .unwrap();
+
+            let decimal = |v: &str| SqlValue::Decimal(BigDecimal::from_str(v).unwrap());
+
+            for (exact, price) in [
+                ("0.100", decimal("2.50")),
+                ("2.50", SqlValue::Int(2)),
+                ("1000000000000.000001", SqlValue::Null),
+            ] {
+                insert.exec(&[decimal(exact), price]).await.unwrap();
+            }
+
+            let result_set = conn
+                .prepare("SELECT exact, price FROM amount ORDER BY rowid")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            let col_types = result_set.column_types().await.unwrap();
+
+            assert_eq!(col_types[0].decimal_size, None);
+            assert_eq!(col_types[1].decimal_size, Some((10, 2)));
+
+            for (exact, price) in [
+                ("0.100", Some("2.50")),
+                ("2.50", Some("2.00")),
+                ("1000000000000.000001", None),
+            ] {
+                assert!(result_set.next().await.unwrap());
+
+                match result_set.get(0).await.unwrap() {
+                    SqlValue::Decimal(v) => assert_eq!(v.to_plain_string(), exact),
+                    _ => panic!("expect decimal {}", exact),
+                }
+
+                match (result_set.get(1).await.unwrap(), price) {
+                    (SqlValue::Decimal(v), Some(price)) => assert_eq!(v.to_plain_string(), price),
+                    (SqlValue::Null, None) => {}
+                    _ => panic!("expect price {:?}", price),
+                }
+            }
+        });
+    }
+
+    async fn count_log(conn: &rinq_rdbc::DbConn) -> i64 {
+        let result_set = conn
+            .prepare("SELECT count(*) FROM log")
+            .await
+            .unwrap()
+            .query(&[])
+            .await
+            .unwrap();
+
+        assert!(result_set.next().await.unwrap());
+
+        match result_set.get(0).await.unwrap() {
+            SqlValue::Int(count) => count,
+            _ => panic!("expect integer count"),
+        }
+    }
+
+    async fn insert_log(tx: &rinq_rdbc::Tx) {
+        tx.prepare("INSERT INTO log VALUES ('a'), ('b')")
+            .await
+            .unwrap()
+            .exec(&[])
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_transaction() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE log (message TEXT)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let tx = conn.begin().await.unwrap();
+
+            insert_log(&tx).await;
+
+            assert_eq!(count_log(&conn).await, 2);
+
+            tx.rollback().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 0);
+
+            // plain `BEGIN` can not be nested.
+            let tx = conn.begin().await.unwrap();
+
+            assert!(conn.begin().await.is_err());
+
+            insert_log(&tx).await;
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 2);
+
+            // no transaction is active.
+            assert!(tx.commit().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_transaction_panic() {
+        register_once();
+
+        let conn = block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE log (message TEXT)", &[])
+                .await
+                .unwrap();
+
+            conn
+        });
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            block_on(async {
+                let tx = conn.begin().await.unwrap();
+
+                insert_log(&tx).await;
+
+                panic!("abort the transaction");
+            })
+        }));
+
+        assert!(panicked.is_err());
+
+        block_on(async {
+            // the transaction was rolled back, so a new one can begin.
+            assert_eq!(count_log(&conn).await, 0);
+
+            let tx = conn.begin().await.unwrap();
+
+            insert_log(&tx).await;
+
+            // a dropped savepoint rolls back only the changes made since the savepoint.
+            let sp = tx.savepoint().await.unwrap();
+
+            insert_log(&sp).await;
+
+            drop(sp);
+
+            assert_eq!(tx.depth(), 0);
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 2);
+        });
+    }
+
+    #[test]
+    fn test_begin_with() {
+        register_once();
+
+        let path = std::env::temp_dir().join(format!(
+            "rinq-begin-with-{}.db",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+
+        block_on(async {
+            let source_name = path.to_str().unwrap();
+
+            let conn = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+            let other = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+
+            let tx = conn.begin_with(IsolationLevel::Serializable).await.unwrap();
+
+            // the write lock is held by `conn`.
+            let err = other
+                .begin_with(IsolationLevel::Serializable)
+                .await
+                .err()
+                .unwrap();
+
+            assert!(err.to_string().contains("database is locked"));
+
+            // a deferred transaction does not take the lock until the first write.
+            let other_tx = other.begin().await.unwrap();
+
+            other_tx.rollback().await.unwrap();
+            tx.commit().await.unwrap();
+        });
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot() {
+        async fn count(tx: &rinq_rdbc::Tx) -> i64 {
+            let rs = tx
+                .query_one_shot("SELECT count(*) FROM t", &[])
+                .await
+                .unwrap();
+
+            assert!(rs.next().await.unwrap());
+
+            rs.get_as(0).await.unwrap()
+        }
+
+        register_once();
+
+        let path = std::env::temp_dir().join(format!(
+            "rinq-snapshot-{}.db",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+
+        block_on(async {
+            let source_name = path.to_str().unwrap();
+
+            let writer = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+
+            writer
+                .execute("PRAGMA journal_mode = WAL", &[])
+                .await
+                .unwrap();
+            writer
+                .execute("CREATE TABLE t (id INTEGER)", &[])
+                .await
+                .unwrap();
+            writer
+                .execute("INSERT INTO t VALUES (1)", &[])
+                .await
+                .unwrap();
+
+            let reader = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+            let unpinned = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+
+            let tx = reader.begin().await.unwrap();
+            let unpinned_tx = unpinned.begin().await.unwrap();
+
+            tx.snapshot().await.unwrap();
+
+            writer
+                .execute("INSERT INTO t VALUES (2)", &[])
+                .await
+                .unwrap();
+
+            // the deferred transaction reads the database as of its first read.
+            assert_eq!(count(&unpinned_tx).await, 2);
+            assert_eq!(count(&tx).await, 1);
+
+            writer
+                .execute("INSERT INTO t VALUES (3)", &[])
+                .await
+                .unwrap();
+
+            assert_eq!(count(&tx).await, 1);
+
+            tx.commit().await.unwrap();
+            unpinned_tx.commit().await.unwrap();
+
+            let tx = reader.begin().await.unwrap();
+
+            assert_eq!(count(&tx).await, 3);
+
+            tx.commit().await.unwrap();
+        });
+
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.clone().into_os_string();
+
+            file.push(suffix);
+
+            _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn test_savepoint() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE log (message TEXT)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            // commit inner, then rollback outer.
+            let tx = conn.begin().await.unwrap();
+            let inner = tx.savepoint().await.unwrap();
+
+            insert_log(&inner).await;
+            inner.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 2);
+
+            tx.rollback().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 0);
+
+            // rollback inner, then commit outer.
+            let tx = conn.begin().await.unwrap();
+
+            insert_log(&tx).await;
+
+            let inner = tx.savepoint().await.unwrap();
+            let innermost = inner.savepoint().await.unwrap();
+
+            insert_log(&innermost).await;
+            innermost.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 4);
+
+            inner.rollback().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 2);
+
+            // a new savepoint starts where the finished one was.
+            let inner = tx.savepoint().await.unwrap();
+
+            insert_log(&inner).await;
+            inner.commit().await.unwrap();
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 4);
+        });
+    }
+
+    #[test]
+    fn test_savepoint_ended() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE log (message TEXT)", &[])
+                .await
+                .unwrap();
+
+            let tx = conn.begin().await.unwrap();
+            let outer = tx.savepoint().await.unwrap();
+            let inner = outer.savepoint().await.unwrap();
+
+            insert_log(&inner).await;
+
+            // rolling back the outer savepoint also ends the inner one.
+            outer.rollback().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 0);
+
+            for err in [
+                inner.commit().await.unwrap_err(),
+                inner.rollback().await.unwrap_err(),
+                inner.savepoint().await.err().unwrap(),
+                outer.commit().await.unwrap_err(),
+            ] {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            }
+
+            // the savepoints opened later are not mistaken for the ended ones.
+            let outer = tx.savepoint().await.unwrap();
+            let inner = outer.savepoint().await.unwrap();
+
+            insert_log(&inner).await;
+
+            outer.commit().await.unwrap();
+
+            assert_eq!(
+                inner.rollback().await.unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 2);
+
+            // the handles of an ended transaction do not touch the next one.
+            let next = conn.begin().await.unwrap();
+
+            insert_log(&next).await;
+
+            for err in [
+                tx.rollback().await.unwrap_err(),
+                outer.rollback().await.unwrap_err(),
+                tx.snapshot().await.unwrap_err(),
+            ] {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            }
+
+            next.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 4);
+        });
+    }
+
+    #[test]
+    fn test_expand_in_clause() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT)", &[])
+                .await
+                .unwrap();
+
+            for name in ["alice", "bob", "carol", "dave", "erin"] {
+                conn.execute("INSERT INTO user (name) VALUES (?)", &[name.into()])
+                    .await
+                    .unwrap();
+            }
+
+            let params = ["carol".into()];
+            let ids = [1, 3, 4].map(SqlValue::Int);
+
+            let (query, values) = rinq_rdbc::expand_in_clause(
+                "SELECT name FROM user WHERE id IN (...?) AND name != ? ORDER BY id",
+                &params,
+                &ids,
+                32766,
+            )
+            .unwrap();
+
+            let result_set = conn.query_one_shot(query, &values).await.unwrap();
+
+            let mut names = vec![];
+
+            while let Some(row) = result_set.next_row().await.unwrap() {
+                names.push(row.get::<String>(0).unwrap());
+            }
+
+            assert_eq!(names, ["alice", "dave"]);
+        });
+    }
+
+    #[test]
+    fn test_exec_batch() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE log (message TEXT UNIQUE)", &[])
+                .await
+                .unwrap();
+
+            let stmt = conn.prepare("INSERT INTO log VALUES (?)").await.unwrap();
+
+            let rows = ["a", "b", "c"]
+                .iter()
+                .map(|message| vec![(*message).into()])
+                .collect::<Vec<_>>();
+
+            assert_eq!(stmt.exec_batch(&rows).await.unwrap(), (3, 3));
+
+            // the middle row violates the unique constraint, the first one is discarded as well.
+            let rows = ["d", "a", "e"]
+                .iter()
+                .map(|message| vec![(*message).into()])
+                .collect::<Vec<_>>();
+
+            let err = stmt.exec_batch(&rows).await.err().unwrap();
+
+            assert!(err.to_string().contains("UNIQUE constraint failed"));
+            assert_eq!(count_log(&conn).await, 3);
+
+            // in a transaction, the batch is rolled back to a savepoint and the transaction goes on.
+            let tx = conn.begin().await.unwrap();
+
+            let stmt = tx.prepare("INSERT INTO log VALUES (?)").await.unwrap();
+
+            stmt.exec(&["f".into()]).await.unwrap();
+
+            assert!(stmt.exec_batch(&rows).await.is_err());
+            assert_eq!(tx.depth(), 0);
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 4);
+        });
+    }
+
+    #[test]
+    fn test_begin_with_stmt() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE log (message TEXT)", &[])
+                .await
+                .unwrap();
+
+            let (tx, stmt) = conn
+                .begin_with_stmt("INSERT INTO log VALUES (?)")
+                .await
+                .unwrap();
+
+            stmt.exec(&["a".into()]).await.unwrap();
+            tx.rollback().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 0);
+
+            // a failed prepare rolls back the transaction it started.
+            let err = conn
+                .begin_with_stmt("INSERT INTO missing VALUES (?)")
+                .await
+                .err()
+                .unwrap();
+
+            assert!(err.to_string().contains("no such table: missing"));
+
+            // the connection is back in autocommit mode, so a new transaction can start.
+            conn.begin().await.unwrap().rollback().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_savepoint_depth() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            let tx = conn.begin().await.unwrap();
+
+            assert_eq!(tx.depth(), 0);
+
+            let inner = tx.savepoint().await.unwrap();
+            let innermost = inner.savepoint().await.unwrap();
+
+            assert_eq!(tx.depth(), 2);
+            assert_eq!(innermost.depth(), 2);
+
+            innermost.commit().await.unwrap();
+
+            assert_eq!(tx.depth(), 1);
+
+            let innermost = inner.savepoint().await.unwrap();
+
+            innermost.rollback().await.unwrap();
+
+            assert_eq!(inner.depth(), 1);
+
+            // releasing a savepoint also releases the savepoints nested in it.
+            let _innermost = inner.savepoint().await.unwrap();
+
+            assert_eq!(tx.depth(), 2);
+
+            // a savepoint opened while a deeper one is active is pushed on top of it.
+            let sibling = inner.savepoint().await.unwrap();
+
+            assert_eq!(tx.depth(), 3);
+
+            sibling.commit().await.unwrap();
+
+            assert_eq!(tx.depth(), 2);
+
+            inner.commit().await.unwrap();
+
+            assert_eq!(tx.depth(), 0);
+
+            let inner = tx.savepoint().await.unwrap();
+
+            assert_eq!(inner.depth(), 1);
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(inner.depth(), 0);
+
+            // a new transaction starts at depth 0.
+            assert_eq!(conn.begin().await.unwrap().depth(), 0);
+        });
+    }
+
+    #[test]
+    fn test_schema_change() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE user (id INTEGER)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let stmt = conn.prepare("SELECT * FROM user").await.unwrap();
+
+            assert_eq!(
+                stmt.query(&[]).await.unwrap().columns().await.unwrap(),
+                ["id"]
+            );
+
+            conn.prepare("ALTER TABLE user ADD COLUMN name TEXT")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            // `sqlite3_prepare_v2` statements are re-prepared on `SQLITE_SCHEMA`.
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert!(!result_set.next().await.unwrap());
+            assert_eq!(result_set.columns().await.unwrap(), ["id", "name"]);
+        });
+    }
+
+    #[test]
+    fn test_derive_from_row() {
+        use rinq_rdbc::FromRow;
+
+        #[derive(FromRow)]
+        struct User {
+            id: i64,
+            #[rdbc(rename = "user_name")]
+            name: String,
+            email: Option<String>,
+            active: bool,
+        }
+
+        #[derive(FromRow)]
+        struct Missing {
+            #[allow(dead_code)]
+            nickname: String,
+        }
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            for query in [
+                "CREATE TABLE user (id INTEGER PRIMARY KEY, user_name TEXT, email TEXT, active BOOLEAN)",
+                "INSERT INTO user (user_name, email, active) VALUES ('alice', 'alice@example.com', 1), ('bob', NULL, 0)",
+            ] {
+                conn.prepare(query).await.unwrap().exec(&[]).await.unwrap();
+            }
+
+            let stmt = conn
+                .prepare("SELECT * FROM user ORDER BY id")
+                .await
+                .unwrap();
+
+            let users = stmt
+                .query(&[])
+                .await
+                .unwrap()
+                .collect::<User>()
+                .await
+                .unwrap();
+
+            assert_eq!(users.len(), 2);
+
+            assert_eq!(users[0].id, 1);
+            assert_eq!(users[0].name, "alice");
+            assert_eq!(users[0].email.as_deref(), Some("alice@example.com"));
+            assert!(users[0].active);
+
+            assert_eq!(users[1].name, "bob");
+            assert_eq!(users[1].email, None);
+            assert!(!users[1].active);
+
+            let err = stmt
+                .query(&[])
+                .await
+                .unwrap()
+                .collect::<Missing>()
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert!(err.to_string().starts_with("Missing.nickname: "));
+        });
+    }
+
+    #[test]
+    fn test_prepare_typed() {
+        use rinq_rdbc::{FromRow, TypedStmt};
+
+        #[derive(Debug, PartialEq, FromRow)]
+        struct User {
+            id: i64,
+            name: String,
+        }
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT)", &[])
+                .await
+                .unwrap();
+
+            let insert = conn
+                .prepare_typed::<(i64, &str), ()>("INSERT INTO user (id, name) VALUES (?, ?)")
+                .await
+                .unwrap();
+
+            for (id, name) in [(1, "alice"), (2, "bob"), (3, "carol")] {
+                assert_eq!(insert.exec((id, name)).await.unwrap(), (id, 1));
+            }
+
+            let select: TypedStmt<(i64,), User> = conn
+                .prepare_typed("SELECT id, name FROM user WHERE id >= ? ORDER BY id")
+                .await
+                .unwrap();
+
+            assert_eq!(
+                select.query((2,)).await.unwrap(),
+                [
+                    User {
+                        id: 2,
+                        name: "bob".to_owned()
+                    },
+                    User {
+                        id: 3,
+                        name: "carol".to_owned()
+                    }
+                ]
+            );
+
+            assert_eq!(
+                select.query_one((3,)).await.unwrap(),
+                Some(User {
+                    id: 3,
+                    name: "carol".to_owned()
+                })
+            );
+            assert_eq!(select.query_one((4,)).await.unwrap(), None);
+
+            // the number of values is still checked by the untyped statement.
+            let err = conn
+                .prepare_typed::<(i64, i64), User>("SELECT id, name FROM user WHERE id = ?")
+                .await
+                .unwrap()
+                .query((1, 2))
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        });
+    }
+
+    #[test]
+    fn test_sql_macro() {
+        use rinq_rdbc::sql;
+
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            sql!("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT)")
+                .execute(&conn)
+                .await
+                .unwrap();
+
+            for (id, name) in [(1, "alice"), (2, "bob")] {
+                sql!("INSERT INTO user (id, name) VALUES (?, ?)", id, name)
+                    .execute(&conn)
+                    .await
+                    .unwrap();
+            }
+
+            let rs = sql!("SELECT name FROM user WHERE id = ?", 2)
+                .query(&conn)
+                .await
+                .unwrap();
+
+            assert!(rs.next().await.unwrap());
+            assert_eq!(rs.get_as::<String>(0).await.unwrap(), "bob");
+        });
+    }
+
+    #[test]
+    fn test_exec_query() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute(
+                "CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT, created_at INTEGER DEFAULT 1700000000)",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            let stmt = conn
+                .prepare("INSERT INTO user (name) VALUES (?), (?) RETURNING id, created_at")
+                .await
+                .unwrap();
+
+            let ((last_insert_id, rows_affected), rows) = stmt
+                .exec_query(&["alice".into(), "bob".into()])
+                .await
+                .unwrap();
+
+            assert_eq!((last_insert_id, rows_affected), (2, 2));
+
+            assert_eq!(
+                rows.iter()
+                    .map(|row| (
+                        row.get_by_name("id").unwrap(),
+                        row.get_by_name("created_at").unwrap()
+                    ))
+                    .collect::<Vec<_>>(),
+                [(1_i64, 1700000000_i64), (2, 1700000000)]
+            );
+
+            // a statement without rows returns its result as well.
+            let ((_, rows_affected), rows) = conn
+                .prepare("DELETE FROM user WHERE id = ?")
+                .await
+                .unwrap()
+                .exec_query(&[SqlValue::Int(1)])
+                .await
+                .unwrap();
+
+            assert_eq!(rows_affected, 1);
+            assert!(rows.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_finalized_stmt() {
+        let driver = Sqlite::default();
+
+        let conn = driver.start_connect(":memory:").unwrap();
+
+        let stmt = driver.start_prepare(&conn, "SELECT ?").unwrap();
+
+        downcast::<DbStmt>(&stmt, "statement")
+            .unwrap()
+            .raw
+            .finalize();
+
+        for err in [
+            driver.start_query(&stmt, &[SqlValue::Int(1)]).err(),
+            driver.start_exec(&stmt, &[SqlValue::Int(1)]).err(),
+            driver
+                .start_query_named(&stmt, &[("?1", SqlValue::Int(1))])
+                .err(),
+        ] {
+            let err = err.unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), "sqlite: statement finalized");
+        }
+    }
+
+    #[test]
+    fn test_named_params() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.prepare("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT, email TEXT)")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let insert = conn
+                .prepare("INSERT INTO user (name, email) VALUES (:name, @email)")
+                .await
+                .unwrap();
+
+            // the order of `params` does not matter.
+            insert
+                .exec_named(&[
+                    ("@email", "alice@example.com".into()),
+                    (":name", "alice".into()),
+                ])
+                .await
+                .unwrap();
+
+            // a bare name matches any prefix, a missing parameter is `NULL`.
+            let (id, _) = insert.exec_named(&[("name", "bob".into())]).await.unwrap();
+
+            let select = conn
+                .prepare("SELECT name, email FROM user WHERE id = $id OR name = :name ORDER BY id")
+                .await
+                .unwrap();
+
+            let rs = select
+                .query_named(&[("$id", id.into()), ("name", "alice".into())])
+                .await
+                .unwrap();
+
+            let mut rows = vec![];
+
+            while let Some(row) = rs.next_row().await.unwrap() {
+                rows.push((
+                    row.get::<String>(0).unwrap(),
+                    row.get::<Option<String>>(1).unwrap(),
+                ));
+            }
+
+            assert_eq!(
+                rows,
+                [
+                    ("alice".to_owned(), Some("alice@example.com".to_owned())),
+                    ("bob".to_owned(), None)
+                ]
+            );
+
+            let err = select
+                .query_named(&[(":missing", 1.into())])
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains(":missing"));
+
+            assert_eq!(
+                insert
+                    .exec_named(&[(":email", "x".into())])
+                    .await
+                    .err()
+                    .unwrap()
+                    .kind(),
+                io::ErrorKind::InvalidInput
+            );
+        });
+    }
+
+    #[test]
+    fn test_execute() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT)", &[])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                conn.execute("INSERT INTO user (name) VALUES (?)", &["alice".into()])
+                    .await
+                    .unwrap(),
+                (1, 1)
+            );
+
+            let tx = conn.begin().await.unwrap();
+
+            assert_eq!(
+                tx.execute(
+                    "INSERT INTO user (name) VALUES (?), (?)",
+                    &["bob".into(), "carol".into()]
+                )
+                .await
+                .unwrap(),
+                (3, 2)
+            );
+
+            tx.commit().await.unwrap();
+
+            // the result set outlives the dropped statement.
+            let result_set = conn
+                .query_one_shot(
+                    "SELECT name FROM user WHERE id > ? ORDER BY id",
+                    &[1.into()],
+                )
+                .await
+                .unwrap();
+
+            let mut names = vec![];
+
+            while let Some(row) = result_set.next_row().await.unwrap() {
+                names.push(row.get::<String>(0).unwrap());
+            }
+
+            assert_eq!(names, ["bob", "carol"]);
+
+            assert!(conn
+                .execute("INSERT INTO missing VALUES (1)", &[])
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_total_changes() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            assert_eq!(conn.total_changes().await.unwrap(), 0);
+
+            for query in [
+                "CREATE TABLE log (id INTEGER PRIMARY KEY, message TEXT)",
+                "INSERT INTO log (message) VALUES ('a'), ('b'), ('c')",
+                "UPDATE log SET message = 'x' WHERE id < 3",
+                "DELETE FROM log WHERE id = 3",
+                "SELECT * FROM log",
+            ] {
+                conn.prepare(query).await.unwrap().exec(&[]).await.unwrap();
+            }
+
+            // 3 inserts, 2 updates and 1 delete.
+            assert_eq!(conn.total_changes().await.unwrap(), 6);
+
+            let ((_, rows_affected), _) = conn.exec_keep("DELETE FROM log", &[]).await.unwrap();
+
+            // the statement only reports its own changes.
+            assert_eq!(rows_affected, 2);
+            assert_eq!(conn.total_changes().await.unwrap(), 8);
+        });
+    }
+
+    #[test]
+    fn test_tx_aborted() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            for query in [
+                "CREATE TABLE log (id INTEGER PRIMARY KEY, message TEXT NOT NULL UNIQUE)",
+                "CREATE TRIGGER panic BEFORE INSERT ON log WHEN NEW.message = 'panic' \
+                 BEGIN SELECT RAISE(ROLLBACK, 'panic'); END",
+            ] {
+                conn.prepare(query).await.unwrap().exec(&[]).await.unwrap();
+            }
+
+            let tx = conn.begin().await.unwrap();
+
+            let insert = tx
+                .prepare("INSERT INTO log (message) VALUES (?)")
+                .await
+                .unwrap();
+
+            insert.exec(&["a".into()]).await.unwrap();
+
+            // a constraint violation fails the statement only.
+            let err = insert.exec(&["a".into()]).await.err().unwrap();
+
+            assert!(err.to_string().contains("UNIQUE constraint failed"));
+            assert!(!tx.is_aborted());
+
+            insert.exec(&["b".into()]).await.unwrap();
+
+            tx.commit().await.unwrap();
+
+            assert_eq!(count_log(&conn).await, 2);
+
+            let tx = conn.begin().await.unwrap();
+
+            let insert = tx
+                .prepare("INSERT INTO log (message) VALUES (?)")
+                .await
+                .unwrap();
+
+            insert.exec(&["c".into()]).await.unwrap();
+
+            assert!(insert.exec(&["panic".into()]).await.is_err());
+            assert!(tx.is_aborted());
+
+            // the insert of `c` is rolled back with the transaction.
+            assert_eq!(count_log(&conn).await, 2);
+        });
+    }
+
+    #[test]
+    fn test_errors() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            assert!(conn.last_error().is_none());
+
+            let err = conn.prepare("SELECT * FROM missing").await.err().unwrap();
+
+            assert!(err.to_string().contains("no such table: missing"));
+            assert!(conn
+                .last_error()
+                .unwrap()
+                .to_string()
+                .contains("no such table: missing"));
+
+            assert_eq!(
+                conn.prepare("-- nothing").await.err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        });
+    }
+
+    #[test]
+    fn test_column_origin() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            conn.execute(
+                "CREATE TABLE posts (id INTEGER PRIMARY KEY, author INTEGER)",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            let stmt = conn
+                .prepare(
+                    "SELECT p.id, u.name AS author_name, 1 + 1 FROM posts p JOIN users u ON u.id = p.author",
+                )
+                .await
+                .unwrap();
+
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert_eq!(
+                result_set.column_origin(3).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            #[cfg(feature = "with-column-metadata")]
+            {
+                assert_eq!(
+                    result_set.column_origin(0).unwrap(),
+                    Some(("posts".to_owned(), "id".to_owned()))
+                );
+
+                assert_eq!(
+                    result_set.column_origin(1).unwrap(),
+                    Some(("users".to_owned(), "name".to_owned()))
+                );
+            }
+
+            #[cfg(not(feature = "with-column-metadata"))]
+            assert_eq!(result_set.column_origin(1).unwrap(), None);
+
+            assert_eq!(result_set.column_origin(2).unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn test_lazy_col_types() {
+        register_once();
+
+        let calls = || crate::tests::DECLTYPE_CALLS.with(|calls| calls.get());
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE t (id INTEGER, price DECIMAL(10, 2))", &[])
+                .await
+                .unwrap();
+
+            let rs = conn
+                .query_one_shot("SELECT id, price FROM t", &[])
+                .await
+                .unwrap();
+
+            let before = calls();
+
+            assert_eq!(rs.columns().await.unwrap(), ["id", "price"]);
+
+            // the names do not resolve the declared types.
+            assert_eq!(calls(), before);
+
+            let col_types = rs.column_types().await.unwrap();
+
+            assert_eq!(col_types[1].database_type_name, "DECIMAL(10, 2)");
+            assert_eq!(col_types[1].decimal_size, Some((10, 2)));
+            assert_eq!(calls(), before + 2);
+
+            // the types are cached by the result set.
+            rs.column_types().await.unwrap();
+
+            assert_eq!(calls(), before + 2);
+        });
+    }
+
+    #[test]
+    fn test_describe() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute(
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT, b REAL)",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            let stmt = conn
+                .prepare("SELECT a, b FROM t WHERE id = :id")
+                .await
+                .unwrap();
+
+            let info = stmt.describe().unwrap();
+
+            assert_eq!(info.parameter_count, 1);
+            assert_eq!(info.parameter_names, [Some(":id".to_owned())]);
+            assert_eq!(info.column_count, 2);
+            assert_eq!(info.column_names, ["a", "b"]);
+            assert_eq!(
+                info.column_types
+                    .iter()
+                    .map(|col_type| col_type.database_type_name.as_ref())
+                    .collect::<Vec<_>>(),
+                ["TEXT", "REAL"]
+            );
+            assert!(info.is_readonly);
+
+            assert_eq!(
+                stmt.parameter_name(1).err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            let stmt = conn
+                .prepare("INSERT INTO t (a, b) VALUES (?, ?)")
+                .await
+                .unwrap();
+
+            let info = stmt.describe().unwrap();
+
+            assert_eq!(info.parameter_names, [None, None]);
+            assert_eq!(info.column_count, 0);
+            assert!(!info.is_readonly);
+        });
+    }
+
+    #[test]
+    fn test_scan_status() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, v INTEGER)", &[])
+                .await
+                .unwrap();
+            conn.execute("INSERT INTO t (v) VALUES (1), (2), (3)", &[])
+                .await
+                .unwrap();
+
+            let stmt = conn.prepare("SELECT * FROM t WHERE v > 1").await.unwrap();
+
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            while result_set.next().await.unwrap() {}
+
+            #[cfg(feature = "with-scanstatus")]
+            {
+                let scan_status = stmt.scan_status().unwrap();
+
+                assert_eq!(scan_status.len(), 1);
+                assert_eq!(scan_status[0].n_loop, 1);
+                assert_eq!(scan_status[0].n_visit, 3);
+                assert_eq!(scan_status[0].name, "t");
+            }
+
+            #[cfg(not(feature = "with-scanstatus"))]
+            assert_eq!(
+                stmt.scan_status().err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_rowid() {
+        register_once();
+
+        rinq_rdbc::register(
+            "sqlite-rowid",
+            Sqlite::with_options(SqliteOptions::default().track_rowid()).unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-rowid", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE t (name TEXT)", &[])
+                .await
+                .unwrap();
+            conn.execute("CREATE TABLE u (t_name TEXT)", &[])
+                .await
+                .unwrap();
+            conn.execute("INSERT INTO t VALUES ('a'), ('b'), ('c')", &[])
+                .await
+                .unwrap();
+            conn.execute("DELETE FROM t WHERE name = 'b'", &[])
+                .await
+                .unwrap();
+
+            let stmt = conn
+                .prepare("SELECT name FROM t ORDER BY 1 DESC")
+                .await
+                .unwrap();
+
+            assert_eq!(stmt.describe().unwrap().column_names, ["name"]);
+
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert_eq!(
+                result_set.rowid().await.err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            assert_eq!(result_set.columns().await.unwrap(), ["name"]);
+
+            let mut rows = vec![];
+
+            while result_set.next().await.unwrap() {
+                rows.push((
+                    result_set.get(0).await.unwrap(),
+                    result_set.rowid().await.unwrap(),
+                ));
+
+                assert!(result_set.get(1).await.is_err());
+            }
+
+            assert_eq!(
+                rows,
+                [
+                    (SqlValue::String("c".into()), 3),
+                    (SqlValue::String("a".into()), 1)
+                ]
+            );
+
+            // `rowid` is ambiguous in a join, which is prepared unchanged.
+            conn.execute("INSERT INTO u VALUES ('a')", &[])
+                .await
+                .unwrap();
+
+            let result_set = conn
+                .query_one_shot("SELECT name FROM t JOIN u ON t.name = u.t_name", &[])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(
+                result_set.rowid().await.err().unwrap().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            let result_set = conn.query_one_shot("SELECT 1", &[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(
+                result_set.rowid().await.err().unwrap().kind(),
+                io::ErrorKind::Unsupported
+            );
+        });
+    }
+
+    #[test]
+    fn test_upsert() {
+        register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            assert!(conn.capabilities().upsert_on_conflict);
+
+            conn.execute(
+                "CREATE TABLE kv (k TEXT PRIMARY KEY, v TEXT, hits INTEGER DEFAULT 0)",
+                &[],
+            )
+            .await
+            .unwrap();
+            conn.execute(
+                "CREATE TRIGGER kv_hits AFTER UPDATE OF v ON kv BEGIN
+                 UPDATE kv SET hits = hits + 1 WHERE k = new.k;
+                 END",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            for v in ["a", "b"] {
+                conn.upsert("kv", &["k"], &[("k", "x".into()), ("v", v.into())], &["v"])
+                    .await
+                    .unwrap();
+            }
+
+            // a conflicting row is left unchanged without update columns.
+            conn.upsert("kv", &["k"], &[("k", "x".into()), ("v", "c".into())], &[])
+                .await
+                .unwrap();
+
+            let rs = conn
+                .query_one_shot("SELECT count(*), max(v), max(hits) FROM kv", &[])
+                .await
+                .unwrap();
+
+            assert!(rs.next().await.unwrap());
+            assert_eq!(rs.get(0).await.unwrap(), SqlValue::Int(1));
+            assert_eq!(rs.get(1).await.unwrap(), SqlValue::String("b".into()));
+            // the second upsert ran the update path.
+            assert_eq!(rs.get(2).await.unwrap(), SqlValue::Int(1));
+        });
+    }
+
+    #[test]
+    fn test_strict_bind() {
+        register_once();
+
+        rinq_rdbc::register(
+            "sqlite-strict-bind",
+            Sqlite::with_options(SqliteOptions::default().strict_bind()).unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            for driver in ["sqlite", "sqlite-strict-bind"] {
+                let conn = rinq_rdbc::open(driver, ":memory:").await.unwrap();
+
+                conn.execute(
+                    "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL)",
+                    &[],
+                )
+                .await
+                .unwrap();
+                conn.execute("INSERT INTO users VALUES (42, 'alice', 1.5)", &[])
+                    .await
+                    .unwrap();
+
+                let stmt = conn
+                    .prepare("SELECT name FROM users u WHERE u.id = ? AND name <> ?")
+                    .await
+                    .unwrap();
+
+                let result = stmt.query(&["42".into(), "bob".into()]).await;
+
+                if driver == "sqlite" {
+                    // sqlite compares the text with the integer column.
+                    assert!(result.is_ok());
+                    continue;
+                }
+
+                let err = result.err().unwrap();
+
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+                assert!(err.to_string().contains("bind type mismatch"));
+                assert!(err.to_string().contains("INTEGER column id"));
+
+                // a text column takes a string, an integer column takes an integer.
+                let rs = stmt.query(&[42.into(), "bob".into()]).await.unwrap();
+
+                assert!(rs.next().await.unwrap());
+                assert_eq!(rs.get(0).await.unwrap(), SqlValue::String("alice".into()));
+
+                let stmt = conn
+                    .prepare("UPDATE users SET name = :name WHERE :score < score")
+                    .await
+                    .unwrap();
+
+                assert!(stmt
+                    .exec_named(&[(":name", "carol".into()), (":score", "1".into())])
+                    .await
+                    .is_err());
+                assert_eq!(
+                    stmt.exec_named(&[(":name", "carol".into()), (":score", 1.into())])
+                        .await
+                        .unwrap()
+                        .1,
+                    1
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,234 @@
+use std::{future::Future, io};
+
+use rinq_rdbc::{DbConn, SqlValue};
+
+/// The checks run by [`HealthCheck::health_check`], all are enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthOptions {
+    /// Run `SELECT 1`.
+    pub ping: bool,
+    /// Run `PRAGMA quick_check`, which reads every page of the database and is slow for large databases.
+    pub quick_check: bool,
+    /// Create a temporary table and insert a row inside a savepoint, which is rolled back.
+    /// The check is skipped if the connection is read-only by `PRAGMA query_only`.
+    pub write: bool,
+}
+
+impl Default for HealthOptions {
+    fn default() -> Self {
+        Self {
+            ping: true,
+            quick_check: true,
+            write: true,
+        }
+    }
+}
+
+/// The result of one check of a [`HealthReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check succeeded.
+    Passed,
+    /// The check is disabled by the [`HealthOptions`] or does not apply to the connection.
+    Skipped,
+    /// The check failed with the error message.
+    Failed(String),
+}
+
+/// The report of [`HealthCheck::health_check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The result of [`HealthOptions::ping`].
+    pub ping: CheckStatus,
+    /// The result of [`HealthOptions::quick_check`].
+    pub quick_check: CheckStatus,
+    /// The result of [`HealthOptions::write`].
+    pub write: CheckStatus,
+}
+
+impl HealthReport {
+    /// Returns true if no check failed.
+    pub fn is_healthy(&self) -> bool {
+        [&self.ping, &self.quick_check, &self.write]
+            .iter()
+            .all(|status| !matches!(status, CheckStatus::Failed(_)))
+    }
+}
+
+/// Readiness probe of a [`DbConn`] opened with the [`Sqlite`](crate::Sqlite) driver.
+pub trait HealthCheck {
+    /// Run the checks enabled by `opts` one after another and report the result of each,
+    /// a failed check does not stop the following ones.
+    ///
+    /// The write check uses a savepoint, so it can run inside a transaction of the connection.
+    fn health_check(&self, opts: HealthOptions) -> impl Future<Output = io::Result<HealthReport>>;
+}
+
+impl HealthCheck for DbConn {
+    async fn health_check(&self, opts: HealthOptions) -> io::Result<HealthReport> {
+        let ping = if opts.ping {
+            status(ping(self).await)
+        } else {
+            CheckStatus::Skipped
+        };
+
+        let quick_check = if opts.quick_check {
+            status(quick_check(self).await)
+        } else {
+            CheckStatus::Skipped
+        };
+
+        let write = if opts.write {
+            match query_int(self, "PRAGMA query_only").await {
+                Ok(1) => CheckStatus::Skipped,
+                Ok(_) => status(write(self).await),
+                Err(err) => CheckStatus::Failed(err.to_string()),
+            }
+        } else {
+            CheckStatus::Skipped
+        };
+
+        Ok(HealthReport {
+            ping,
+            quick_check,
+            write,
+        })
+    }
+}
+
+fn status(result: io::Result<()>) -> CheckStatus {
+    match result {
+        Ok(()) => CheckStatus::Passed,
+        Err(err) => CheckStatus::Failed(err.to_string()),
+    }
+}
+
+/// Returns the integer in the first column of the first row returned by `query`.
+async fn query_int(conn: &DbConn, query: &str) -> io::Result<i64> {
+    let result_set = conn.query_one_shot(query, &[]).await?;
+
+    if !result_set.next().await? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("sqlite: no row returned by {}", query),
+        ));
+    }
+
+    result_set.get_as(0).await
+}
+
+async fn ping(conn: &DbConn) -> io::Result<()> {
+    match query_int(conn, "SELECT 1").await? {
+        1 => Ok(()),
+        value => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("sqlite: SELECT 1 returned {}", value),
+        )),
+    }
+}
+
+/// `quick_check` returns a single `ok` row, or a row for each problem found.
+async fn quick_check(conn: &DbConn) -> io::Result<()> {
+    let result_set = conn.query_one_shot("PRAGMA quick_check", &[]).await?;
+
+    let mut problems = vec![];
+
+    while result_set.next().await? {
+        match result_set.get(0).await? {
+            SqlValue::String(message) if message == "ok" => {}
+            value => problems.push(format!("{:?}", value)),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("sqlite: quick_check: {}", problems.join(", ")),
+        ))
+    }
+}
+
+async fn write(conn: &DbConn) -> io::Result<()> {
+    conn.execute("SAVEPOINT rinq_health_check", &[]).await?;
+
+    let result = async {
+        conn.execute("CREATE TEMP TABLE rinq_health_check (x INTEGER)", &[])
+            .await?;
+        conn.execute("INSERT INTO rinq_health_check VALUES (1)", &[])
+            .await?;
+
+        Ok(())
+    }
+    .await;
+
+    // the table is dropped by the rollback, the savepoint itself is removed by the release.
+    let rollback = async {
+        conn.execute("ROLLBACK TO rinq_health_check", &[]).await?;
+        conn.execute("RELEASE rinq_health_check", &[]).await?;
+
+        Ok::<_, io::Error>(())
+    }
+    .await;
+
+    result.and(rollback)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::Schema;
+
+    use super::*;
+
+    #[test]
+    fn test_health_check() {
+        crate::tests::register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            let report = conn.health_check(HealthOptions::default()).await.unwrap();
+
+            assert_eq!(
+                report,
+                HealthReport {
+                    ping: CheckStatus::Passed,
+                    quick_check: CheckStatus::Passed,
+                    write: CheckStatus::Passed,
+                }
+            );
+            assert!(report.is_healthy());
+
+            // the write check leaves nothing behind and works inside a transaction.
+            assert!(!conn.table_exists("temp.rinq_health_check").await.unwrap());
+
+            let tx = conn.begin().await.unwrap();
+
+            let report = conn.health_check(HealthOptions::default()).await.unwrap();
+
+            assert_eq!(report.write, CheckStatus::Passed);
+
+            tx.commit().await.unwrap();
+
+            let report = conn
+                .health_check(HealthOptions {
+                    quick_check: false,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(report.quick_check, CheckStatus::Skipped);
+
+            conn.execute("PRAGMA query_only = ON", &[]).await.unwrap();
+
+            let report = conn.health_check(HealthOptions::default()).await.unwrap();
+
+            assert_eq!(report.write, CheckStatus::Skipped);
+            assert!(report.is_healthy());
+        });
+    }
+}
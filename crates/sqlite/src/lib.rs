@@ -1,27 +1,160 @@
 use std::{
-    ffi::{CStr, CString},
+    borrow::Cow,
+    ffi::{c_int, CStr, CString},
+    fmt::Display,
     io,
-    ops::Deref,
     ptr::null_mut,
+    slice::from_raw_parts,
     str::from_utf8_unchecked,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use rinq_rdbc::{ScanStatus, SqlValue};
 use sqlite3_sys as ffi;
 
 use ffi::{
-    sqlite3_errcode, sqlite3_errmsg, sqlite3_prepare_v2, SQLITE_OK, SQLITE_OPEN_CREATE,
-    SQLITE_OPEN_FULLMUTEX, SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI,
+    sqlite3_errcode, sqlite3_errmsg, sqlite3_extended_errcode, sqlite3_prepare_v2, SQLITE_BLOB,
+    SQLITE_DONE, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_OK, SQLITE_OPEN_CREATE,
+    SQLITE_OPEN_FULLMUTEX, SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI, SQLITE_ROW, SQLITE_TEXT,
+    SQLITE_TRANSIENT,
 };
 
+#[cfg(all(feature = "bundled", feature = "system"))]
+compile_error!("features `bundled` and `system` are mutually exclusive, disable the default features to use `system`");
+
+#[cfg(not(any(feature = "bundled", feature = "system")))]
+compile_error!("either feature `bundled` or `system` must be enabled to link sqlite");
+
+mod aggregate;
+pub use aggregate::*;
+mod attach;
+pub use attach::*;
+mod decimal;
+mod driver;
+pub use driver::*;
+mod health;
+pub use health::*;
+mod limit;
+mod migrate;
+pub use migrate::*;
+mod options;
+pub use options::*;
+mod rowid;
+mod schema;
+pub use schema::*;
+mod session;
+mod strict;
+#[cfg(feature = "with-chrono")]
+mod temporal;
+#[cfg(feature = "with-uuid")]
+mod uuid_blob;
+mod vacuum;
+pub use vacuum::*;
+mod watch;
+
+use driver::TxStack;
+use limit::ConnectionPermit;
+use session::Session;
+use strict::ParamType;
+use watch::Watchers;
+
+/// The minimum linked sqlite version this crate supports, `3.35.0` is the first release with `RETURNING`.
+pub const MIN_SQLITE_VERSION_NUMBER: i32 = 3035000;
+
+/// Returns the version string of the linked sqlite library, e.g. `3.45.3`.
+///
+/// Which library is linked is selected by the `bundled` (default) and `system` features:
+/// `bundled` compiles the amalgamation shipped with `sqlite3-src`,
+/// `system` links the library found by pkg-config and requires `default-features = false`.
+pub fn sqlite_version() -> &'static str {
+    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::sqlite3_libversion()).to_bytes()) }
+}
+
+/// Returns the version number of the linked sqlite library, in the form `X*1000000 + Y*1000 + Z`.
+pub fn sqlite_version_number() -> i32 {
+    unsafe { ffi::sqlite3_libversion_number() }
+}
+
+// `sqlite3-sys` does not declare the 64-bit variants added in sqlite `3.37.0`.
+extern "C" {
+    fn sqlite3_total_changes64(db: *mut ffi::sqlite3) -> i64;
+}
+
+/// Returns the error kind of a sqlite result code.
+///
+/// A busy or locked database is reported as [`io::ErrorKind::WouldBlock`],
+/// see [`rinq_rdbc::is_retryable`].
+fn error_kind(code: i32) -> io::ErrorKind {
+    match code & 0xff {
+        ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED | ffi::SQLITE_PROTOCOL => io::ErrorKind::WouldBlock,
+        _ => io::ErrorKind::Other,
+    }
+}
+
+/// An error reported by the sqlite library, the inner error of the [`io::Error`]s of this driver.
+#[derive(Debug, Clone)]
+pub struct SqliteError {
+    code: i32,
+    message: String,
+}
+
+impl SqliteError {
+    /// Returns the extended result code of the error.
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// Returns the message of `sqlite3_errmsg`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for SqliteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sqlite3: code={}, error={}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for SqliteError {}
+
+/// Wraps an error with the context of the failed operation, the error is the [`source`](std::error::Error::source).
+#[derive(Debug)]
+struct ContextError {
+    context: String,
+    source: io::Error,
+}
+
+impl ContextError {
+    fn wrap(context: String, source: io::Error) -> io::Error {
+        io::Error::new(source.kind(), ContextError { context, source })
+    }
+}
+
+impl Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.context)
+    }
+}
+
+impl std::error::Error for ContextError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
 unsafe fn to_io_error(db: *mut sqlite3_sys::sqlite3) -> io::Error {
+    let code = sqlite3_extended_errcode(db);
+
     io::Error::new(
-        io::ErrorKind::Other,
-        format!(
-            "sqlite3: code={}, error={}",
-            sqlite3_errcode(db),
-            from_utf8_unchecked(CStr::from_ptr(sqlite3_errmsg(db)).to_bytes())
-        ),
+        error_kind(code),
+        SqliteError {
+            code,
+            message: from_utf8_unchecked(CStr::from_ptr(sqlite3_errmsg(db)).to_bytes()).to_owned(),
+        },
     )
 }
 
@@ -47,11 +180,24 @@ unsafe impl Sync for RawConn {}
 
 impl RawConn {
     /// Create new sqlite connection with `source_name`.
+    ///
+    /// The error message names the source with secret uri parameters removed, see [`sanitize_source_name`].
     fn new(source_name: &str) -> io::Result<Self> {
+        let source = CString::new(source_name).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "sqlite: nul byte in source name: {}",
+                    sanitize_source_name(source_name)
+                ),
+            )
+        })?;
+
         let mut db = null_mut();
+
         unsafe {
             let rc = sqlite3_sys::sqlite3_open_v2(
-                CString::new(source_name)?.as_ptr(),
+                source.as_ptr(),
                 &mut db,
                 SQLITE_OPEN_CREATE
                     | SQLITE_OPEN_READWRITE
@@ -60,26 +206,69 @@ impl RawConn {
                 null_mut(),
             );
 
+            // sqlite allocates a handle even if the open fails, which must be closed by the `Drop`.
+            let conn = RawConn(db);
+
             if rc != SQLITE_OK {
-                return Err(to_io_error(db));
+                return Err(ContextError::wrap(
+                    format!("sqlite: open {}", sanitize_source_name(source_name)),
+                    to_io_error(db),
+                ));
             }
+
+            Ok(conn)
         }
+    }
+}
 
-        Ok(RawConn(db))
+/// The uri parameters whose values are removed by [`sanitize_source_name`].
+const SECRET_PARAMS: &[&str] = &["key", "hexkey", "textkey", "password", "pwd"];
+
+/// Returns `source_name` without the secret parameters of a `file:` uri, e.g. `key=...` or `password=...`,
+/// so that it can be included in error messages and logs.
+fn sanitize_source_name(source_name: &str) -> Cow<'_, str> {
+    let Some((path, query)) = source_name.split_once('?') else {
+        return Cow::Borrowed(source_name);
+    };
+
+    let params = query
+        .split('&')
+        .filter(|param| {
+            let key = param.split_once('=').map_or(*param, |(key, _)| key);
+
+            !SECRET_PARAMS
+                .iter()
+                .any(|secret| key.eq_ignore_ascii_case(secret))
+        })
+        .collect::<Vec<_>>();
+
+    if params.is_empty() {
+        Cow::Owned(path.to_owned())
+    } else {
+        Cow::Owned(format!("{}?{}", path, params.join("&")))
     }
 }
 
 /// sqlite3_stmt wrapper type with `Drop` trait implementation.
-struct RawStmt(*mut ffi::sqlite3_stmt);
+///
+/// The pointer is null once the statement is finalized.
+struct RawStmt(AtomicPtr<ffi::sqlite3_stmt>);
 
-/// Safety: The RINQ framework has prohibited auto trait [`Sync`].
-unsafe impl Send for RawStmt {}
-unsafe impl Sync for RawStmt {}
+impl RawStmt {
+    /// Finalizes the statement now rather than on drop, later uses return an error.
+    #[cfg(test)]
+    fn finalize(&self) {
+        unsafe {
+            ffi::sqlite3_finalize(self.0.swap(null_mut(), Ordering::SeqCst));
+        }
+    }
+}
 
 impl Drop for RawStmt {
     fn drop(&mut self) {
+        // finalizing a null pointer is a no-op.
         unsafe {
-            ffi::sqlite3_finalize(self.0);
+            ffi::sqlite3_finalize(*self.0.get_mut());
         }
     }
 }
@@ -88,14 +277,39 @@ impl Drop for RawStmt {
 #[derive(Clone)]
 struct DbConn {
     raw: Arc<RawConn>,
+    /// The transaction and savepoints started by the driver.
+    tx_stack: Arc<Mutex<TxStack>>,
+    /// The options of the driver which opened this connection.
+    options: Arc<SqliteOptions>,
+    /// The subscriptions of [`DbConn::watch_table`](rinq_rdbc::DbConn::watch_table),
+    /// declared after `raw` because the update hook of the connection points to it.
+    watchers: Arc<Watchers>,
+    /// The values of [`DbConn::set_session_value`](rinq_rdbc::DbConn::set_session_value),
+    /// declared after `raw` because the `session_value` function of the connection points to it.
+    session: Arc<Session>,
+    /// The slot of [`SqliteOptions::max_connections`], released after the connection is closed.
+    _permit: Option<Arc<ConnectionPermit>>,
 }
 
 impl DbConn {
-    /// Create new sqlite connection with `source_name`.
-    fn new(source_name: &str) -> io::Result<Self> {
-        Ok(Self {
-            raw: Arc::new(RawConn::new(source_name)?),
-        })
+    /// Create new sqlite connection with `source_name` and apply `options` to it.
+    ///
+    /// A relative path is resolved against [`SqliteOptions::base_dir`].
+    fn new(source_name: &str, options: Arc<SqliteOptions>) -> io::Result<Self> {
+        let conn = Self {
+            raw: Arc::new(RawConn::new(&options.resolve_source(source_name)?)?),
+            tx_stack: Default::default(),
+            options,
+            watchers: Default::default(),
+            session: Default::default(),
+            _permit: None,
+        };
+
+        Session::register(&conn)?;
+
+        conn.options.apply(&conn)?;
+
+        Ok(conn)
     }
 
     fn to_c_handle(&self) -> *mut ffi::sqlite3 {
@@ -122,7 +336,38 @@ impl DbConn {
     }
 
     /// using `sqlite3_prepare_v2` to compile sql and create `Prepared Statement Object`
+    ///
+    /// With [`SqliteOptions::track_rowid`], a `SELECT` is prepared with a hidden `rowid` column if possible.
     fn prepare(&self, query: &CStr) -> io::Result<DbStmt> {
+        let mut stmt = self.prepare_tracking_rowid(query)?;
+
+        if self.options.is_strict_bind() {
+            let query = query.to_str().map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("sqlite: {}", err))
+            })?;
+
+            stmt.param_types = Some(Arc::new(self.param_types(&stmt, query)?));
+        }
+
+        Ok(stmt)
+    }
+
+    fn prepare_tracking_rowid(&self, query: &CStr) -> io::Result<DbStmt> {
+        if self.options.is_tracking_rowid() {
+            if let Some(rewritten) = query.to_str().ok().and_then(rowid::select_with_rowid) {
+                // e.g. "ambiguous column name: rowid", prepare the query as is.
+                if let Ok(mut stmt) = self.prepare_raw(&CString::new(rewritten)?) {
+                    stmt.rowid_col = stmt.raw_column_count().checked_sub(1);
+
+                    return Ok(stmt);
+                }
+            }
+        }
+
+        self.prepare_raw(query)
+    }
+
+    fn prepare_raw(&self, query: &CStr) -> io::Result<DbStmt> {
         let mut c_stmt = null_mut();
 
         unsafe {
@@ -139,22 +384,627 @@ impl DbConn {
             }
         }
 
+        // `query` is empty or only contains comments.
+        if c_stmt.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("sqlite3: no sql statement in query: {:?}", query),
+            ));
+        }
+
         Ok(DbStmt {
-            raw: RawStmt(c_stmt),
+            raw: Arc::new(RawStmt(AtomicPtr::new(c_stmt))),
             conn: self.clone(),
+            rowid_col: None,
+            param_types: None,
         })
     }
+
+    /// Returns the rowid of the most recent successful insert, see `sqlite3_last_insert_rowid`.
+    fn last_insert_rowid(&self) -> i64 {
+        unsafe { ffi::sqlite3_last_insert_rowid(self.to_c_handle()) }
+    }
+
+    /// Returns the number of rows modified by the most recent completed insert, update or delete.
+    fn changes(&self) -> i64 {
+        unsafe { ffi::sqlite3_changes(self.to_c_handle()) as i64 }
+    }
+
+    /// Returns the number of rows modified by all completed inserts, updates and deletes since the connection was opened.
+    ///
+    /// `sqlite3_total_changes64` is only available since sqlite `3.37.0`,
+    /// older libraries fall back to the 32-bit `sqlite3_total_changes`.
+    fn total_changes(&self) -> i64 {
+        if sqlite_version_number() >= 3037000 {
+            unsafe { sqlite3_total_changes64(self.to_c_handle()) }
+        } else {
+            unsafe { ffi::sqlite3_total_changes(self.to_c_handle()) as i64 }
+        }
+    }
+
+    /// Returns the most recent error of this connection, if the most recent api call failed.
+    fn last_error(&self) -> Option<io::Error> {
+        unsafe {
+            match sqlite3_errcode(self.to_c_handle()) {
+                SQLITE_OK | SQLITE_ROW | SQLITE_DONE => None,
+                _ => Some(to_io_error(self.to_c_handle())),
+            }
+        }
+    }
+
+    /// Returns true if a transaction is open, i.e. the connection is not in autocommit mode.
+    fn in_transaction(&self) -> bool {
+        unsafe { ffi::sqlite3_get_autocommit(self.to_c_handle()) == 0 }
+    }
 }
 
+/// Sqlite prepared statement with [`Clone`] trait implementation.
+///
+/// The statement keeps its connection alive, so it is always finalized before the connection is closed.
+#[derive(Clone)]
 struct DbStmt {
-    raw: RawStmt,
+    raw: Arc<RawStmt>,
     conn: DbConn,
+    /// The hidden `rowid` column appended by [`SqliteOptions::track_rowid`], which is the last column.
+    rowid_col: Option<usize>,
+    /// The columns compared with the parameters, checked on bind if [`SqliteOptions::strict_bind`] is enabled.
+    param_types: Option<Arc<Vec<Option<ParamType>>>>,
+}
+
+impl DbStmt {
+    fn to_c_handle(&self) -> *mut ffi::sqlite3_stmt {
+        self.raw.0.load(Ordering::SeqCst)
+    }
+
+    /// Returns error if the statement is finalized, which is checked before binding or stepping it.
+    fn check_live(&self) -> io::Result<()> {
+        if self.to_c_handle().is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sqlite: statement finalized",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Bind `values` to the parameters of this statement, see [`bind_values`].
+    ///
+    /// Non-finite floats are handled as configured by [`SqliteOptions::non_finite_floats`].
+    fn bind(&self, values: &[SqlValue<'_>]) -> io::Result<()> {
+        self.check_live()?;
+
+        for (index, value) in values.iter().enumerate() {
+            self.conn.options.check_bind_float(value, &(index + 1))?;
+            self.check_param_type(index, value, &(index + 1))?;
+        }
+
+        bind_values(self.to_c_handle(), values)
+    }
+
+    /// Bind `params` to the parameters with the same names, see [`bind_named`].
+    ///
+    /// Non-finite floats are handled as configured by [`SqliteOptions::non_finite_floats`].
+    fn bind_named(&self, params: &[(&str, SqlValue<'_>)]) -> io::Result<()> {
+        self.check_live()?;
+
+        for (name, value) in params {
+            self.conn.options.check_bind_float(value, name)?;
+
+            if self.param_types.is_some() {
+                let index = parameter_index(self.to_c_handle(), name)?;
+
+                self.check_param_type(index as usize - 1, value, name)?;
+            }
+        }
+
+        bind_named(self.to_c_handle(), params)
+    }
+
+    /// Check `value` against the column compared with the parameter at `index`, see [`SqliteOptions::strict_bind`].
+    fn check_param_type(
+        &self,
+        index: usize,
+        value: &SqlValue<'_>,
+        param: &dyn Display,
+    ) -> io::Result<()> {
+        match self
+            .param_types
+            .as_ref()
+            .and_then(|param_types| param_types.get(index))
+        {
+            Some(Some(param_type)) => param_type.check(value, param),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the largest parameter index of this statement, see `sqlite3_bind_parameter_count`.
+    fn parameter_count(&self) -> usize {
+        unsafe { ffi::sqlite3_bind_parameter_count(self.to_c_handle()) as usize }
+    }
+
+    /// Returns the name of the parameter at `index`, which is bound to the value at `index`,
+    /// or `None` for a nameless `?`. The caller must make sure that `index` is in range.
+    fn parameter_name(&self, index: usize) -> Option<String> {
+        unsafe {
+            let name = ffi::sqlite3_bind_parameter_name(self.to_c_handle(), index as c_int + 1);
+
+            (!name.is_null()).then(|| to_string(name))
+        }
+    }
+
+    /// Returns the counters of each loop of the query plan, see `sqlite3_stmt_scanstatus`.
+    ///
+    /// `sqlite3_stmt_scanstatus` only exists if sqlite is compiled with `SQLITE_ENABLE_STMT_SCANSTATUS`,
+    /// so it is only called with the `with-scanstatus` feature.
+    #[cfg(feature = "with-scanstatus")]
+    fn scan_status(&self) -> io::Result<Vec<ScanStatus>> {
+        let mut scan_status = vec![];
+
+        unsafe {
+            for index in 0.. {
+                let mut n_loop: i64 = 0;
+                let mut n_visit: i64 = 0;
+                let mut est: f64 = 0.0;
+                let mut name: *const std::ffi::c_char = std::ptr::null();
+
+                // returns non-zero once `index` is past the last loop.
+                if ffi::sqlite3_stmt_scanstatus(
+                    self.to_c_handle(),
+                    index,
+                    ffi::SQLITE_SCANSTAT_NLOOP,
+                    (&mut n_loop as *mut i64).cast(),
+                ) != 0
+                {
+                    break;
+                }
+
+                for (op, out) in [
+                    (
+                        ffi::SQLITE_SCANSTAT_NVISIT,
+                        (&mut n_visit as *mut i64).cast(),
+                    ),
+                    (ffi::SQLITE_SCANSTAT_EST, (&mut est as *mut f64).cast()),
+                    (
+                        ffi::SQLITE_SCANSTAT_NAME,
+                        (&mut name as *mut *const std::ffi::c_char).cast(),
+                    ),
+                ] {
+                    ffi::sqlite3_stmt_scanstatus(self.to_c_handle(), index, op, out);
+                }
+
+                scan_status.push(ScanStatus {
+                    n_loop,
+                    n_visit,
+                    est,
+                    name: to_string(name),
+                });
+            }
+        }
+
+        Ok(scan_status)
+    }
+
+    #[cfg(not(feature = "with-scanstatus"))]
+    fn scan_status(&self) -> io::Result<Vec<ScanStatus>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sqlite: scan status requires the `with-scanstatus` feature",
+        ))
+    }
+
+    /// Returns true if the statement makes no direct changes to the database file, see `sqlite3_stmt_readonly`.
+    fn is_readonly(&self) -> bool {
+        unsafe { ffi::sqlite3_stmt_readonly(self.to_c_handle()) != 0 }
+    }
+
+    /// Reset the statement to its initial state and clear all bindings, ready to be re-executed.
+    fn reset(&self) {
+        let c_stmt = self.to_c_handle();
+
+        // a finalized statement is reported by the next bind or step.
+        if c_stmt.is_null() {
+            return;
+        }
+
+        unsafe {
+            // returns the error of the last `sqlite3_step`, which has already been reported.
+            ffi::sqlite3_reset(c_stmt);
+            ffi::sqlite3_clear_bindings(c_stmt);
+        }
+    }
+
+    /// Evaluate the statement with `sqlite3_step`, returns true if a new row is ready.
+    fn step(&self) -> io::Result<bool> {
+        self.check_live()?;
+
+        match unsafe { ffi::sqlite3_step(self.to_c_handle()) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(unsafe { to_io_error(self.conn.to_c_handle()) }),
+        }
+    }
+
+    /// Returns the number of columns in the result set of this statement, without the hidden `rowid` column.
+    fn column_count(&self) -> usize {
+        self.rowid_col.unwrap_or_else(|| self.raw_column_count())
+    }
+
+    /// Returns the number of columns including the hidden `rowid` column.
+    fn raw_column_count(&self) -> usize {
+        unsafe { ffi::sqlite3_column_count(self.to_c_handle()) as usize }
+    }
+
+    /// Returns the rowid of the current row, if the statement has a hidden `rowid` column.
+    ///
+    /// The caller must make sure that the statement points to a row.
+    fn rowid(&self) -> Option<io::Result<i64>> {
+        let col = self.rowid_col?;
+
+        Some(match self.column_storage_value(col) {
+            Ok(SqlValue::Int(rowid)) => Ok(rowid),
+            Ok(value) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sqlite: rowid of the current row is {}", value.redacted()),
+            )),
+            Err(err) => Err(err),
+        })
+    }
+
+    /// Returns the name of the column at `col`.
+    fn column_name(&self, col: usize) -> String {
+        unsafe { to_string(ffi::sqlite3_column_name(self.to_c_handle(), col as c_int)) }
+    }
+
+    /// Returns the declared type of the column at `col`,
+    /// or an empty string if the column is an expression or subquery.
+    fn column_decltype(&self, col: usize) -> String {
+        #[cfg(test)]
+        tests::DECLTYPE_CALLS.with(|calls| calls.set(calls.get() + 1));
+
+        unsafe {
+            to_string(ffi::sqlite3_column_decltype(
+                self.to_c_handle(),
+                col as c_int,
+            ))
+        }
+    }
+
+    /// Returns the `(table, column)` that the column at `col` is read from,
+    /// or `None` if the column is an expression or subquery.
+    ///
+    /// The `sqlite3_column_table_name` and `sqlite3_column_origin_name` functions only exist if sqlite
+    /// is compiled with `SQLITE_ENABLE_COLUMN_METADATA`, so they are only called with the
+    /// `with-column-metadata` feature, which always returns `None` otherwise.
+    #[cfg(feature = "with-column-metadata")]
+    fn column_origin(&self, col: usize) -> Option<(String, String)> {
+        unsafe {
+            let table = ffi::sqlite3_column_table_name(self.to_c_handle(), col as c_int);
+            let column = ffi::sqlite3_column_origin_name(self.to_c_handle(), col as c_int);
+
+            if table.is_null() || column.is_null() {
+                return None;
+            }
+
+            Some((to_string(table), to_string(column)))
+        }
+    }
+
+    #[cfg(not(feature = "with-column-metadata"))]
+    fn column_origin(&self, _col: usize) -> Option<(String, String)> {
+        None
+    }
+
+    /// Returns the value of the column at `col` of the current row.
+    ///
+    /// The value is decoded by its storage class (`sqlite3_column_type`) rather than the declared type
+    /// of the column, so a `NULL` is always [`SqlValue::Null`]. Text and blob data are copied,
+    /// because the buffers returned by sqlite are invalidated by the next `sqlite3_step`.
+    ///
+    /// With `with-decimal`, numbers in a `DECIMAL` or `NUMERIC` column are returned as
+    /// [`SqlValue::Decimal`], see [`decimal::to_decimal`].
+    /// With `with-chrono`, text in a `DATE`, `TIME`, `DATETIME` or `TIMESTAMP` column is returned as
+    /// a temporal value, see [`temporal`].
+    /// With `with-uuid`, blobs in a `UUID` column are returned as [`SqlValue::Uuid`], see [`uuid_blob`].
+    ///
+    /// The caller must make sure that the statement points to a row and `col` is in range.
+    fn column_value(&self, col: usize) -> io::Result<SqlValue<'static>> {
+        let value = self.column_storage_value(col)?;
+
+        #[cfg(any(
+            feature = "with-decimal",
+            feature = "with-chrono",
+            feature = "with-uuid"
+        ))]
+        {
+            let decltype = self.column_decltype(col);
+
+            #[cfg(feature = "with-decimal")]
+            if decimal::is_decimal_type(&decltype) {
+                return Ok(decimal::to_decimal(value, decimal::decimal_size(&decltype)));
+            }
+
+            #[cfg(feature = "with-chrono")]
+            if let Some(temporal_type) = temporal::temporal_type(&decltype) {
+                return Ok(temporal::to_temporal(value, temporal_type));
+            }
+
+            #[cfg(feature = "with-uuid")]
+            if uuid_blob::is_uuid_type(&decltype) {
+                return uuid_blob::to_uuid(value);
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Returns the value of the column at `col` of the current row, decoded by its storage class only.
+    ///
+    /// Non-finite floats are handled as configured by [`SqliteOptions::non_finite_floats`],
+    /// text and blobs larger than [`SqliteOptions::max_value_bytes`] are rejected before they are copied.
+    fn column_storage_value(&self, col: usize) -> io::Result<SqlValue<'static>> {
+        let stmt = self.to_c_handle();
+        let c_col = col as c_int;
+
+        unsafe {
+            match ffi::sqlite3_column_type(stmt, c_col) {
+                SQLITE_INTEGER => Ok(SqlValue::Int(ffi::sqlite3_column_int64(stmt, c_col))),
+                SQLITE_FLOAT => {
+                    let value = ffi::sqlite3_column_double(stmt, c_col);
+
+                    self.conn.options.check_column_float(value, col)?;
+
+                    Ok(SqlValue::Float(value))
+                }
+                SQLITE_TEXT => {
+                    let text = column_bytes(ffi::sqlite3_column_text(stmt, c_col), stmt, c_col);
+
+                    self.conn.options.check_value_size(text.len(), col)?;
+
+                    let text = String::from_utf8(text.to_vec()).map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidData, err.utf8_error())
+                    })?;
+
+                    Ok(SqlValue::String(Cow::Owned(text)))
+                }
+                SQLITE_BLOB => {
+                    let blob =
+                        column_bytes(ffi::sqlite3_column_blob(stmt, c_col).cast(), stmt, c_col);
+
+                    self.conn.options.check_value_size(blob.len(), col)?;
+
+                    Ok(SqlValue::Binary(Cow::Borrowed(blob)).into_owned())
+                }
+                _ => Ok(SqlValue::Null),
+            }
+        }
+    }
+}
+
+/// Returns the `SQLITE_TRANSIENT` destructor, which makes sqlite copy the buffer before the bind call returns.
+fn sqlite_transient() -> Option<ffi::sqlite3_callback> {
+    Some(unsafe { std::mem::transmute::<isize, ffi::sqlite3_callback>(SQLITE_TRANSIENT as isize) })
+}
+
+/// Bind `values` to the parameters of `stmt`, the value at index `n` is bound to parameter `n + 1`.
+///
+/// See [`bind_value`] for how each value is bound.
+fn bind_values(stmt: *mut ffi::sqlite3_stmt, values: &[SqlValue<'_>]) -> io::Result<()> {
+    for (index, value) in values.iter().enumerate() {
+        let index = c_int::try_from(index + 1).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "sqlite: too many parameters")
+        })?;
+
+        bind_value(stmt, index, value)?;
+    }
+
+    Ok(())
+}
+
+/// Bind each value of `params` to the parameter of `stmt` with the same name, see [`bind_value`].
+///
+/// A name is written as in the sql, e.g. `:id`, `@id` or `$id`. A name without one of these prefixes
+/// matches the first parameter with any of them, so `id` matches `:id`.
+/// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error for a name that is not a parameter of `stmt`.
+fn bind_named(stmt: *mut ffi::sqlite3_stmt, params: &[(&str, SqlValue<'_>)]) -> io::Result<()> {
+    for (name, value) in params {
+        bind_value(stmt, parameter_index(stmt, name)?, value)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the index of the parameter of `stmt` named `name`, see [`bind_named`] for the matching.
+fn parameter_index(stmt: *mut ffi::sqlite3_stmt, name: &str) -> io::Result<c_int> {
+    let candidates = if name.starts_with([':', '@', '$']) {
+        vec![name.to_string()]
+    } else {
+        vec![
+            format!(":{}", name),
+            format!("@{}", name),
+            format!("${}", name),
+        ]
+    };
+
+    let mut index = 0;
+
+    for candidate in candidates {
+        index =
+            unsafe { ffi::sqlite3_bind_parameter_index(stmt, CString::new(candidate)?.as_ptr()) };
+
+        if index != 0 {
+            break;
+        }
+    }
+
+    if index == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("sqlite: unknown parameter name: {}", name),
+        ));
+    }
+
+    Ok(index)
+}
+
+/// Bind `value` to parameter `index` of `stmt`.
+///
+/// `Bool` is bound as integer `0` or `1`, `Decimal` and temporal values as text, `Uuid` as a 16-byte blob,
+/// text and blob buffers are copied by sqlite.
+///
+/// sqlite integers are 64-bit, so a `BigInt` is bound as int64 if it fits in `i64`,
+/// otherwise an [`InvalidData`](io::ErrorKind::InvalidData) error is returned rather than truncating
+/// the value or silently storing it as text.
+fn bind_value(stmt: *mut ffi::sqlite3_stmt, index: c_int, value: &SqlValue<'_>) -> io::Result<()> {
+    let rc = unsafe {
+        match value {
+            SqlValue::Bool(v) => ffi::sqlite3_bind_int64(stmt, index, *v as i64),
+            SqlValue::Int(v) => ffi::sqlite3_bind_int64(stmt, index, *v),
+            SqlValue::BigInt(v) => match i64::try_from(*v) {
+                Ok(v) => ffi::sqlite3_bind_int64(stmt, index, v),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "sqlite: {} parameter {} is out of the 64-bit integer range",
+                            value.redacted(),
+                            index
+                        ),
+                    ))
+                }
+            },
+            SqlValue::Float(v) => ffi::sqlite3_bind_double(stmt, index, *v),
+            SqlValue::String(v) => bind_text(stmt, index, v)?,
+            SqlValue::Binary(v) => ffi::sqlite3_bind_blob(
+                stmt,
+                index,
+                v.as_ptr().cast(),
+                bind_len(v.len())?,
+                sqlite_transient(),
+            ),
+            SqlValue::Null => ffi::sqlite3_bind_null(stmt, index),
+            // the plain string keeps trailing zeros and never uses exponent notation.
+            #[cfg(feature = "with-decimal")]
+            SqlValue::Decimal(v) => bind_text(stmt, index, &v.to_plain_string())?,
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Date(v) => {
+                bind_text(stmt, index, &v.format(rinq_rdbc::DATE_FORMAT).to_string())?
+            }
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Time(v) => {
+                bind_text(stmt, index, &v.format(rinq_rdbc::TIME_FORMAT).to_string())?
+            }
+            #[cfg(feature = "with-chrono")]
+            SqlValue::Timestamp(v) => bind_text(
+                stmt,
+                index,
+                &v.format(rinq_rdbc::TIMESTAMP_FORMAT).to_string(),
+            )?,
+            #[cfg(feature = "with-uuid")]
+            SqlValue::Uuid(v) => ffi::sqlite3_bind_blob(
+                stmt,
+                index,
+                v.as_bytes().as_ptr().cast(),
+                16,
+                sqlite_transient(),
+            ),
+            // variants enabled in rdbc by another crate, e.g. `Decimal` without `with-decimal`.
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                    "sqlite: bind {} parameter {}, the type is not enabled by the crate features",
+                    value.redacted(),
+                    index
+                ),
+                ))
+            }
+        }
+    };
+
+    if rc != SQLITE_OK {
+        return Err(unsafe { to_io_error(ffi::sqlite3_db_handle(stmt)) });
+    }
+
+    Ok(())
+}
+
+/// Returns the length of a text or blob parameter as `c_int`.
+fn bind_len(len: usize) -> io::Result<c_int> {
+    c_int::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("sqlite: bind a value of {} bytes", len),
+        )
+    })
+}
+
+/// Bind `text` to parameter `index` of `stmt`, returns the sqlite result code.
+unsafe fn bind_text(stmt: *mut ffi::sqlite3_stmt, index: c_int, text: &str) -> io::Result<c_int> {
+    Ok(ffi::sqlite3_bind_text(
+        stmt,
+        index,
+        text.as_ptr().cast(),
+        bind_len(text.len())?,
+        sqlite_transient(),
+    ))
+}
+
+/// Copy a nullable c string returned by sqlite, a null pointer is read as an empty string.
+unsafe fn to_string(ptr: *const std::ffi::c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// Returns the bytes of a text or blob column, `ptr` must be returned by `sqlite3_column_text/blob` just before.
+unsafe fn column_bytes<'a>(ptr: *const u8, stmt: *mut ffi::sqlite3_stmt, col: c_int) -> &'a [u8] {
+    // an empty blob is returned as null pointer.
+    if ptr.is_null() {
+        return &[];
+    }
+
+    from_raw_parts(ptr, ffi::sqlite3_column_bytes(stmt, col) as usize)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{cell::Cell, sync::Once};
+
     use super::*;
 
+    thread_local! {
+        /// The number of `sqlite3_column_decltype` calls on this thread.
+        pub(crate) static DECLTYPE_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Register the [`Sqlite`] driver, can be called more than once.
+    pub(crate) fn register_once() {
+        static REGISTER: Once = Once::new();
+
+        REGISTER.call_once(|| register().unwrap());
+    }
+
+    #[test]
+    fn test_sqlite_version() {
+        assert!(sqlite_version_number() >= MIN_SQLITE_VERSION_NUMBER);
+
+        let version = sqlite_version_number();
+
+        assert_eq!(
+            sqlite_version(),
+            format!(
+                "{}.{}.{}",
+                version / 1000000,
+                version / 1000 % 1000,
+                version % 1000
+            )
+        );
+    }
+
     #[test]
     fn test_open_private() {
         // temporary in-memory database
@@ -163,9 +1013,96 @@ mod tests {
         RawConn::new("").unwrap();
     }
 
+    #[test]
+    fn test_sanitize_source_name() {
+        for (source_name, expect) in [
+            ("./data.db", "./data.db"),
+            ("file:data.db?mode=ro", "file:data.db?mode=ro"),
+            ("file:data.db?key=secret", "file:data.db"),
+            (
+                "file:data.db?mode=rw&KEY=secret&cache=shared&password=p",
+                "file:data.db?mode=rw&cache=shared",
+            ),
+        ] {
+            assert_eq!(sanitize_source_name(source_name), expect);
+        }
+    }
+
+    #[test]
+    fn test_open_error() {
+        let err = RawConn::new("file:/rinq-missing-dir/data.db?mode=rw&key=hunter2")
+            .err()
+            .unwrap();
+
+        let message = err.to_string();
+
+        assert!(message.contains("file:/rinq-missing-dir/data.db?mode=rw"));
+        assert!(!message.contains("hunter2"));
+
+        // the cause is the source of the error.
+        let source = std::error::Error::source(&err).unwrap();
+
+        assert!(source.to_string().contains("unable to open database file"));
+
+        let cause = source
+            .downcast_ref::<io::Error>()
+            .unwrap()
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<SqliteError>()
+            .unwrap();
+
+        assert_eq!(cause.code(), ffi::SQLITE_CANTOPEN);
+        assert_eq!(cause.message(), "unable to open database file");
+        assert!(std::error::Error::source(cause).is_none());
+    }
+
+    #[test]
+    fn test_retryable() {
+        let path = std::env::temp_dir().join(format!(
+            "rinq-retryable-{}.db",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+
+        let source_name = path.to_str().unwrap();
+
+        let conn = DbConn::new(source_name, Default::default()).unwrap();
+        let other = DbConn::new(source_name, Default::default()).unwrap();
+
+        conn.exec(c"CREATE TABLE t (id INTEGER PRIMARY KEY);")
+            .unwrap();
+        conn.exec(c"INSERT INTO t VALUES (1);").unwrap();
+
+        // the write lock is held by `conn`.
+        conn.exec(c"BEGIN IMMEDIATE;").unwrap();
+
+        let err = other.exec(c"BEGIN IMMEDIATE;").unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(rinq_rdbc::is_retryable(&err));
+
+        let err = conn.exec(c"INSERT INTO t VALUES (1);").unwrap_err();
+
+        assert!(err.to_string().contains("UNIQUE constraint failed"));
+        assert!(!rinq_rdbc::is_retryable(&err));
+
+        let err = conn.exec(c"INSERT INTO;").unwrap_err();
+
+        assert!(!rinq_rdbc::is_retryable(&err));
+
+        conn.exec(c"ROLLBACK;").unwrap();
+
+        drop((conn, other));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn test_exec() {
-        let conn = DbConn::new("").unwrap();
+        let conn = DbConn::new("", Default::default()).unwrap();
 
         // start a transaction.
         conn.exec(c"BEGIN TRANSACTION;").unwrap();
@@ -0,0 +1,117 @@
+//! The connection limit of [`SqliteOptions::max_connections`](crate::SqliteOptions::max_connections).
+
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+/// A semaphore counting the open connections of a [`Sqlite`](crate::Sqlite) driver.
+#[derive(Debug)]
+pub(crate) struct ConnectionLimit {
+    max: usize,
+    state: Mutex<LimitState>,
+}
+
+#[derive(Debug, Default)]
+struct LimitState {
+    open: usize,
+    /// The connects waiting for a connection to close.
+    waiters: Vec<Waker>,
+}
+
+impl ConnectionLimit {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            max,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns a permit to open a connection, or registers `cx` to be woken once a connection closes.
+    pub(crate) fn poll_acquire(self: &Arc<Self>, cx: &mut Context<'_>) -> Poll<ConnectionPermit> {
+        let mut state = self.lock();
+
+        if state.open < self.max {
+            state.open += 1;
+
+            return Poll::Ready(ConnectionPermit(self.clone()));
+        }
+
+        if !state.waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            state.waiters.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LimitState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A slot of a [`ConnectionLimit`], held by a connection and released when the connection is closed.
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit(Arc<ConnectionLimit>);
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.0.lock();
+
+            state.open -= 1;
+
+            std::mem::take(&mut state.waiters)
+        };
+
+        // all waiters race for the slot, the others register again.
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, pin::pin};
+
+    use futures::{executor::block_on, poll};
+
+    use crate::{Sqlite, SqliteOptions};
+
+    #[test]
+    fn test_max_connections() {
+        rinq_rdbc::register(
+            "sqlite-limit",
+            Sqlite::with_options(SqliteOptions::default().max_connections(1)).unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let first = rinq_rdbc::open("sqlite-limit", ":memory:").await.unwrap();
+
+            let mut second = pin!(rinq_rdbc::open("sqlite-limit", ":memory:"));
+
+            assert!(poll!(second.as_mut()).is_pending());
+
+            // a statement keeps its connection open.
+            let stmt = first.prepare("SELECT 1").await.unwrap();
+
+            drop(first);
+
+            assert!(poll!(second.as_mut()).is_pending());
+
+            drop(stmt);
+
+            let second = second.await.unwrap();
+
+            second
+                .execute("CREATE TABLE t (id INTEGER)", &[])
+                .await
+                .unwrap();
+        });
+
+        let err = Sqlite::with_options(SqliteOptions::default().max_connections(0)).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
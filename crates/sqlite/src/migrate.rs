@@ -0,0 +1,245 @@
+use std::{ffi::CString, io};
+
+use rinq_rdbc::DbConn;
+use sqlite3_sys as ffi;
+
+/// A schema migration applied by [`open_and_migrate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration<'a> {
+    /// The schema version after this migration, stored in `PRAGMA user_version`.
+    pub version: i32,
+    /// The sql statements of this migration, separated by `;`.
+    pub up_sql: &'a str,
+}
+
+/// Open a database like [`rinq_rdbc::open`] and bring its schema to the version of the last migration.
+///
+/// The current version is read from `PRAGMA user_version`, `0` for a new database.
+/// Each migration with a greater version is applied in its own transaction together with
+/// the update of `user_version`, so a failed migration rolls back and leaves the version of the
+/// previous one. Migrations that are already applied are skipped.
+///
+/// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if the versions are not positive and strictly increasing.
+pub async fn open_and_migrate<D: AsRef<str>, S: AsRef<str>>(
+    driver_name: D,
+    source_name: S,
+    migrations: &[Migration<'_>],
+) -> io::Result<DbConn> {
+    let mut last = 0;
+
+    for migration in migrations {
+        if migration.version <= last {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "sqlite: migration versions must be positive and strictly increasing, got {} after {}",
+                    migration.version, last
+                ),
+            ));
+        }
+
+        last = migration.version;
+    }
+
+    let conn = rinq_rdbc::open(driver_name, source_name).await?;
+
+    let current = user_version(&conn).await?;
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let tx = conn.begin().await?;
+
+        if let Err(err) = apply(&tx, migration).await {
+            // the migration error names the failed statement, a rollback failure is only appended to it.
+            let message = match tx.rollback().await {
+                Ok(()) => format!("sqlite: migration {}: {}", migration.version, err),
+                Err(rollback_err) => format!(
+                    "sqlite: migration {}: {}, rollback failed: {}",
+                    migration.version, err, rollback_err
+                ),
+            };
+
+            return Err(io::Error::new(err.kind(), message));
+        }
+
+        tx.commit().await?;
+    }
+
+    Ok(conn)
+}
+
+async fn user_version(conn: &DbConn) -> io::Result<i32> {
+    let result_set = conn
+        .prepare("PRAGMA user_version")
+        .await?
+        .query(&[])
+        .await?;
+
+    result_set.next().await?;
+
+    let version = i64::try_from(result_set.get(0).await?)?;
+
+    // sqlite stores `user_version` as a 32-bit integer.
+    Ok(version as i32)
+}
+
+async fn apply(tx: &rinq_rdbc::Tx, migration: &Migration<'_>) -> io::Result<()> {
+    for statement in split_statements(migration.up_sql)? {
+        let stmt = match tx.prepare(statement).await {
+            Ok(stmt) => stmt,
+            // the statement only contains comments.
+            Err(err) if err.kind() == io::ErrorKind::InvalidInput => continue,
+            Err(err) => return Err(err),
+        };
+
+        stmt.exec(&[]).await?;
+    }
+
+    // pragma values can not be bound as parameters.
+    tx.prepare(format!("PRAGMA user_version = {}", migration.version))
+        .await?
+        .exec(&[])
+        .await?;
+
+    Ok(())
+}
+
+/// Split `sql` into statements, a `;` inside a string, comment or trigger body does not end a statement.
+fn split_statements(sql: &str) -> io::Result<Vec<&str>> {
+    let mut statements = vec![];
+
+    let mut start = 0;
+
+    for (end, _) in sql.match_indices(';') {
+        let statement = &sql[start..=end];
+
+        if unsafe { ffi::sqlite3_complete(CString::new(statement)?.as_ptr()) } != 0 {
+            if !statement[..statement.len() - 1].trim().is_empty() {
+                statements.push(statement);
+            }
+
+            start = end + 1;
+        }
+    }
+
+    // the last statement may omit the `;`.
+    if !sql[start..].trim().is_empty() {
+        statements.push(&sql[start..]);
+    }
+
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            up_sql: "-- the first version;\nCREATE TABLE user (id INTEGER PRIMARY KEY, name TEXT);",
+        },
+        Migration {
+            version: 2,
+            up_sql: "ALTER TABLE user ADD COLUMN email TEXT;
+                     CREATE TRIGGER no_empty BEFORE INSERT ON user WHEN NEW.name = ''
+                     BEGIN SELECT RAISE(ABORT, 'empty name;'); END;
+                     INSERT INTO user (name, email) VALUES ('admin', 'admin@example.com')",
+        },
+    ];
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("SELECT ';'; -- a;\n;SELECT 2").unwrap(),
+            ["SELECT ';';", " -- a;\n;", "SELECT 2"]
+        );
+
+        assert_eq!(split_statements(MIGRATIONS[1].up_sql).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_open_and_migrate() {
+        crate::tests::register_once();
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+
+        let path = env::temp_dir().join(format!("rinq-migrate-{}.db", nanos));
+
+        let source_name = path.to_str().unwrap();
+
+        block_on(async {
+            let conn = open_and_migrate("sqlite", source_name, MIGRATIONS)
+                .await
+                .unwrap();
+
+            assert_eq!(user_version(&conn).await.unwrap(), 2);
+
+            drop(conn);
+
+            // re-applying would fail to create the table and insert a second admin.
+            let conn = open_and_migrate("sqlite", source_name, MIGRATIONS)
+                .await
+                .unwrap();
+
+            let result_set = conn
+                .prepare("SELECT count(*) FROM user")
+                .await
+                .unwrap()
+                .query(&[])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(i64::try_from(result_set.get(0).await.unwrap()).unwrap(), 1);
+
+            drop(result_set);
+            drop(conn);
+
+            let failing = [
+                MIGRATIONS[0],
+                MIGRATIONS[1],
+                Migration {
+                    version: 3,
+                    up_sql: "DROP TABLE user; INSERT INTO missing VALUES (1)",
+                },
+            ];
+
+            let err = open_and_migrate("sqlite", source_name, &failing)
+                .await
+                .err()
+                .unwrap();
+
+            assert!(err.to_string().contains("migration 3"));
+            assert!(err.to_string().contains("no such table: missing"));
+
+            let conn = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+
+            // the dropped table is restored by the rollback.
+            assert_eq!(user_version(&conn).await.unwrap(), 2);
+            assert!(conn.prepare("SELECT * FROM user").await.is_ok());
+
+            let unordered = [MIGRATIONS[1], MIGRATIONS[0]];
+
+            assert_eq!(
+                open_and_migrate("sqlite", source_name, &unordered)
+                    .await
+                    .err()
+                    .unwrap()
+                    .kind(),
+                io::ErrorKind::InvalidInput
+            );
+        });
+
+        fs::remove_file(path).unwrap();
+    }
+}
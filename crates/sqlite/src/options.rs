@@ -0,0 +1,874 @@
+use std::{
+    borrow::Cow,
+    ffi::{c_int, c_void, CStr, CString},
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use sqlite3_sys as ffi;
+
+use rinq_rdbc::SqlValue;
+
+use crate::{sqlite_transient, to_io_error, DbConn, RegisterFunction};
+
+/// Where temporary tables and indices are stored, see [`SqliteOptions::temp_store`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TempStore {
+    /// Use the compile-time default of sqlite, which is a file unless configured otherwise.
+    #[default]
+    Default,
+    /// Store temporary tables and indices in a file.
+    File,
+    /// Store temporary tables and indices in memory.
+    Memory,
+}
+
+/// How non-finite floats (`NaN` and the infinities) are bound and decoded,
+/// see [`SqliteOptions::non_finite_floats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NonFiniteFloats {
+    /// Store the values as sqlite does: `NaN` is stored as `NULL` and read back as [`SqlValue::Null`],
+    /// the infinities are stored as `REAL` and read back unchanged.
+    #[default]
+    Coerce,
+    /// Return [`InvalidInput`](io::ErrorKind::InvalidInput) error when a non-finite float is bound,
+    /// and [`InvalidData`](io::ErrorKind::InvalidData) error when an infinity is read,
+    /// e.g. one stored by another client or computed by `9e999`.
+    Reject,
+}
+
+/// Options applied to each connection opened by a [`Sqlite`](crate::Sqlite) driver,
+/// see [`Sqlite::with_options`](crate::Sqlite::with_options).
+#[derive(Debug, Default, Clone)]
+pub struct SqliteOptions {
+    random_seed: Option<u64>,
+    pragmas: Vec<(String, String)>,
+    non_finite_floats: NonFiniteFloats,
+    temp_store: Option<TempStore>,
+    base_dir: Option<PathBuf>,
+    max_value_bytes: Option<usize>,
+    track_rowid: bool,
+    strict_bind: bool,
+    max_connections: Option<usize>,
+    /// The functions registered with [`Sqlite::create_aggregate`](crate::Sqlite::create_aggregate).
+    pub(crate) functions: Vec<Arc<dyn RegisterFunction>>,
+    #[cfg(feature = "sqlcipher")]
+    encryption_key: Option<SecretBytes>,
+    /// `None` allows [`DEFAULT_ALLOWED_PRAGMAS`].
+    allowed_pragmas: Option<Vec<String>>,
+}
+
+/// The pragmas allowed by [`SqliteOptions::pragma`] unless replaced with [`SqliteOptions::allowed_pragmas`].
+///
+/// The list only contains pragmas that tune performance or enforce constraints, pragmas that can
+/// corrupt the database or bypass its checks, e.g. `writable_schema` or `ignore_check_constraints`,
+/// are not included.
+pub const DEFAULT_ALLOWED_PRAGMAS: &[&str] = &[
+    "analysis_limit",
+    "auto_vacuum",
+    "automatic_index",
+    "busy_timeout",
+    "cache_size",
+    "cache_spill",
+    "cell_size_check",
+    "defer_foreign_keys",
+    "foreign_keys",
+    "journal_mode",
+    "journal_size_limit",
+    "locking_mode",
+    "mmap_size",
+    "page_size",
+    "query_only",
+    "recursive_triggers",
+    "secure_delete",
+    "synchronous",
+    "temp_store",
+    "trusted_schema",
+    "wal_autocheckpoint",
+];
+
+impl SqliteOptions {
+    /// Replace the built-in `random()` and `randomblob(N)` functions of each connection with
+    /// deterministic ones seeded with `seed`, so that tests produce stable output.
+    ///
+    /// This overrides the built-ins for all sql run on the connection, and every connection
+    /// starts the same sequence. The sequence is not cryptographically secure and may
+    /// change between releases of this crate.
+    pub fn deterministic_random(mut self, seed: u64) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+
+    /// Run `PRAGMA name = value` on each new connection, in the order the pragmas are added.
+    ///
+    /// The pragma must be on the allow-list, see [`allowed_pragmas`](Self::allowed_pragmas),
+    /// and the value must be a number or a keyword, e.g. `-4000`, `WAL` or `ON`,
+    /// otherwise [`Sqlite::with_options`](crate::Sqlite::with_options) returns an error.
+    pub fn pragma(mut self, name: &str, value: &str) -> Self {
+        self.pragmas.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Replace the pragmas allowed by [`pragma`](Self::pragma), which defaults to [`DEFAULT_ALLOWED_PRAGMAS`].
+    ///
+    /// Names are compared case-insensitively.
+    pub fn allowed_pragmas(mut self, names: &[&str]) -> Self {
+        self.allowed_pragmas = Some(names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Run `PRAGMA temp_store` with `temp_store` on each new connection,
+    /// e.g. [`TempStore::Memory`] for workloads with large sorts or temp tables on slow disks.
+    pub fn temp_store(mut self, temp_store: TempStore) -> Self {
+        self.temp_store = Some(temp_store);
+        self
+    }
+
+    /// Resolve relative database paths against `base_dir` instead of the working directory of the process.
+    ///
+    /// Absolute paths, `file:` uris, `:memory:` and the empty source name of a temporary database are opened as is.
+    pub fn base_dir<P: Into<PathBuf>>(mut self, base_dir: P) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    /// Returns `source_name` resolved against the [`base_dir`](Self::base_dir).
+    pub(crate) fn resolve_source<'a>(&self, source_name: &'a str) -> io::Result<Cow<'a, str>> {
+        let Some(base_dir) = &self.base_dir else {
+            return Ok(Cow::Borrowed(source_name));
+        };
+
+        if source_name.is_empty()
+            || source_name == ":memory:"
+            || source_name.starts_with("file:")
+            || Path::new(source_name).is_absolute()
+        {
+            return Ok(Cow::Borrowed(source_name));
+        }
+
+        match base_dir.join(source_name).to_str() {
+            Some(path) => Ok(Cow::Owned(path.to_owned())),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "sqlite: base dir is not valid unicode: {}",
+                    base_dir.display()
+                ),
+            )),
+        }
+    }
+
+    /// Limit the size of the text and blob values read from result sets to `max` bytes,
+    /// reading a larger value returns [`InvalidData`](io::ErrorKind::InvalidData) error
+    /// rather than copying it into memory.
+    ///
+    /// Unlike `SQLITE_LIMIT_LENGTH` this only limits what is read, so the values can still be
+    /// written and processed in sql, e.g. `length(data)`.
+    pub fn max_value_bytes(mut self, max: usize) -> Self {
+        self.max_value_bytes = Some(max);
+        self
+    }
+
+    /// Check the size of the text or blob in column `col` against [`max_value_bytes`](Self::max_value_bytes).
+    pub(crate) fn check_value_size(&self, len: usize, col: usize) -> io::Result<()> {
+        match self.max_value_bytes {
+            Some(max) if len > max => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "sqlite: value of {} bytes in column {} is too large, the limit is {} bytes",
+                    len, col, max
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Select the `rowid` of each `SELECT` implicitly, so that [`ResultSet::rowid`](rinq_rdbc::ResultSet::rowid)
+    /// returns the rowid of the current row even if it is not in the select list.
+    ///
+    /// `rowid` is appended to the result columns of a query which starts with `SELECT` and selects
+    /// `FROM` a single rowid table, the column is hidden from the result set. A query which selects from
+    /// a join, a view, a subquery or a `WITHOUT ROWID` table is prepared unchanged, as is `SELECT DISTINCT`,
+    /// and `rowid` returns an error for it. A table with a column named `rowid` returns that column.
+    ///
+    /// Queries which can not be rewritten are prepared twice, so this is disabled by default.
+    pub fn track_rowid(mut self) -> Self {
+        self.track_rowid = true;
+        self
+    }
+
+    /// Returns true if [`track_rowid`](Self::track_rowid) is enabled.
+    pub(crate) fn is_tracking_rowid(&self) -> bool {
+        self.track_rowid
+    }
+
+    /// Reject a string or blob bound to a parameter compared with an `INTEGER` or `REAL` column,
+    /// e.g. `"42"` for `WHERE id = ?`, which sqlite would otherwise compare as text.
+    ///
+    /// The comparisons `column op ?` and `? op column` are found in the query text when it is prepared,
+    /// and the declared type of the column is looked up in the tables named after `FROM`, `JOIN`,
+    /// `UPDATE` and `INTO`. A parameter in any other position is not checked.
+    /// A mismatch returns [`InvalidInput`](io::ErrorKind::InvalidInput) error from the bind.
+    ///
+    /// Each prepare reads the table schemas, so this is disabled by default.
+    pub fn strict_bind(mut self) -> Self {
+        self.strict_bind = true;
+        self
+    }
+
+    /// Returns true if [`strict_bind`](Self::strict_bind) is enabled.
+    pub(crate) fn is_strict_bind(&self) -> bool {
+        self.strict_bind
+    }
+
+    /// Limit the number of connections open at once by the driver to `max`, e.g. to stay below the file descriptor limit.
+    ///
+    /// Once the limit is reached, [`open`](rinq_rdbc::open) waits until a connection of the driver is closed,
+    /// a connection is closed when the [`DbConn`](rinq_rdbc::DbConn) and all of its statements and transactions are dropped.
+    /// The limit is independent of any connection pool, and it is not set by default.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Returns the limit set by [`max_connections`](Self::max_connections).
+    pub(crate) fn connection_limit(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    /// Set how non-finite floats are bound and decoded, defaults to [`NonFiniteFloats::Coerce`].
+    pub fn non_finite_floats(mut self, policy: NonFiniteFloats) -> Self {
+        self.non_finite_floats = policy;
+        self
+    }
+
+    /// Open each connection with the SQLCipher encryption `key`, requires the `sqlcipher` feature.
+    ///
+    /// The key is passed to `sqlite3_key` right after the database is opened, which is the same as
+    /// running `PRAGMA key` before any other statement, then the schema is read to check the key,
+    /// so a wrong key fails the connect rather than the first query.
+    ///
+    /// The options keep the key to open new connections, it is zeroed when the options are dropped.
+    #[cfg(feature = "sqlcipher")]
+    pub fn encryption_key(mut self, key: SecretBytes) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Check the float bound to parameter `param` against the [`NonFiniteFloats`] policy.
+    pub(crate) fn check_bind_float(
+        &self,
+        value: &SqlValue<'_>,
+        param: &dyn Display,
+    ) -> io::Result<()> {
+        match value {
+            SqlValue::Float(v)
+                if !v.is_finite() && self.non_finite_floats == NonFiniteFloats::Reject =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("sqlite: non-finite float parameter {}", param),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the float read from column `col` against the [`NonFiniteFloats`] policy.
+    pub(crate) fn check_column_float(&self, value: f64, col: usize) -> io::Result<()> {
+        if !value.is_finite() && self.non_finite_floats == NonFiniteFloats::Reject {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sqlite: non-finite float in column {}", col),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the options before they are applied to any connection.
+    ///
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error for a pragma that is not allowed
+    /// or whose value is not a number or a keyword, and for a [`max_connections`](Self::max_connections) of `0`.
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        if self.max_connections == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sqlite: max_connections must be at least 1",
+            ));
+        }
+
+        for (name, value) in &self.pragmas {
+            let allowed = match &self.allowed_pragmas {
+                Some(allowed) => allowed.iter().any(|n| n.eq_ignore_ascii_case(name)),
+                None => DEFAULT_ALLOWED_PRAGMAS
+                    .iter()
+                    .any(|n| n.eq_ignore_ascii_case(name)),
+            };
+
+            if !allowed || !is_pragma_word(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("sqlite: pragma {} is not allowed", name),
+                ));
+            }
+
+            let number = value.strip_prefix('-').unwrap_or(value);
+
+            if !is_pragma_word(number) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("sqlite: invalid value of pragma {}: {}", name, value),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the options to the new connection `conn`.
+    pub(crate) fn apply(&self, conn: &DbConn) -> io::Result<()> {
+        #[cfg(feature = "sqlcipher")]
+        if let Some(key) = &self.encryption_key {
+            apply_key(conn, key)?;
+        }
+
+        if let Some(temp_store) = self.temp_store {
+            conn.exec(match temp_store {
+                TempStore::Default => c"PRAGMA temp_store = DEFAULT",
+                TempStore::File => c"PRAGMA temp_store = FILE",
+                TempStore::Memory => c"PRAGMA temp_store = MEMORY",
+            })?;
+        }
+
+        for (name, value) in &self.pragmas {
+            // checked by `validate`, so the pragma contains no nul byte.
+            conn.exec(&CString::new(format!("PRAGMA {} = {}", name, value))?)?;
+        }
+
+        if let Some(seed) = self.random_seed {
+            register_random(conn, seed)?;
+        }
+
+        for function in &self.functions {
+            function.clone().register(conn)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Secret bytes, e.g. an encryption key, which are zeroed when dropped and not printed by [`Debug`](std::fmt::Debug).
+#[cfg(feature = "sqlcipher")]
+#[derive(Clone)]
+pub struct SecretBytes(Vec<u8>);
+
+#[cfg(feature = "sqlcipher")]
+impl SecretBytes {
+    /// Returns the secret bytes.
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+impl From<Vec<u8>> for SecretBytes {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+impl From<String> for SecretBytes {
+    fn from(value: String) -> Self {
+        Self(value.into_bytes())
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+impl std::fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretBytes({} bytes)", self.0.len())
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        for byte in self.0.iter_mut() {
+            // volatile, so the writes to memory which is freed next are not optimized away.
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+
+        std::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Set the SQLCipher `key` of `conn` and read the schema to check it.
+#[cfg(feature = "sqlcipher")]
+fn apply_key(conn: &DbConn, key: &SecretBytes) -> io::Result<()> {
+    let len = c_int::try_from(key.expose().len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "sqlite: encryption key is too long",
+        )
+    })?;
+
+    unsafe {
+        if ffi::sqlite3_key(conn.to_c_handle(), key.expose().as_ptr().cast(), len) != ffi::SQLITE_OK
+        {
+            return Err(to_io_error(conn.to_c_handle()));
+        }
+    }
+
+    conn.exec(c"SELECT count(*) FROM sqlite_master")
+}
+
+/// Returns true if `word` is a non-empty identifier or unsigned number, which can be put in a pragma as is.
+fn is_pragma_word(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// A splitmix64 generator shared by the `random` and `randomblob` functions of one connection.
+struct SeededRandom(AtomicU64);
+
+impl SeededRandom {
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .0
+            .fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e3779b97f4a7c15);
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Register `random` and `randomblob` on `conn`, which replace the built-ins.
+fn register_random(conn: &DbConn, seed: u64) -> io::Result<()> {
+    let random = Arc::new(SeededRandom(AtomicU64::new(seed)));
+
+    let functions: [(&CStr, c_int, ffi::sqlite3_create_function_callback1); 2] = [
+        (c"random", 0, random_func),
+        (c"randomblob", 1, randomblob_func),
+    ];
+
+    for (name, n_arg, func) in functions {
+        unsafe {
+            // the user data is released by `drop_random`, also when the registration fails.
+            let rc = ffi::sqlite3_create_function_v2(
+                conn.to_c_handle(),
+                name.as_ptr(),
+                n_arg,
+                ffi::SQLITE_UTF8,
+                Arc::into_raw(random.clone()) as *mut c_void,
+                Some(func),
+                None,
+                None,
+                Some(drop_random),
+            );
+
+            if rc != ffi::SQLITE_OK {
+                return Err(to_io_error(conn.to_c_handle()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the generator registered as the user data of the function called with `ctx`.
+unsafe fn random_of<'a>(ctx: *mut ffi::sqlite3_context) -> &'a SeededRandom {
+    &*(ffi::sqlite3_user_data(ctx) as *const SeededRandom)
+}
+
+extern "C" fn random_func(
+    ctx: *mut ffi::sqlite3_context,
+    _argc: c_int,
+    _argv: *mut *mut ffi::sqlite3_value,
+) {
+    unsafe {
+        let value = random_of(ctx).next_u64();
+
+        ffi::sqlite3_result_int64(ctx, value as i64);
+    }
+}
+
+/// Like the built-in, `randomblob(N)` returns a blob of one byte if `N` is less than 1.
+extern "C" fn randomblob_func(
+    ctx: *mut ffi::sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    unsafe {
+        let len = ffi::sqlite3_value_int64(*argv).max(1);
+
+        let max_len = ffi::sqlite3_limit(
+            ffi::sqlite3_context_db_handle(ctx),
+            ffi::SQLITE_LIMIT_LENGTH,
+            -1,
+        );
+
+        if len > max_len as i64 {
+            ffi::sqlite3_result_error_toobig(ctx);
+            return;
+        }
+
+        let random = random_of(ctx);
+
+        let mut blob = Vec::with_capacity(len as usize + 8);
+
+        while blob.len() < len as usize {
+            blob.extend_from_slice(&random.next_u64().to_le_bytes());
+        }
+
+        ffi::sqlite3_result_blob(
+            ctx,
+            blob.as_ptr() as *const c_void,
+            len as c_int,
+            sqlite_transient(),
+        );
+    }
+}
+
+extern "C" fn drop_random(random: *mut c_void) {
+    unsafe { drop(Arc::from_raw(random as *const SeededRandom)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use futures::executor::block_on;
+    use rinq_rdbc::{DbConn, SqlValue};
+
+    use crate::Sqlite;
+
+    use super::*;
+
+    fn register_seeded() {
+        static REGISTER: Once = Once::new();
+
+        REGISTER.call_once(|| {
+            rinq_rdbc::register(
+                "sqlite-seeded",
+                Sqlite::with_options(SqliteOptions::default().deterministic_random(42)).unwrap(),
+            )
+            .unwrap()
+        });
+    }
+
+    async fn randoms(conn: &DbConn) -> Vec<SqlValue<'static>> {
+        let stmt = conn
+            .prepare("SELECT random(), randomblob(20), randomblob(0)")
+            .await
+            .unwrap();
+
+        let mut values = vec![];
+
+        for _ in 0..3 {
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+
+            for col in 0..3 {
+                values.push(result_set.get(col).await.unwrap());
+            }
+        }
+
+        values
+    }
+
+    #[test]
+    fn test_deterministic_random() {
+        register_seeded();
+        crate::tests::register_once();
+
+        block_on(async {
+            let first = randoms(&rinq_rdbc::open("sqlite-seeded", ":memory:").await.unwrap()).await;
+
+            let second =
+                randoms(&rinq_rdbc::open("sqlite-seeded", ":memory:").await.unwrap()).await;
+
+            assert_eq!(first, second);
+
+            // successive calls advance the sequence.
+            assert_ne!(first[0], first[3]);
+
+            assert!(matches!(&first[1], SqlValue::Binary(blob) if blob.len() == 20));
+            assert!(matches!(&first[2], SqlValue::Binary(blob) if blob.len() == 1));
+
+            let builtin = randoms(&rinq_rdbc::open("sqlite", ":memory:").await.unwrap()).await;
+
+            assert_ne!(first, builtin);
+        });
+    }
+
+    #[test]
+    fn test_pragmas() {
+        let err = Sqlite::with_options(SqliteOptions::default().pragma("writable_schema", "ON"))
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("writable_schema"));
+
+        let err = Sqlite::with_options(
+            SqliteOptions::default().pragma("cache_size", "1; DROP TABLE users"),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        Sqlite::with_options(
+            SqliteOptions::default()
+                .allowed_pragmas(&["writable_schema"])
+                .pragma("writable_schema", "ON"),
+        )
+        .unwrap();
+
+        rinq_rdbc::register(
+            "sqlite-pragmas",
+            Sqlite::with_options(
+                SqliteOptions::default()
+                    .pragma("cache_size", "-4000")
+                    .pragma("Foreign_Keys", "ON"),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-pragmas", ":memory:").await.unwrap();
+
+            for (pragma, value) in [("cache_size", -4000), ("foreign_keys", 1)] {
+                let stmt = conn.prepare(&format!("PRAGMA {}", pragma)).await.unwrap();
+                let result_set = stmt.query(&[]).await.unwrap();
+
+                assert!(result_set.next().await.unwrap());
+                assert_eq!(result_set.get(0).await.unwrap(), SqlValue::Int(value));
+            }
+        });
+    }
+
+    async fn round_trip(conn: &DbConn, value: f64) -> io::Result<SqlValue<'static>> {
+        let stmt = conn.prepare("SELECT ?").await?;
+        let result_set = stmt.query(&[SqlValue::Float(value)]).await?;
+
+        assert!(result_set.next().await?);
+
+        result_set.get(0).await
+    }
+
+    #[test]
+    fn test_non_finite_floats() {
+        crate::tests::register_once();
+
+        rinq_rdbc::register(
+            "sqlite-reject-non-finite",
+            Sqlite::with_options(
+                SqliteOptions::default().non_finite_floats(NonFiniteFloats::Reject),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            assert_eq!(round_trip(&conn, f64::NAN).await.unwrap(), SqlValue::Null);
+            assert_eq!(
+                round_trip(&conn, f64::INFINITY).await.unwrap(),
+                SqlValue::Float(f64::INFINITY)
+            );
+
+            let conn = rinq_rdbc::open("sqlite-reject-non-finite", ":memory:")
+                .await
+                .unwrap();
+
+            for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                let err = round_trip(&conn, value).await.unwrap_err();
+
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            }
+
+            let stmt = conn.prepare("SELECT :v").await.unwrap();
+            let err = stmt
+                .query_named(&[("v", SqlValue::Float(f64::NAN))])
+                .await
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains('v'));
+
+            assert_eq!(round_trip(&conn, 1.5).await.unwrap(), SqlValue::Float(1.5));
+
+            let stmt = conn.prepare("SELECT 9e999").await.unwrap();
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(
+                result_set.get(0).await.unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        });
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn test_encryption_key() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        crate::tests::register_once();
+
+        let options = |key: &str| {
+            Sqlite::with_options(
+                SqliteOptions::default().encryption_key(SecretBytes::from(key.to_string())),
+            )
+            .unwrap()
+        };
+
+        rinq_rdbc::register("sqlite-cipher", options("correct horse")).unwrap();
+        rinq_rdbc::register("sqlite-cipher-wrong", options("battery staple")).unwrap();
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+
+        let path = std::env::temp_dir().join(format!("rinq-cipher-{}.db", nanos));
+        let source_name = path.to_str().unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-cipher", source_name).await.unwrap();
+
+            conn.execute("CREATE TABLE t(x)", &[]).await.unwrap();
+
+            drop(conn);
+
+            rinq_rdbc::open("sqlite-cipher", source_name).await.unwrap();
+
+            assert!(rinq_rdbc::open("sqlite-cipher-wrong", source_name)
+                .await
+                .is_err());
+
+            let conn = rinq_rdbc::open("sqlite", source_name).await.unwrap();
+
+            assert!(conn.prepare("SELECT * FROM t").await.is_err());
+        });
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_temp_store() {
+        rinq_rdbc::register(
+            "sqlite-temp-memory",
+            Sqlite::with_options(SqliteOptions::default().temp_store(TempStore::Memory)).unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-temp-memory", ":memory:")
+                .await
+                .unwrap();
+
+            let stmt = conn.prepare("PRAGMA temp_store").await.unwrap();
+            let result_set = stmt.query(&[]).await.unwrap();
+
+            assert!(result_set.next().await.unwrap());
+            assert_eq!(result_set.get(0).await.unwrap(), SqlValue::Int(2));
+        });
+    }
+
+    #[test]
+    fn test_base_dir() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+
+        let base_dir = std::env::temp_dir().join(format!("rinq-base-dir-{}", nanos));
+
+        std::fs::create_dir(&base_dir).unwrap();
+
+        let options = SqliteOptions::default().base_dir(&base_dir);
+
+        assert_eq!(options.resolve_source(":memory:").unwrap(), ":memory:");
+        assert_eq!(options.resolve_source("").unwrap(), "");
+        assert_eq!(
+            options.resolve_source("file:data.db?mode=ro").unwrap(),
+            "file:data.db?mode=ro"
+        );
+
+        rinq_rdbc::register("sqlite-base-dir", Sqlite::with_options(options).unwrap()).unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-base-dir", "data.db").await.unwrap();
+
+            conn.execute("CREATE TABLE t(x)", &[]).await.unwrap();
+        });
+
+        assert!(base_dir.join("data.db").is_file());
+        assert!(!Path::new("data.db").exists());
+
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+
+    #[test]
+    fn test_max_value_bytes() {
+        rinq_rdbc::register(
+            "sqlite-max-value",
+            Sqlite::with_options(SqliteOptions::default().max_value_bytes(8)).unwrap(),
+        )
+        .unwrap();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite-max-value", ":memory:")
+                .await
+                .unwrap();
+
+            let stmt = conn
+                .prepare("SELECT ?, ?, length(?), zeroblob(8)")
+                .await
+                .unwrap();
+
+            let result_set = stmt
+                .query(&[
+                    SqlValue::String("0123456789".into()),
+                    SqlValue::Binary(vec![0; 9].into()),
+                    SqlValue::String("0123456789".into()),
+                ])
+                .await
+                .unwrap();
+
+            assert!(result_set.next().await.unwrap());
+
+            for col in 0..2 {
+                let err = result_set.get(col).await.unwrap_err();
+
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                assert!(err.to_string().contains("too large"));
+            }
+
+            assert_eq!(result_set.get(2).await.unwrap(), SqlValue::Int(10));
+            assert_eq!(
+                result_set.get(3).await.unwrap(),
+                SqlValue::Binary(vec![0; 8].into())
+            );
+        });
+    }
+}
@@ -0,0 +1,123 @@
+//! The query rewrite of [`SqliteOptions::track_rowid`](crate::SqliteOptions::track_rowid).
+
+/// Returns `query` with `rowid` appended to the result columns, or `None` if `query` is not a plain `SELECT`.
+///
+/// The column is inserted before the first `FROM` outside of parentheses, strings, quoted identifiers
+/// and comments, so the positions of the selected columns do not change, e.g. for `ORDER BY 1`.
+/// `SELECT DISTINCT` is not rewritten, because the extra column would change which rows are distinct.
+///
+/// Whether `rowid` resolves to a single table is left to sqlite, which fails to prepare the rewritten
+/// query for a join, a view or a compound select whose other parts have fewer columns.
+pub(crate) fn select_with_rowid(query: &str) -> Option<String> {
+    let start = query.len() - query.trim_start().len();
+
+    let keyword = query.get(start..start + 6)?;
+
+    if !keyword.eq_ignore_ascii_case("select") || is_ident(query[start + 6..].chars().next()?) {
+        return None;
+    }
+
+    let rest = query[start + 6..].trim_start();
+
+    if rest
+        .get(..8)
+        .is_some_and(|word| word.eq_ignore_ascii_case("distinct"))
+    {
+        return None;
+    }
+
+    let bytes = query.as_bytes();
+    let mut depth = 0usize;
+    let mut i = start + 6;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'(' => depth += 1,
+            b')' => depth = depth.checked_sub(1)?,
+            quote @ (b'\'' | b'"' | b'`') => i = skip_quoted(bytes, i, quote)?,
+            b'[' => i = skip_quoted(bytes, i, b']')?,
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = query[i..].find('\n').map_or(bytes.len(), |end| i + end);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = query[i + 2..].find("*/").map(|end| i + 2 + end + 1)?;
+            }
+            b'f' | b'F'
+                if depth == 0
+                    && query
+                        .get(i..i + 4)
+                        .is_some_and(|word| word.eq_ignore_ascii_case("from"))
+                    && !is_ident(query[..i].chars().next_back()?)
+                    && query[i + 4..].chars().next().is_some_and(|c| !is_ident(c)) =>
+            {
+                return Some(format!("{}, rowid {}", &query[..i], &query[i..]));
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Returns the index of the quote that ends the quoted text starting at `start`,
+/// a doubled quote is an escaped one.
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8) -> Option<usize> {
+    let mut i = start + 1;
+
+    loop {
+        if *bytes.get(i)? == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+
+            return Some(i);
+        }
+
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_with_rowid() {
+        assert_eq!(
+            select_with_rowid("SELECT name FROM t ORDER BY 1").unwrap(),
+            "SELECT name , rowid FROM t ORDER BY 1"
+        );
+        assert_eq!(
+            select_with_rowid("  select *\nfrom t").unwrap(),
+            "  select *\n, rowid from t"
+        );
+        assert_eq!(
+            select_with_rowid(
+                "SELECT (SELECT 1 FROM u), 'from', \"from\", [from], `from` -- from\n /* from */ FROM t"
+            )
+            .unwrap(),
+            "SELECT (SELECT 1 FROM u), 'from', \"from\", [from], `from` -- from\n /* from */ , rowid FROM t"
+        );
+        assert_eq!(
+            select_with_rowid("SELECT 'it''s' AS fromage FROM t").unwrap(),
+            "SELECT 'it''s' AS fromage , rowid FROM t"
+        );
+
+        assert_eq!(select_with_rowid("SELECT 1"), None);
+        assert_eq!(select_with_rowid("SELECT DISTINCT name FROM t"), None);
+        assert_eq!(select_with_rowid("SELECTED FROM t"), None);
+        assert_eq!(
+            select_with_rowid("WITH c AS (SELECT 1) SELECT * FROM c"),
+            None
+        );
+        assert_eq!(select_with_rowid("DELETE FROM t"), None);
+        assert_eq!(select_with_rowid("SELECT 'unterminated FROM t"), None);
+    }
+}
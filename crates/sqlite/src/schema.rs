@@ -0,0 +1,125 @@
+use std::{borrow::Cow, future::Future, io};
+
+use rinq_rdbc::{DbConn, SqlValue};
+
+use crate::Attach;
+
+/// Sqlite schema lookups of a [`DbConn`] opened with the [`Sqlite`](crate::Sqlite) driver.
+///
+/// A name is either unqualified, e.g. `users`, which is looked up in all databases of the connection
+/// like sqlite resolves it in a query, or qualified with a schema, e.g. `main.users` or `archive.users`,
+/// which is split at the first `.`. Names are compared case-insensitively like sql identifiers.
+pub trait Schema {
+    /// Returns true if the table `name` exists, a view is not a table.
+    ///
+    /// Returns error if the schema of a qualified name is not attached.
+    fn table_exists(&self, name: &str) -> impl Future<Output = io::Result<bool>>;
+
+    /// Returns true if the index `name` exists, including the automatic indexes of `UNIQUE` constraints.
+    ///
+    /// Returns error if the schema of a qualified name is not attached.
+    fn index_exists(&self, name: &str) -> impl Future<Output = io::Result<bool>>;
+}
+
+impl Schema for DbConn {
+    async fn table_exists(&self, name: &str) -> io::Result<bool> {
+        object_exists(self, "table", name).await
+    }
+
+    async fn index_exists(&self, name: &str) -> io::Result<bool> {
+        object_exists(self, "index", name).await
+    }
+}
+
+/// Returns true if an object of `object_type` named `name` exists in the schema table.
+async fn object_exists(conn: &DbConn, object_type: &str, name: &str) -> io::Result<bool> {
+    let (schemas, name) = match name.split_once('.') {
+        Some((schema, name)) => (vec![schema.to_owned()], name),
+        None => (
+            conn.database_list()
+                .await?
+                .into_iter()
+                .map(|(schema, _)| schema)
+                .collect(),
+            name,
+        ),
+    };
+
+    for schema in schemas {
+        // schema names can not be bound as parameters, `temp.sqlite_master` is an alias of `sqlite_temp_master`.
+        let stmt = conn
+            .prepare(format!(
+                "SELECT 1 FROM \"{}\".sqlite_master WHERE type = ? AND name = ? COLLATE NOCASE",
+                schema.replace('"', "\"\"")
+            ))
+            .await?;
+
+        let result_set = stmt
+            .query(&[
+                SqlValue::String(Cow::Borrowed(object_type)),
+                SqlValue::String(Cow::Borrowed(name)),
+            ])
+            .await?;
+
+        if result_set.next().await? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn test_table_exists() {
+        crate::tests::register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT UNIQUE)",
+                &[],
+            )
+            .await
+            .unwrap();
+            conn.execute("CREATE INDEX users_name ON users (name)", &[])
+                .await
+                .unwrap();
+            conn.execute("CREATE VIEW names AS SELECT name FROM users", &[])
+                .await
+                .unwrap();
+
+            conn.attach(":memory:", "archive").await.unwrap();
+
+            conn.execute("CREATE TABLE archive.posts (id INTEGER)", &[])
+                .await
+                .unwrap();
+
+            assert!(conn.table_exists("users").await.unwrap());
+            assert!(conn.table_exists("USERS").await.unwrap());
+            assert!(conn.table_exists("main.users").await.unwrap());
+            assert!(!conn.table_exists("archive.users").await.unwrap());
+
+            assert!(conn.table_exists("posts").await.unwrap());
+            assert!(conn.table_exists("archive.posts").await.unwrap());
+
+            assert!(!conn.table_exists("missing").await.unwrap());
+            assert!(!conn.table_exists("names").await.unwrap());
+            // a name is bound, not spliced into the sql.
+            assert!(!conn.table_exists("users' OR '1' = '1").await.unwrap());
+
+            assert!(conn.table_exists("nowhere.users").await.is_err());
+
+            assert!(conn.index_exists("users_name").await.unwrap());
+            assert!(conn.index_exists("main.users_name").await.unwrap());
+            assert!(!conn.index_exists("users").await.unwrap());
+            assert!(!conn.index_exists("missing_index").await.unwrap());
+        });
+    }
+}
@@ -0,0 +1,179 @@
+//! The session values of [`DbConn::set_session_value`](rinq_rdbc::DbConn::set_session_value).
+
+use std::{
+    collections::HashMap,
+    ffi::{c_int, c_void},
+    io,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use rinq_rdbc::SqlValue;
+use sqlite3_sys as ffi;
+
+use crate::{
+    aggregate::{result_error, set_result, value_of},
+    to_io_error, DbConn,
+};
+
+/// The values of a connection, shared by its clones and read by the `session_value(key)` sql function.
+///
+/// The function of the connection points to this value, so it is declared
+/// after the raw connection in [`DbConn`] to outlive it.
+#[derive(Default)]
+pub(crate) struct Session {
+    values: Mutex<HashMap<String, SqlValue<'static>>>,
+}
+
+impl Session {
+    /// Register the `session_value(key)` function on `conn`, which returns the value of `key` or `NULL`.
+    pub(crate) fn register(conn: &DbConn) -> io::Result<()> {
+        unsafe {
+            let rc = ffi::sqlite3_create_function_v2(
+                conn.to_c_handle(),
+                c"session_value".as_ptr(),
+                1,
+                ffi::SQLITE_UTF8,
+                Arc::as_ptr(&conn.session) as *mut c_void,
+                Some(session_value),
+                None,
+                None,
+                None,
+            );
+
+            if rc != ffi::SQLITE_OK {
+                return Err(to_io_error(conn.to_c_handle()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set `key` to `value`, [`SqlValue::Null`] removes the key.
+    pub(crate) fn set(&self, key: &str, value: SqlValue<'static>) {
+        let mut values = self.lock();
+
+        if value == SqlValue::Null {
+            values.remove(key);
+        } else {
+            values.insert(key.to_owned(), value);
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<SqlValue<'static>> {
+        self.lock()
+            .get(key)
+            .map(|value| value.as_ref().into_owned())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, SqlValue<'static>>> {
+        self.values.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The function runs inside `sqlite3_step`, it must not panic or call back into the connection.
+extern "C" fn session_value(
+    ctx: *mut ffi::sqlite3_context,
+    _argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    // Safety: the user data is set by `Session::register` and outlives the connection,
+    // the function is registered with one argument.
+    unsafe {
+        let session = &*(ffi::sqlite3_user_data(ctx) as *const Session);
+
+        let value = match value_of(*argv) {
+            Ok(SqlValue::String(key)) => session.get(&key),
+            Ok(_) => {
+                return result_error(
+                    ctx,
+                    &io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "sqlite: session_value expects a text key",
+                    ),
+                )
+            }
+            Err(err) => return result_error(ctx, &err),
+        };
+
+        set_result(ctx, &value.unwrap_or(SqlValue::Null));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn test_session_value() {
+        crate::tests::register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE doc (id INTEGER PRIMARY KEY, body TEXT)", &[])
+                .await
+                .unwrap();
+            conn.execute("CREATE TABLE audit (doc_id INTEGER, user_id INTEGER)", &[])
+                .await
+                .unwrap();
+            conn.execute(
+                "CREATE TRIGGER doc_audit AFTER INSERT ON doc BEGIN
+                 INSERT INTO audit VALUES (new.id, session_value('user_id'));
+                 END",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(conn.get_session_value("user_id").unwrap(), None);
+
+            conn.set_session_value("user_id", 42).unwrap();
+
+            assert_eq!(
+                conn.get_session_value("user_id").unwrap(),
+                Some(SqlValue::Int(42))
+            );
+
+            // the value is kept across statements and read by the trigger.
+            for body in ["a", "b"] {
+                conn.execute("INSERT INTO doc (body) VALUES (?)", &[body.into()])
+                    .await
+                    .unwrap();
+            }
+
+            let rs = conn
+                .query_one_shot(
+                    "SELECT count(*), min(user_id), session_value('missing') FROM audit",
+                    &[],
+                )
+                .await
+                .unwrap();
+
+            assert!(rs.next().await.unwrap());
+            assert_eq!(rs.get(0).await.unwrap(), SqlValue::Int(2));
+            assert_eq!(rs.get(1).await.unwrap(), SqlValue::Int(42));
+            assert_eq!(rs.get(2).await.unwrap(), SqlValue::Null);
+
+            conn.set_session_value("user_id", SqlValue::Null).unwrap();
+
+            assert_eq!(conn.get_session_value("user_id").unwrap(), None);
+
+            let err = conn
+                .query_one_shot("SELECT session_value(1)", &[])
+                .await
+                .unwrap()
+                .next()
+                .await
+                .unwrap_err();
+
+            assert!(err.to_string().contains("expects a text key"));
+
+            // the values belong to the connection.
+            let other = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            assert_eq!(other.get_session_value("user_id").unwrap(), None);
+        });
+    }
+}
@@ -0,0 +1,400 @@
+//! The parameter type checks of [`SqliteOptions::strict_bind`](crate::SqliteOptions::strict_bind).
+
+use std::{collections::HashMap, io};
+
+use rinq_rdbc::SqlValue;
+
+use crate::{DbConn, DbStmt};
+
+/// The column a parameter is compared with, e.g. `id` in `WHERE id = ?`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ParamType {
+    column: String,
+    decltype: String,
+}
+
+impl ParamType {
+    /// Returns [`InvalidInput`](io::ErrorKind::InvalidInput) error if `value` is a string or blob
+    /// and the column has `INTEGER` or `REAL` affinity.
+    pub(crate) fn check(
+        &self,
+        value: &SqlValue<'_>,
+        param: &dyn std::fmt::Display,
+    ) -> io::Result<()> {
+        let decltype = self.decltype.to_ascii_uppercase();
+
+        // the affinity rules of https://www.sqlite.org/datatype3.html#determination_of_column_affinity
+        let numeric = decltype.contains("INT")
+            || (!["CHAR", "CLOB", "TEXT", "BLOB"]
+                .iter()
+                .any(|t| decltype.contains(t))
+                && ["REAL", "FLOA", "DOUB"]
+                    .iter()
+                    .any(|t| decltype.contains(t)));
+
+        if numeric && matches!(value, SqlValue::String(_) | SqlValue::Binary(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "sqlite: bind type mismatch, parameter {} is compared with {} column {}, got {}",
+                    param,
+                    self.decltype,
+                    self.column,
+                    value.redacted()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An identifier or keyword, unquoted.
+    Ident(String),
+    /// A parameter with its index.
+    Param(usize),
+    /// A comparison or assignment operator.
+    Op,
+    Dot,
+    Comma,
+    Other,
+}
+
+/// The keywords that end a table reference rather than naming its alias.
+const CLAUSE_KEYWORDS: &[&str] = &[
+    "cross",
+    "default",
+    "except",
+    "full",
+    "group",
+    "having",
+    "indexed",
+    "inner",
+    "intersect",
+    "join",
+    "left",
+    "limit",
+    "natural",
+    "not",
+    "on",
+    "order",
+    "outer",
+    "returning",
+    "right",
+    "select",
+    "set",
+    "union",
+    "using",
+    "values",
+    "where",
+    "window",
+];
+
+impl DbConn {
+    /// Returns the column that each parameter of `stmt` is compared with, indexed by the parameter index - 1.
+    ///
+    /// The comparisons `column op ?` and `? op column` are found in `query`, and the declared type
+    /// of the column is looked up in the tables named after `FROM`, `JOIN`, `UPDATE` and `INTO`.
+    pub(crate) fn param_types(
+        &self,
+        stmt: &DbStmt,
+        query: &str,
+    ) -> io::Result<Vec<Option<ParamType>>> {
+        let tokens = tokenize(query);
+        let tables = tables(&tokens);
+
+        let mut param_types = vec![None; stmt.parameter_count()];
+
+        for (param, qualifier, column) in comparisons(&tokens) {
+            let Some(slot) = param.checked_sub(1).and_then(|i| param_types.get_mut(i)) else {
+                continue;
+            };
+
+            let candidates = tables.iter().filter(|(_, table, alias)| match qualifier {
+                Some(qualifier) => {
+                    alias
+                        .as_deref()
+                        .unwrap_or(table)
+                        .eq_ignore_ascii_case(qualifier)
+                        || table.eq_ignore_ascii_case(qualifier)
+                }
+                None => true,
+            });
+
+            for (schema, table, _) in candidates {
+                if let Some(decltype) = self.declared_type(schema.as_deref(), table, column)? {
+                    *slot = Some(ParamType {
+                        column: column.to_owned(),
+                        decltype,
+                    });
+
+                    break;
+                }
+            }
+        }
+
+        Ok(param_types)
+    }
+
+    /// Returns the declared type of `column` of `table`, `None` if the table has no such column.
+    fn declared_type(
+        &self,
+        schema: Option<&str>,
+        table: &str,
+        column: &str,
+    ) -> io::Result<Option<String>> {
+        let stmt = self.prepare_raw(
+            c"SELECT type FROM pragma_table_info(?1, ?2) WHERE name = ?3 COLLATE NOCASE",
+        )?;
+
+        stmt.bind(&[
+            table.into(),
+            schema.map(SqlValue::from).unwrap_or(SqlValue::Null),
+            column.into(),
+        ])?;
+
+        if !stmt.step()? {
+            return Ok(None);
+        }
+
+        match stmt.column_storage_value(0)? {
+            SqlValue::String(decltype) => Ok(Some(decltype.into_owned())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Returns the tokens of `query`, strings, numbers and comments are [`Token::Other`].
+///
+/// Parameters are numbered as sqlite does: `?NNN` has index `NNN`, `?` and the first occurrence
+/// of a named parameter take the largest index so far plus one.
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut chars = query.chars().peekable();
+    let mut tokens = vec![];
+    let mut max_index = 0;
+    let mut names = HashMap::new();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\'' => {
+                for next in chars.by_ref() {
+                    if next == '\'' {
+                        break;
+                    }
+                }
+
+                Token::Other
+            }
+            '"' | '`' | '[' => {
+                let end = if c == '[' { ']' } else { c };
+
+                Token::Ident(chars.by_ref().take_while(|next| *next != end).collect())
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for _ in chars.by_ref().take_while(|next| *next != '\n') {}
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                let mut star = false;
+
+                for next in chars.by_ref() {
+                    if star && next == '/' {
+                        break;
+                    }
+
+                    star = next == '*';
+                }
+
+                continue;
+            }
+            '?' => {
+                let mut digits = String::new();
+
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+
+                let index = digits.parse().unwrap_or(max_index + 1);
+
+                max_index = max_index.max(index);
+
+                Token::Param(index)
+            }
+            ':' | '@' | '$' if chars.peek().is_some_and(|next| is_ident(*next)) => {
+                let mut name = c.to_string();
+
+                while let Some(next) = chars.next_if(|next| is_ident(*next)) {
+                    name.push(next);
+                }
+
+                let index = *names.entry(name).or_insert_with(|| {
+                    max_index += 1;
+                    max_index
+                });
+
+                Token::Param(index)
+            }
+            '=' | '<' | '>' => {
+                _ = chars.next_if(|next| matches!((c, next), ('=' | '<' | '>', '=') | ('<', '>')));
+                Token::Op
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op,
+            '.' => Token::Dot,
+            ',' => Token::Comma,
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+
+                while let Some(next) = chars.next_if(|next| is_ident(*next)) {
+                    ident.push(next);
+                }
+
+                Token::Ident(ident)
+            }
+            c if c.is_ascii_digit() => {
+                while chars
+                    .next_if(|next| is_ident(*next) || *next == '.')
+                    .is_some()
+                {}
+
+                Token::Other
+            }
+            c if c.is_whitespace() => continue,
+            _ => Token::Other,
+        };
+
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Returns the `(schema, table, alias)` of each table named after `FROM`, `JOIN`, `UPDATE` or `INTO`.
+fn tables(tokens: &[Token]) -> Vec<(Option<String>, String, Option<String>)> {
+    let mut tables = vec![];
+
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let starts_list = matches!(&tokens[i], Token::Ident(word)
+            if ["from", "join", "update", "into"].iter().any(|k| word.eq_ignore_ascii_case(k)));
+
+        i += 1;
+
+        if !starts_list {
+            continue;
+        }
+
+        loop {
+            let (schema, table) = match (tokens.get(i), tokens.get(i + 1), tokens.get(i + 2)) {
+                (Some(Token::Ident(schema)), Some(Token::Dot), Some(Token::Ident(table))) => {
+                    i += 3;
+                    (Some(schema.clone()), table.clone())
+                }
+                (Some(Token::Ident(table)), _, _) => {
+                    i += 1;
+                    (None, table.clone())
+                }
+                _ => break,
+            };
+
+            if matches!(tokens.get(i), Some(Token::Ident(word)) if word.eq_ignore_ascii_case("as"))
+            {
+                i += 1;
+            }
+
+            let alias = match tokens.get(i) {
+                Some(Token::Ident(alias))
+                    if !CLAUSE_KEYWORDS
+                        .iter()
+                        .any(|k| alias.eq_ignore_ascii_case(k)) =>
+                {
+                    i += 1;
+                    Some(alias.clone())
+                }
+                _ => None,
+            };
+
+            tables.push((schema, table, alias));
+
+            if tokens.get(i) != Some(&Token::Comma) {
+                break;
+            }
+
+            i += 1;
+        }
+    }
+
+    tables
+}
+
+/// Returns the `(param, qualifier, column)` of each comparison of a column with a parameter.
+fn comparisons(tokens: &[Token]) -> Vec<(usize, Option<&str>, &str)> {
+    let mut comparisons = vec![];
+
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Op = token else {
+            continue;
+        };
+
+        // `column op ?` or `qualifier.column op ?`
+        if let (Some(Token::Ident(column)), Some(Token::Param(param))) =
+            (i.checked_sub(1).map(|j| &tokens[j]), tokens.get(i + 1))
+        {
+            let qualifier = match i.checked_sub(3).map(|j| &tokens[j..i - 1]) {
+                Some([Token::Ident(qualifier), Token::Dot]) => Some(qualifier.as_str()),
+                _ => None,
+            };
+
+            comparisons.push((*param, qualifier, column.as_str()));
+        }
+
+        // `? op column` or `? op qualifier.column`
+        if let (Some(Token::Param(param)), Some(Token::Ident(first))) =
+            (i.checked_sub(1).map(|j| &tokens[j]), tokens.get(i + 1))
+        {
+            match (tokens.get(i + 2), tokens.get(i + 3)) {
+                (Some(Token::Dot), Some(Token::Ident(column))) => {
+                    comparisons.push((*param, Some(first.as_str()), column.as_str()))
+                }
+                _ => comparisons.push((*param, None, first.as_str())),
+            }
+        }
+    }
+
+    comparisons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparisons() {
+        let tokens = tokenize(
+            "SELECT * FROM main.users AS u, orders o JOIN items ON o.id = items.order_id \
+             WHERE u.id = ? AND 'a = ?' <> ?2 AND ?3 >= total -- AND x = ?\n AND name != :name",
+        );
+
+        assert_eq!(
+            tables(&tokens),
+            [
+                (Some("main".into()), "users".into(), Some("u".into())),
+                (None, "orders".into(), Some("o".into())),
+                (None, "items".into(), None),
+            ]
+        );
+
+        assert_eq!(
+            comparisons(&tokens),
+            [(1, Some("u"), "id"), (3, None, "total"), (4, None, "name")]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Date and time columns, sqlite has no date or time storage class.
+//!
+//! Temporal values are bound as ISO 8601 text in the formats of [`rinq_rdbc::DATE_FORMAT`],
+//! [`rinq_rdbc::TIME_FORMAT`] and [`rinq_rdbc::TIMESTAMP_FORMAT`]. Text read from a column whose declared type is
+//! `DATE`, `TIME`, `DATETIME` or `TIMESTAMP` is parsed back, timestamps also accept the
+//! `YYYY-MM-DD HH:MM:SS` form of sqlite's `CURRENT_TIMESTAMP` and any rfc 3339 offset.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use rinq_rdbc::{SqlValue, DATE_FORMAT, TIME_FORMAT};
+
+/// The temporal variant selected by the declared type of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TemporalType {
+    Date,
+    Time,
+    Timestamp,
+}
+
+/// Returns the temporal variant of the declared type `decltype`, or `None` if it is not a date or time type.
+pub(crate) fn temporal_type(decltype: &str) -> Option<TemporalType> {
+    let name = decltype
+        .split(|c: char| c == '(' || c.is_whitespace())
+        .find(|word| !word.is_empty())
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+
+    match name.as_str() {
+        "DATE" => Some(TemporalType::Date),
+        "TIME" => Some(TemporalType::Time),
+        "DATETIME" | "TIMESTAMP" => Some(TemporalType::Timestamp),
+        _ => None,
+    }
+}
+
+/// Parse a text value read from a column of `temporal_type`, other values and unparsable text are returned unchanged.
+pub(crate) fn to_temporal(
+    value: SqlValue<'static>,
+    temporal_type: TemporalType,
+) -> SqlValue<'static> {
+    let SqlValue::String(text) = &value else {
+        return value;
+    };
+
+    let text = text.trim();
+
+    let parsed = match temporal_type {
+        TemporalType::Date => NaiveDate::parse_from_str(text, DATE_FORMAT)
+            .ok()
+            .map(SqlValue::Date),
+        TemporalType::Time => NaiveTime::parse_from_str(text, TIME_FORMAT)
+            .ok()
+            .map(SqlValue::Time),
+        TemporalType::Timestamp => parse_timestamp(text).map(SqlValue::Timestamp),
+    };
+
+    parsed.unwrap_or(value)
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|timestamp| timestamp.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn test_to_temporal() {
+        assert_eq!(temporal_type("date"), Some(TemporalType::Date));
+        assert_eq!(temporal_type("TIMESTAMP(3)"), Some(TemporalType::Timestamp));
+        assert_eq!(temporal_type("DATETIME"), Some(TemporalType::Timestamp));
+        assert_eq!(temporal_type("TEXT"), None);
+
+        let text = |v: &str| SqlValue::String(Cow::Owned(v.to_owned()));
+
+        let expect = NaiveDate::from_ymd_opt(2024, 2, 29)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc();
+
+        for v in [
+            "2024-02-29T12:00:00Z",
+            "2024-02-29T20:00:00+08:00",
+            "2024-02-29 12:00:00",
+        ] {
+            assert!(
+                matches!(to_temporal(text(v), TemporalType::Timestamp), SqlValue::Timestamp(t) if t == expect)
+            );
+        }
+
+        assert!(matches!(
+            to_temporal(text("yesterday"), TemporalType::Date),
+            SqlValue::String(_)
+        ));
+        assert!(matches!(
+            to_temporal(SqlValue::Int(1), TemporalType::Time),
+            SqlValue::Int(1)
+        ));
+    }
+}
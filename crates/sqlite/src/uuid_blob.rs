@@ -0,0 +1,67 @@
+//! Uuid columns, sqlite has no uuid storage class.
+//!
+//! [`SqlValue::Uuid`] is bound as a 16-byte blob in big-endian byte order,
+//! blobs read from a column whose declared type is `UUID` are converted back.
+
+use std::io;
+
+use rinq_rdbc::SqlValue;
+use uuid::Uuid;
+
+/// Returns true if the declared type `decltype` is `UUID`.
+pub(crate) fn is_uuid_type(decltype: &str) -> bool {
+    decltype
+        .split(|c: char| c == '(' || c.is_whitespace())
+        .find(|word| !word.is_empty())
+        .is_some_and(|name| name.eq_ignore_ascii_case("UUID"))
+}
+
+/// Convert a blob read from a `UUID` column, other values are returned unchanged.
+///
+/// Returns an [`InvalidData`](io::ErrorKind::InvalidData) error if the blob is not 16 bytes long.
+pub(crate) fn to_uuid(value: SqlValue<'static>) -> io::Result<SqlValue<'static>> {
+    let SqlValue::Binary(blob) = &value else {
+        return Ok(value);
+    };
+
+    let uuid = Uuid::from_slice(blob).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "sqlite: expect a 16-byte blob in a UUID column, got {} bytes",
+                blob.len()
+            ),
+        )
+    })?;
+
+    Ok(SqlValue::Uuid(uuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn test_to_uuid() {
+        assert!(is_uuid_type("UUID"));
+        assert!(is_uuid_type("uuid NOT NULL"));
+        assert!(!is_uuid_type("BLOB"));
+        assert!(!is_uuid_type(""));
+
+        let uuid = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+
+        let value = to_uuid(SqlValue::Binary(Cow::Owned(uuid.as_bytes().to_vec()))).unwrap();
+
+        assert!(matches!(value, SqlValue::Uuid(v) if v == uuid));
+
+        assert!(matches!(to_uuid(SqlValue::Null).unwrap(), SqlValue::Null));
+
+        let err = to_uuid(SqlValue::Binary(Cow::Owned(vec![0; 15])))
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
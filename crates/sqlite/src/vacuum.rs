@@ -0,0 +1,138 @@
+use std::{future::Future, io, path::Path};
+
+use rinq_rdbc::DbConn;
+
+/// Sqlite `VACUUM` commands of a [`DbConn`] opened with the [`Sqlite`](crate::Sqlite) driver.
+///
+/// `VACUUM` can not run inside a transaction, the returned error says
+/// `cannot VACUUM from within a transaction` if one is open.
+pub trait Vacuum {
+    /// Rebuild the main database file to reclaim free pages and defragment it.
+    fn vacuum(&self) -> impl Future<Output = io::Result<()>>;
+
+    /// Write a compacted copy of the main database to a new file at `path`, the database itself is unchanged.
+    ///
+    /// Returns error if `path` is not valid UTF-8 or the file already exists.
+    fn vacuum_into<P: AsRef<Path>>(&self, path: P) -> impl Future<Output = io::Result<()>>;
+}
+
+impl Vacuum for DbConn {
+    async fn vacuum(&self) -> io::Result<()> {
+        self.prepare("VACUUM").await?.exec(&[]).await?;
+
+        Ok(())
+    }
+
+    async fn vacuum_into<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+
+        let path = path.to_str().ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("sqlite: vacuum into a non UTF-8 path: {:?}", path),
+        ))?;
+
+        self.prepare(format!("VACUUM INTO '{}'", path.replace('\'', "''")))
+            .await?
+            .exec(&[])
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use futures::executor::block_on;
+    use rinq_rdbc::SqlValue;
+
+    use super::*;
+
+    async fn populated() -> DbConn {
+        crate::tests::register_once();
+
+        let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+        for query in [
+            "CREATE TABLE log (id INTEGER PRIMARY KEY, message TEXT)",
+            "INSERT INTO log (message) VALUES ('a'), ('b'), ('c')",
+            "DELETE FROM log WHERE message = 'b'",
+        ] {
+            conn.prepare(query).await.unwrap().exec(&[]).await.unwrap();
+        }
+
+        conn
+    }
+
+    async fn count(conn: &DbConn) -> i64 {
+        let result_set = conn
+            .prepare("SELECT count(*) FROM log")
+            .await
+            .unwrap()
+            .query(&[])
+            .await
+            .unwrap();
+
+        assert!(result_set.next().await.unwrap());
+
+        match result_set.get(0).await.unwrap() {
+            SqlValue::Int(count) => count,
+            _ => panic!("expect integer count"),
+        }
+    }
+
+    #[test]
+    fn test_vacuum() {
+        block_on(async {
+            let conn = populated().await;
+
+            conn.vacuum().await.unwrap();
+
+            assert_eq!(count(&conn).await, 2);
+
+            conn.prepare("BEGIN")
+                .await
+                .unwrap()
+                .exec(&[])
+                .await
+                .unwrap();
+
+            let err = conn.vacuum().await.unwrap_err();
+
+            assert!(err
+                .to_string()
+                .contains("cannot VACUUM from within a transaction"));
+        });
+    }
+
+    #[test]
+    fn test_vacuum_into() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+
+        let path = env::temp_dir().join(format!("rinq-vacuum-{}-it's.db", nanos));
+
+        block_on(async {
+            let conn = populated().await;
+
+            conn.vacuum_into(&path).await.unwrap();
+
+            // the target file must not exist.
+            assert!(conn.vacuum_into(&path).await.is_err());
+
+            let copy = rinq_rdbc::open("sqlite", path.to_str().unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(count(&copy).await, 2);
+        });
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! The row change subscriptions of [`DbConn::watch_table`](rinq_rdbc::DbConn::watch_table).
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, c_int, c_void, CStr},
+    io,
+    sync::{Arc, Mutex, MutexGuard, Once, PoisonError, Weak},
+    task::{Context, Poll, Waker},
+};
+
+use rinq_rdbc::{ChangeEvent, ChangeOp};
+use sqlite3_sys as ffi;
+
+use crate::DbConn;
+
+/// The maximum number of unread events of a subscription, newer events are dropped
+/// and counted in [`ChangeEvent::missed`] of the next delivered one.
+const WATCH_CAPACITY: usize = 1024;
+
+/// The subscriptions of a connection, shared by its clones.
+///
+/// The `sqlite3_update_hook` of the connection points to this value, so it is declared
+/// after the raw connection in [`DbConn`] to outlive it.
+pub(crate) struct Watchers {
+    hook: Once,
+    subscribers: Mutex<Vec<Weak<Watch>>>,
+}
+
+impl Default for Watchers {
+    fn default() -> Self {
+        Self {
+            hook: Once::new(),
+            subscribers: Default::default(),
+        }
+    }
+}
+
+impl Drop for Watchers {
+    fn drop(&mut self) {
+        for watch in lock(&self.subscribers).iter().filter_map(Weak::upgrade) {
+            let mut state = lock(&watch.state);
+
+            state.closed = true;
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Watchers {
+    /// Subscribes to the changes of `table`, installing the update hook of `conn` on first use.
+    ///
+    /// `table` is either unqualified, which matches the table in any database of the connection,
+    /// or qualified with a schema like `main.users`. Names are compared case-insensitively.
+    pub(crate) fn subscribe(conn: &DbConn, table: &str) -> Arc<Watch> {
+        let watchers = &conn.watchers;
+
+        // the hook is installed without holding `subscribers`, which the hook locks
+        // while sqlite holds the connection mutex.
+        watchers.hook.call_once(|| unsafe {
+            ffi::sqlite3_update_hook(
+                conn.to_c_handle(),
+                Some(update_hook),
+                Arc::as_ptr(watchers) as *mut c_void,
+            );
+        });
+
+        let (schema, table) = match table.split_once('.') {
+            Some((schema, table)) => (Some(schema.to_owned()), table.to_owned()),
+            None => (None, table.to_owned()),
+        };
+
+        let watch = Arc::new(Watch {
+            schema,
+            table,
+            state: Default::default(),
+        });
+
+        let mut subscribers = lock(&watchers.subscribers);
+
+        subscribers.retain(|watch| watch.strong_count() > 0);
+        subscribers.push(Arc::downgrade(&watch));
+
+        watch
+    }
+
+    fn notify(&self, schema: &str, table: &str, event: ChangeEvent) {
+        let mut subscribers = lock(&self.subscribers);
+
+        subscribers.retain(|watch| watch.strong_count() > 0);
+
+        for watch in subscribers.iter().filter_map(Weak::upgrade) {
+            if watch.matches(schema, table) {
+                watch.push(event);
+            }
+        }
+    }
+}
+
+/// A subscription of [`Watchers::subscribe`], the subscription ends when it is dropped.
+pub(crate) struct Watch {
+    schema: Option<String>,
+    table: String,
+    state: Mutex<WatchState>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    queue: VecDeque<ChangeEvent>,
+    /// The number of events dropped since the last pushed one.
+    missed: u64,
+    waker: Option<Waker>,
+    /// The connection is closed, no more events are pushed.
+    closed: bool,
+}
+
+impl Watch {
+    fn matches(&self, schema: &str, table: &str) -> bool {
+        self.table.eq_ignore_ascii_case(table)
+            && self
+                .schema
+                .as_deref()
+                .map_or(true, |name| name.eq_ignore_ascii_case(schema))
+    }
+
+    fn push(&self, mut event: ChangeEvent) {
+        let mut state = lock(&self.state);
+
+        if state.queue.len() >= WATCH_CAPACITY {
+            state.missed += 1;
+            return;
+        }
+
+        event.missed = std::mem::take(&mut state.missed);
+
+        state.queue.push_back(event);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns the oldest unread event, or error once the connection is closed and all events are read.
+    pub(crate) fn poll_next(&self, cx: &mut Context<'_>) -> Poll<io::Result<ChangeEvent>> {
+        let mut state = lock(&self.state);
+
+        if let Some(event) = state.queue.pop_front() {
+            return Poll::Ready(Ok(event));
+        }
+
+        if state.closed {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "sqlite: the watched connection is closed",
+            )));
+        }
+
+        state.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+/// The hook runs inside `sqlite3_step`, it must not panic or call back into the connection.
+extern "C" fn update_hook(
+    watchers: *mut c_void,
+    op: c_int,
+    schema: *const c_char,
+    table: *const c_char,
+    rowid: ffi::sqlite3_int64,
+) {
+    let op = match op {
+        ffi::SQLITE_INSERT => ChangeOp::Insert,
+        ffi::SQLITE_UPDATE => ChangeOp::Update,
+        ffi::SQLITE_DELETE => ChangeOp::Delete,
+        _ => return,
+    };
+
+    // Safety: the pointer is set by `Watchers::subscribe` and outlives the connection,
+    // the names are nul-terminated strings valid for the duration of the call.
+    let (watchers, schema, table) = unsafe {
+        (
+            &*(watchers as *const Watchers),
+            CStr::from_ptr(schema).to_string_lossy(),
+            CStr::from_ptr(table).to_string_lossy(),
+        )
+    };
+
+    watchers.notify(
+        &schema,
+        &table,
+        ChangeEvent {
+            op,
+            rowid,
+            missed: 0,
+        },
+    );
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, StreamExt};
+
+    use super::*;
+
+    #[test]
+    fn test_watch_table() {
+        crate::tests::register_once();
+
+        block_on(async {
+            let conn = rinq_rdbc::open("sqlite", ":memory:").await.unwrap();
+
+            conn.execute("CREATE TABLE t (name TEXT)", &[])
+                .await
+                .unwrap();
+            conn.execute("CREATE TABLE u (name TEXT)", &[])
+                .await
+                .unwrap();
+
+            let mut changes = conn.watch_table("T").unwrap();
+            let mut qualified = conn.watch_table("main.t").unwrap();
+            let mut other = conn.watch_table("temp.t").unwrap();
+
+            conn.execute("INSERT INTO t VALUES ('a'), ('b')", &[])
+                .await
+                .unwrap();
+            conn.execute("INSERT INTO u VALUES ('c')", &[])
+                .await
+                .unwrap();
+            conn.execute("UPDATE t SET name = 'd' WHERE rowid = 2", &[])
+                .await
+                .unwrap();
+            conn.execute("DELETE FROM t WHERE rowid = 1", &[])
+                .await
+                .unwrap();
+
+            let event = |op, rowid| ChangeEvent {
+                op,
+                rowid,
+                missed: 0,
+            };
+
+            let expected = [
+                event(ChangeOp::Insert, 1),
+                event(ChangeOp::Insert, 2),
+                event(ChangeOp::Update, 2),
+                event(ChangeOp::Delete, 1),
+            ];
+
+            for expected in expected {
+                assert_eq!(changes.next().await.unwrap(), expected);
+                assert_eq!(qualified.next().await.unwrap(), expected);
+            }
+
+            // more events than the capacity of a subscription, the overflow is counted.
+            conn.execute(
+                "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 1030) \
+                 INSERT INTO t SELECT x FROM n",
+                &[],
+            )
+            .await
+            .unwrap();
+
+            for _ in 0..WATCH_CAPACITY {
+                assert_eq!(changes.next().await.unwrap().missed, 0);
+            }
+
+            conn.execute("DELETE FROM t WHERE rowid = 2", &[])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                changes.next().await.unwrap(),
+                ChangeEvent {
+                    op: ChangeOp::Delete,
+                    rowid: 2,
+                    missed: 1030 - WATCH_CAPACITY as u64,
+                }
+            );
+
+            // the stream ends once the connection is closed and all events are read.
+            drop(conn);
+
+            assert_eq!(other.next().await, None);
+            assert_eq!(changes.next().await, None);
+        });
+    }
+}